@@ -1,10 +1,18 @@
+use std::collections::HashSet;
 use std::fs;
 use std::path::Path;
 
 use anyhow::{Context, Result};
 
 use super::schema::AargalConfig;
-use crate::config::schema::BlockAction;
+use crate::config::schema::{
+    BlockAction, IngestSource, ParserConfig, ParserFormat, RuleConfig, TierAction, TierConfig,
+    WatchMode,
+};
+use crate::engine::filter::RequestFilter;
+use crate::engine::rules::window_name;
+use crate::ingest::syslog::Listen;
+use crate::parser::build_parser;
 
 
 pub fn load_config(path: &Path) -> Result<AargalConfig> {
@@ -34,16 +42,331 @@ fn validate(cfg: &AargalConfig) -> anyhow::Result<()> {
         anyhow::bail!("scoring.threshold must be > 0");
     }
 
+    let rates = &cfg.scoring.rates;
+    if rates.windows_seconds.is_empty() || rates.windows_seconds.contains(&0) {
+        anyhow::bail!("scoring.rates.windows_seconds must be a non-empty list of values > 0");
+    }
+    let positive = |limit: f64| limit > 0.0;
+    if !positive(rates.max_requests_per_second) || !positive(rates.max_errors_per_minute) {
+        anyhow::bail!(
+            "scoring.rates.max_requests_per_second and max_errors_per_minute must be > 0"
+        );
+    }
+
+    if cfg.scoring.user_agent.rotation_threshold < 2 {
+        anyhow::bail!("scoring.user_agent.rotation_threshold must be >= 2");
+    }
+
+    let paths = &cfg.scoring.paths;
+    if paths.min_requests == 0 {
+        anyhow::bail!("scoring.paths.min_requests must be > 0");
+    }
+    if !(paths.unique_ratio > 0.0 && paths.unique_ratio <= 1.0) {
+        anyhow::bail!("scoring.paths.unique_ratio must be in (0, 1]");
+    }
+    if paths.enumeration_run < 2 {
+        anyhow::bail!("scoring.paths.enumeration_run must be >= 2");
+    }
+
+    if let Some(path) = cfg.scoring.honeypot.paths.iter().find(|p| !p.starts_with('/')) {
+        anyhow::bail!("scoring.honeypot.paths entries must start with '/' (got {:?})", path);
+    }
+
+    for crawler in &cfg.scoring.crawlers.verify {
+        if crawler.user_agents.iter().all(|ua| ua.trim().is_empty()) {
+            anyhow::bail!(
+                "scoring.crawlers.verify entry {:?} needs at least one user_agents token",
+                crawler.name
+            );
+        }
+    }
+
+    validate_rules(&cfg.rules, &rates.windows_seconds, "rules")?;
+    validate_tiers(&cfg.scoring.tiers, "scoring.tiers")?;
+
+    let mut profiles = HashSet::new();
+    for profile in &cfg.scoring.profiles {
+        if profile.name.trim().is_empty() {
+            anyhow::bail!("scoring.profiles entries need a name");
+        }
+        if !profiles.insert(profile.name.as_str()) {
+            anyhow::bail!("scoring.profiles entry {:?} is defined twice", profile.name);
+        }
+        if profile.hosts.is_empty()
+            && profile.path_prefixes.is_empty()
+            && profile.sources.is_empty()
+        {
+            anyhow::bail!(
+                "scoring.profiles entry {:?} needs hosts, path_prefixes or sources",
+                profile.name
+            );
+        }
+        if let Some(prefix) = profile.path_prefixes.iter().find(|p| !p.starts_with('/')) {
+            anyhow::bail!(
+                "scoring.profiles entry {:?}: path_prefixes must start with '/' (got {:?})",
+                profile.name,
+                prefix
+            );
+        }
+        if profile.threshold == Some(0) {
+            anyhow::bail!("scoring.profiles entry {:?}: threshold must be > 0", profile.name);
+        }
+        if profile.threshold.is_some() && !profile.tiers.is_empty() {
+            anyhow::bail!(
+                "scoring.profiles entry {:?}: its tiers replace threshold; set one or the other",
+                profile.name
+            );
+        }
+        if profile.threshold.is_some() && !cfg.scoring.tiers.is_empty() {
+            anyhow::bail!(
+                "scoring.profiles entry {:?}: scoring.tiers replaces threshold; \
+                 give the profile its own tiers",
+                profile.name
+            );
+        }
+        validate_tiers(&profile.tiers, &format!("scoring.profiles {:?} tiers", profile.name))?;
+        if let Some(w) = &profile.weights {
+            let total = w.rate + w.error + w.user_agent + w.path_entropy;
+            if total != 100 {
+                anyhow::bail!(
+                    "scoring.profiles entry {:?}: weights must sum to 100 (got {})",
+                    profile.name,
+                    total
+                );
+            }
+        }
+        validate_rules(
+            &profile.rules,
+            &rates.windows_seconds,
+            &format!("scoring.profiles {:?} rules", profile.name),
+        )?;
+    }
+
     if cfg.general.state_ttl_seconds < 60 {
         anyhow::bail!("general.state_ttl_seconds must be >= 60");
     }
 
+    if let Some(cp) = &cfg.ingest.checkpoint {
+        if cp.interval_seconds == 0 {
+            anyhow::bail!("ingest.checkpoint.interval_seconds must be > 0");
+        }
+    }
+
     if cfg.fail2ban.enabled && cfg.actions.on_block != BlockAction::Fail2ban {
         anyhow::bail!(
             "fail2ban.enabled=true but actions.on_block != fail2ban"
         );
     }
 
+    let timed_bans = cfg
+        .scoring
+        .tiers
+        .iter()
+        .chain(cfg.scoring.profiles.iter().flat_map(|p| &p.tiers))
+        .any(|tier| !tier.ban_seconds.is_empty());
+    if timed_bans && cfg.actions.on_block == BlockAction::Fail2ban && cfg.fail2ban.bantime.is_none() {
+        anyhow::bail!(
+            "ban_seconds with actions.on_block = \"fail2ban\" needs fail2ban.bantime \
+             (the jail's own bantime, restored after each timed ban)"
+        );
+    }
+    if cfg.fail2ban.bantime == Some(0) {
+        anyhow::bail!("fail2ban.bantime must be > 0");
+    }
+
+    if matches!(cfg.ingest.source, IngestSource::File | IngestSource::Archive)
+        && cfg.ingest.file_sources().is_empty()
+    {
+        anyhow::bail!(
+            "ingest.source = \"file\" / \"archive\" needs ingest.path or [[ingest.sources]]"
+        );
+    }
+
+    if cfg.ingest.source == IngestSource::Syslog {
+        let syslog = cfg
+            .ingest
+            .syslog
+            .as_ref()
+            .context("ingest.source = \"syslog\" needs [ingest.syslog]")?;
+        syslog.listen.parse::<Listen>()?;
+    }
+
+    if cfg.ingest.glob_rescan_seconds == 0 {
+        anyhow::bail!("ingest.glob_rescan_seconds must be > 0");
+    }
+
+    if cfg.ingest.watch == WatchMode::Inotify && !cfg!(target_os = "linux") {
+        anyhow::bail!("ingest.watch = \"inotify\" is only supported on Linux");
+    }
+
+    for source in &cfg.ingest.sources {
+        glob::Pattern::new(&source.path)
+            .with_context(|| format!("Invalid ingest.sources path {:?}", source.path))?;
+        let parser = cfg.parser.for_source(source);
+        validate_client_ip(&parser)
+            .with_context(|| format!("Invalid parser for ingest source {:?}", source.path))?;
+        build_parser(&parser)
+            .with_context(|| format!("Invalid parser for ingest source {:?}", source.path))?;
+    }
+
+    validate_client_ip(&cfg.parser)?;
+    build_parser(&cfg.parser)?;
+    RequestFilter::new(&cfg.parser, &cfg.filter)?;
+
+    Ok(())
+}
+
+/// `nginx_combined` has no forwarding header for `[parser.client_ip]`
+/// to read, so every request would keep the proxy address.
+fn validate_client_ip(parser: &ParserConfig) -> anyhow::Result<()> {
+    if parser.client_ip.is_some() && parser.format == ParserFormat::NginxCombined {
+        anyhow::bail!(
+            "[parser.client_ip] needs parser.format = \"nginx_log_format\" or \"json\" \
+             with the forwarding header in the log"
+        );
+    }
+    Ok(())
+}
+
+/// Tiers are named uniquely, ordered by `enter`, and leave at or below
+/// where they enter.
+fn validate_tiers(tiers: &[TierConfig], what: &str) -> anyhow::Result<()> {
+    let mut names = HashSet::new();
+    let mut previous = 0;
+    for tier in tiers {
+        if tier.name.trim().is_empty() {
+            anyhow::bail!("{} entries need a name", what);
+        }
+        if !names.insert(tier.name.as_str()) {
+            anyhow::bail!("{} entry {:?} is defined twice", what, tier.name);
+        }
+        if tier.enter <= previous {
+            anyhow::bail!(
+                "{} entry {:?}: enter must be > 0 and above the previous tier's",
+                what,
+                tier.name
+            );
+        }
+        previous = tier.enter;
+        if tier.exit() == 0 || tier.exit() > tier.enter {
+            anyhow::bail!("{} entry {:?}: exit must be in 1..=enter", what, tier.name);
+        }
+        if !tier.ban_seconds.is_empty() && tier.action != TierAction::Block {
+            anyhow::bail!("{} entry {:?}: ban_seconds needs action = \"block\"", what, tier.name);
+        }
+        if tier.ban_seconds.contains(&0) {
+            anyhow::bail!("{} entry {:?}: ban_seconds must be > 0", what, tier.name);
+        }
+    }
     Ok(())
 }
 
+/// Names are unique per list (`what`); every rule has an effect and
+/// reads only configured windows.
+fn validate_rules(rules: &[RuleConfig], windows: &[u64], what: &str) -> anyhow::Result<()> {
+    let mut names = HashSet::new();
+    for rule in rules {
+        if rule.name.trim().is_empty() {
+            anyhow::bail!("{} entries need a name (condition {:?})", what, rule.condition.as_str());
+        }
+        if !names.insert(rule.name.as_str()) {
+            anyhow::bail!("{} entry {:?} is defined twice", what, rule.name);
+        }
+        if rule.score == 0 && rule.decision.is_none() {
+            anyhow::bail!("{} entry {:?} needs a score > 0 or a decision", what, rule.name);
+        }
+        if let Some(secs) = rule.condition.windows().into_iter().find(|s| !windows.contains(s)) {
+            anyhow::bail!(
+                "{} entry {:?} reads {} but scoring.rates.windows_seconds has no {}s window",
+                what,
+                rule.name,
+                window_name(secs),
+                secs
+            );
+        }
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+        [general]
+        mode = "detect"
+        state_ttl_seconds = 3600
+
+        [ingest]
+        source = "file"
+        path = "/var/log/nginx/access.log"
+        poll_interval_ms = 100
+
+        [parser]
+        format = "nginx_combined"
+        ignore_status = []
+
+        [scoring]
+        threshold = 60
+
+        [scoring.weights]
+        rate = 40
+        error = 30
+        user_agent = 20
+        path_entropy = 10
+
+        [scoring.rates]
+        windows_seconds = [10]
+        max_requests_per_second = 1.0
+        max_errors_per_minute = 60.0
+
+        [actions]
+        on_block = "log"
+
+        [fail2ban]
+        enabled = false
+        socket = "/run/fail2ban/fail2ban.sock"
+        jail = "aargal"
+
+        [logging]
+        level = "info"
+        json = false
+
+        [[scoring.profiles]]
+        name = "api"
+        path_prefixes = ["/api/"]
+    "#;
+
+    fn check(extra: &str) -> anyhow::Result<()> {
+        let config: AargalConfig = toml::from_str(&format!("{}{}", CONFIG, extra)).unwrap();
+        validate(&config)
+    }
+
+    #[test]
+    fn profile_threshold_conflicts_with_any_tiers() {
+        check("threshold = 80\n").unwrap();
+
+        let own_tiers = r#"
+            threshold = 80
+
+            [[scoring.profiles.tiers]]
+            name = "watch"
+            enter = 40
+            action = "detect"
+        "#;
+        let err = check(own_tiers).unwrap_err().to_string();
+        assert!(err.contains("its tiers replace threshold"), "{}", err);
+
+        let global_tiers = r#"
+            threshold = 80
+
+            [[scoring.tiers]]
+            name = "watch"
+            enter = 40
+            action = "detect"
+        "#;
+        let err = check(global_tiers).unwrap_err().to_string();
+        assert!(err.contains("scoring.tiers replaces threshold"), "{}", err);
+    }
+}
@@ -1,12 +1,19 @@
 use serde::Deserialize;
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
+use crate::engine::rules::Condition;
+
 #[derive(Debug, Deserialize)]
 pub struct AargalConfig {
     pub general: GeneralConfig,
     pub ingest: IngestConfig,
     pub parser: ParserConfig,
+    #[serde(default)]
+    pub filter: FilterConfig,
     pub scoring: ScoringConfig,
+    #[serde(default)]
+    pub rules: Vec<RuleConfig>,
     pub actions: ActionsConfig,
     pub fail2ban: Fail2BanConfig,
     pub logging: LoggingConfig,
@@ -29,33 +36,254 @@ pub struct GeneralConfig {
 
 /* ---------------- Ingest ---------------- */
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum IngestSource {
     File,
     Stdin,
+    /// Receive lines from nginx `access_log syslog:server=...`.
+    Syslog,
+    /// Read `path` / `sources` once (plain, .gz or .zst), oldest event
+    /// first, then exit.
+    Archive,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct IngestConfig {
     pub source: IngestSource,
-    pub path: PathBuf,
+    /// Single log file (or glob for `source = "archive"`). Ignored when
+    /// `sources` is non-empty.
+    #[serde(default)]
+    pub path: Option<PathBuf>,
     pub poll_interval_ms: u64,
+    /// Several log files, each with its own format and label.
+    #[serde(default)]
+    pub sources: Vec<SourceConfig>,
+    /// How often glob patterns are re-expanded to pick up new files.
+    #[serde(default = "default_glob_rescan_seconds")]
+    pub glob_rescan_seconds: u64,
+    /// Persist read offsets so restarts resume instead of skipping ahead.
+    #[serde(default)]
+    pub checkpoint: Option<CheckpointConfig>,
+    /// How idle file sources wait for new data.
+    #[serde(default)]
+    pub watch: WatchMode,
+    /// Required when `source = "syslog"`.
+    #[serde(default)]
+    pub syslog: Option<SyslogConfig>,
 }
 
-/* ---------------- Parser ---------------- */
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum WatchMode {
+    /// inotify where available, otherwise polling.
+    #[default]
+    Auto,
+    /// inotify only; fail at startup when it is unavailable.
+    Inotify,
+    /// Sleep `poll_interval_ms` between reads (NFS and other network
+    /// filesystems, where inotify misses remote writes).
+    Poll,
+}
+
+/// One `[[ingest.sources]]` entry.
+#[derive(Debug, Deserialize, Clone)]
+pub struct SourceConfig {
+    /// File path or glob pattern (`/var/log/nginx/*.access.log`).
+    pub path: String,
+    /// Overrides `parser.format` for this source.
+    #[serde(default)]
+    pub format: Option<ParserFormat>,
+    /// Overrides `parser.log_format` for this source.
+    #[serde(default)]
+    pub log_format: Option<String>,
+    /// Carried on every event from this source (e.g. the vhost name).
+    #[serde(default)]
+    pub label: Option<String>,
+}
+
+impl IngestConfig {
+    /// Configured sources; a bare `path` is a single unlabelled source,
+    /// taken literally except in archive mode, where it may be a glob.
+    pub fn file_sources(&self) -> Vec<SourceConfig> {
+        if !self.sources.is_empty() {
+            return self.sources.clone();
+        }
+
+        self.path
+            .iter()
+            .map(|path| SourceConfig {
+                path: match self.source {
+                    IngestSource::Archive => path.to_string_lossy().into_owned(),
+                    _ => glob::Pattern::escape(&path.to_string_lossy()),
+                },
+                format: None,
+                log_format: None,
+                label: None,
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct SyslogConfig {
+    /// `udp://127.0.0.1:5514`, `tcp://127.0.0.1:5514` or
+    /// `unix:/run/aargal/syslog.sock` (datagram socket).
+    pub listen: String,
+    /// Only accept messages with this tag/APP-NAME (nginx sends `nginx`).
+    #[serde(default)]
+    pub tag: Option<String>,
+}
+
+fn default_glob_rescan_seconds() -> u64 {
+    10
+}
 
 #[derive(Debug, Deserialize)]
+pub struct CheckpointConfig {
+    /// State file holding the offsets (JSON).
+    pub path: PathBuf,
+    #[serde(default = "default_checkpoint_interval")]
+    pub interval_seconds: u64,
+    /// Upper bound on backlog read at startup; older data is skipped.
+    #[serde(default = "default_max_backlog_bytes")]
+    pub max_backlog_bytes: u64,
+}
+
+fn default_checkpoint_interval() -> u64 {
+    10
+}
+
+fn default_max_backlog_bytes() -> u64 {
+    64 * 1024 * 1024
+}
+
+/* ---------------- Parser ---------------- */
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum ParserFormat {
     NginxCombined,
+    /// Custom nginx `log_format`, given verbatim in `parser.log_format`.
+    NginxLogFormat,
     Json,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone)]
 pub struct ParserConfig {
     pub format: ParserFormat,
     pub ignore_status: Vec<u16>,
+    /// Required when `format = "nginx_log_format"`.
+    #[serde(default)]
+    pub log_format: Option<String>,
+    #[serde(default)]
+    pub json: JsonFieldMap,
+    /// Resolve the real client behind trusted reverse proxies.
+    #[serde(default)]
+    pub client_ip: Option<ClientIpConfig>,
+}
+
+/// Trusted-proxy aware client address resolution, modelled on nginx
+/// `set_real_ip_from` / `real_ip_header` / `real_ip_recursive`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ClientIpConfig {
+    /// Proxy networks (CIDR or single addresses) whose forwarding header
+    /// is believed.
+    pub trusted_proxies: Vec<String>,
+    /// Parsed field holding the forwarding chain, e.g. the
+    /// `http_x_forwarded_for` variable of a custom `log_format`.
+    #[serde(default = "default_client_ip_header")]
+    pub header: String,
+    /// Skip trusted hops when walking the header right-to-left.
+    #[serde(default = "default_true")]
+    pub recursive: bool,
+}
+
+fn default_client_ip_header() -> String {
+    "http_x_forwarded_for".into()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Field names used by the `json` parser.
+///
+/// Defaults match an nginx `log_format ... escape=json` that uses the
+/// variable names as keys. Dotted keys address nested objects.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct JsonFieldMap {
+    pub ip: String,
+    pub status: String,
+    pub path: String,
+    pub method: String,
+    pub protocol: String,
+    /// Full request line (`GET /x HTTP/1.1`), used when `method` /
+    /// `path` / `protocol` are not logged separately.
+    pub request: String,
+    pub user_agent: String,
+    pub referer: String,
+    pub bytes_sent: String,
+    pub timestamp: String,
+    pub host: String,
+    /// Additional keys copied verbatim into the event's extensions,
+    /// e.g. the forwarding header used by `[parser.client_ip]`.
+    pub extensions: Vec<String>,
+}
+
+impl ParserConfig {
+    /// This parser config with a source's format overrides applied.
+    pub fn for_source(&self, source: &SourceConfig) -> ParserConfig {
+        let mut cfg = self.clone();
+        if let Some(format) = source.format {
+            cfg.format = format;
+        }
+        if source.log_format.is_some() {
+            cfg.log_format = source.log_format.clone();
+        }
+        cfg
+    }
+}
+
+impl Default for JsonFieldMap {
+    fn default() -> Self {
+        Self {
+            ip: "remote_addr".into(),
+            status: "status".into(),
+            path: "request_uri".into(),
+            method: "request_method".into(),
+            protocol: "server_protocol".into(),
+            request: "request".into(),
+            user_agent: "http_user_agent".into(),
+            referer: "http_referer".into(),
+            bytes_sent: "body_bytes_sent".into(),
+            timestamp: "time_iso8601".into(),
+            host: "host".into(),
+            extensions: vec!["http_x_forwarded_for".into()],
+        }
+    }
+}
+
+/* ---------------- Filter ---------------- */
+
+/// Request-level filters applied after parsing, before state is updated.
+///
+/// `include_*` lists are allow-lists: when non-empty, an event must match
+/// one entry. `exclude_*` lists drop anything that matches.
+#[derive(Debug, Deserialize, Default)]
+#[serde(default)]
+pub struct FilterConfig {
+    pub include_path_prefixes: Vec<String>,
+    pub exclude_path_prefixes: Vec<String>,
+    /// Regular expressions matched against the full path (including query).
+    pub exclude_path_patterns: Vec<String>,
+    pub include_methods: Vec<String>,
+    pub exclude_methods: Vec<String>,
+    pub include_hosts: Vec<String>,
+    pub exclude_hosts: Vec<String>,
+    /// Regular expressions matched against the user agent.
+    pub exclude_user_agents: Vec<String>,
 }
 
 /* ---------------- Scoring ---------------- */
@@ -64,9 +292,109 @@ pub struct ParserConfig {
 pub struct ScoringConfig {
     pub threshold: u32,
     pub weights: ScoringWeights,
+    #[serde(default)]
+    pub rates: RateLimits,
+    #[serde(default)]
+    pub user_agent: UserAgentConfig,
+    #[serde(default)]
+    pub paths: PathConfig,
+    #[serde(default)]
+    pub honeypot: HoneypotConfig,
+    #[serde(default)]
+    pub robots: RobotsConfig,
+    #[serde(default)]
+    pub crawlers: CrawlerConfig,
+    /// Tried in order; requests matching none are scored with the
+    /// settings above.
+    #[serde(default)]
+    pub profiles: Vec<ProfileConfig>,
+    /// Graded responses, lowest `enter` first; replaces `threshold` when
+    /// set.
+    #[serde(default)]
+    pub tiers: Vec<TierConfig>,
+    /// How long a client that was banned is remembered (and its next ban
+    /// escalated), however long ago its state would otherwise expire.
+    #[serde(default = "default_offence_memory_seconds")]
+    pub offence_memory_seconds: u64,
 }
 
-#[derive(Debug, Deserialize)]
+fn default_offence_memory_seconds() -> u64 {
+    7 * 24 * 3600
+}
+
+/// One `[[scoring.tiers]]` entry.
+///
+/// A client enters a tier when its score reaches `enter` and stays in it
+/// until the score falls below `exit`, so a score hovering around one
+/// boundary does not flap.
+#[derive(Debug, Deserialize, Clone)]
+pub struct TierConfig {
+    pub name: String,
+    pub enter: u32,
+    /// Defaults to `enter` (no hysteresis).
+    #[serde(default)]
+    pub exit: Option<u32>,
+    pub action: TierAction,
+    /// Ban length for a client's 1st, 2nd, ... ban; the last entry
+    /// repeats. Empty leaves it to the blocking backend (the fail2ban
+    /// jail's `bantime`). Block tiers only.
+    #[serde(default)]
+    pub ban_seconds: Vec<u64>,
+}
+
+impl TierConfig {
+    /// The single tier a plain `threshold` stands for.
+    pub fn threshold(threshold: u32) -> Self {
+        Self {
+            name: "threshold".into(),
+            enter: threshold,
+            exit: None,
+            action: TierAction::Block,
+            ban_seconds: Vec::new(),
+        }
+    }
+
+    pub fn exit(&self) -> u32 {
+        self.exit.unwrap_or(self.enter)
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TierAction {
+    /// Log only.
+    Detect,
+    /// Log for a rate limiter to pick up; nothing is banned.
+    Throttle,
+    /// `actions.on_block` (logged only in detect mode).
+    Block,
+}
+
+/// Rates at which the rate and error signals reach their full weight.
+///
+/// Each client is counted over every window; the busiest one (relative
+/// to the limit) is scored, so short bursts and slow crawls both show.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct RateLimits {
+    pub windows_seconds: Vec<u64>,
+    /// Requests per second earning the full `weights.rate`.
+    pub max_requests_per_second: f64,
+    /// Error responses per minute earning the full `weights.error`.
+    pub max_errors_per_minute: f64,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self {
+            windows_seconds: crate::model::ip_state::DEFAULT_WINDOWS.to_vec(),
+            max_requests_per_second: 5.0,
+            max_errors_per_minute: 30.0,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
 pub struct ScoringWeights {
     pub rate: u32,
     pub error: u32,
@@ -74,6 +402,192 @@ pub struct ScoringWeights {
     pub path_entropy: u32,
 }
 
+/// User-agent signal: empty agents, scraping libraries, AI crawlers and
+/// agent rotation each earn the full `weights.user_agent`.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct UserAgentConfig {
+    /// TOML file with additional signatures (`libraries`, `ai_crawlers`);
+    /// `builtin = false` in the file replaces the built-in lists.
+    pub signatures_file: Option<PathBuf>,
+    /// Distinct user agents from one IP that count as rotation.
+    pub rotation_threshold: usize,
+}
+
+impl Default for UserAgentConfig {
+    fn default() -> Self {
+        Self {
+            signatures_file: None,
+            rotation_threshold: 5,
+        }
+    }
+}
+
+/// Crawl-pattern signal: breadth-first crawling, sitemap walking and ID
+/// enumeration each earn the full `weights.path_entropy`.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct PathConfig {
+    /// Requests before breadth and sitemap walking are judged.
+    pub min_requests: u64,
+    /// Share of requests to never-before-seen paths that counts as
+    /// crawling (humans revisit pages; crawlers rarely do).
+    pub unique_ratio: f64,
+    /// Consecutive IDs under one path template (`/item/1`, `/item/2`,
+    /// ...) that count as enumeration.
+    pub enumeration_run: u32,
+}
+
+impl Default for PathConfig {
+    fn default() -> Self {
+        Self {
+            min_requests: 20,
+            unique_ratio: 0.9,
+            enumeration_run: 10,
+        }
+    }
+}
+
+/// Trap paths: any client requesting one is scored (and optionally acted
+/// on) at once, and stays trapped until its state expires.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct HoneypotConfig {
+    /// Paths without query string; a trailing `*` matches any path with
+    /// that prefix.
+    pub paths: Vec<String>,
+    /// Added to the score of a trapped client, on top of the weights.
+    pub score: u32,
+    /// Act on trapped clients whatever `threshold` says.
+    pub block: bool,
+}
+
+impl Default for HoneypotConfig {
+    fn default() -> Self {
+        Self {
+            paths: Vec::new(),
+            score: 100,
+            block: false,
+        }
+    }
+}
+
+/// Robots.txt compliance: clients that read robots.txt (or are named in
+/// it) and then fetch disallowed paths or ignore `Crawl-delay`.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct RobotsConfig {
+    /// robots.txt for sites without an entry in `vhosts`.
+    pub file: Option<PathBuf>,
+    /// Per-site robots.txt, keyed by `$host` or source label.
+    pub vhosts: BTreeMap<String, PathBuf>,
+    /// Added to the score of a non-compliant client, on top of the
+    /// weights.
+    pub score: u32,
+}
+
+impl Default for RobotsConfig {
+    fn default() -> Self {
+        Self {
+            file: None,
+            vhosts: BTreeMap::new(),
+            score: 30,
+        }
+    }
+}
+
+/// Offline verification of clients claiming to be search-engine
+/// crawlers, against published IP range files kept on disk.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct CrawlerConfig {
+    /// Added to the score of a client spoofing a crawler, on top of the
+    /// weights.
+    pub score: u32,
+    /// How often range files are checked for changes.
+    pub reload_check_seconds: u64,
+    pub verify: Vec<CrawlerSource>,
+}
+
+impl Default for CrawlerConfig {
+    fn default() -> Self {
+        Self {
+            score: 50,
+            reload_check_seconds: 30,
+            verify: Vec::new(),
+        }
+    }
+}
+
+/// One `[[scoring.crawlers.verify]]` entry.
+#[derive(Debug, Deserialize, Clone)]
+pub struct CrawlerSource {
+    pub name: String,
+    /// User-agent tokens (case-insensitive) that claim this crawler.
+    pub user_agents: Vec<String>,
+    /// Published ranges: Google/Bing/Apple JSON, or one CIDR per line.
+    pub ranges: PathBuf,
+}
+
+/// One `[[scoring.profiles]]` entry: its own threshold, weights, rules
+/// and rate counters for part of the traffic, e.g. a login endpoint or
+/// an API host.
+///
+/// A request belongs to the first profile whose every non-empty
+/// criterion matches.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ProfileConfig {
+    pub name: String,
+    /// `$host` values (case-insensitive, port ignored).
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    #[serde(default)]
+    pub path_prefixes: Vec<String>,
+    /// Ingest source labels.
+    #[serde(default)]
+    pub sources: Vec<String>,
+    /// Defaults to `scoring.threshold`; not allowed with `scoring.tiers`
+    /// (give the profile its own `tiers` instead).
+    #[serde(default)]
+    pub threshold: Option<u32>,
+    /// Defaults to `scoring.weights`.
+    #[serde(default)]
+    pub weights: Option<ScoringWeights>,
+    /// Scored after the top-level `[[rules]]`, for this profile only.
+    #[serde(default)]
+    pub rules: Vec<RuleConfig>,
+    /// Defaults to `scoring.tiers`.
+    #[serde(default)]
+    pub tiers: Vec<TierConfig>,
+}
+
+/* ---------------- Rules ---------------- */
+
+/// One `[[rules]]` entry: a site-specific condition scored on top of the
+/// built-in signals.
+#[derive(Debug, Deserialize, Clone)]
+pub struct RuleConfig {
+    /// Reported as the score reason.
+    pub name: String,
+    /// e.g. `path ~ "^/api/" && window_1m.requests > 120 && ua.is_empty`
+    pub condition: Condition,
+    /// Added to the score when the condition holds.
+    #[serde(default)]
+    pub score: u32,
+    /// Decide directly when the condition holds, whatever the score.
+    #[serde(default)]
+    pub decision: Option<RuleDecision>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleDecision {
+    /// Act as if over the threshold (a detection in detect mode).
+    Block,
+    /// Never act; wins over every other signal.
+    Allow,
+}
+
 /* ---------------- Actions ---------------- */
 
 #[derive(Debug, Deserialize,Clone, PartialEq, Eq)]
@@ -96,6 +610,10 @@ pub struct Fail2BanConfig {
     pub enabled: bool,
     pub socket: PathBuf,
     pub jail: String,
+    /// The jail's own `bantime` in seconds. Restored after every ban
+    /// with a `ban_seconds` length, and the length of the others.
+    #[serde(default)]
+    pub bantime: Option<u64>,
 }
 
 /* ---------------- Logging ---------------- */
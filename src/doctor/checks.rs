@@ -1,22 +1,86 @@
 use std::path::Path;
 
-use crate::config::schema::{AargalConfig, IngestSource};
+use crate::config::schema::{AargalConfig, IngestSource, WatchMode};
 use crate::doctor::report::DoctorReport;
+use crate::engine::signals::crawler::CrawlerVerifier;
+use crate::engine::signals::robots::RobotsSignal;
+use crate::engine::signals::user_agent::UserAgentSignal;
+use crate::ingest::syslog::Listen;
 
 pub fn check_ingest(
     config: &AargalConfig,
     report: &mut DoctorReport,
 ) -> anyhow::Result<()> {
     match config.ingest.source {
-        IngestSource::File => {
-            let path = &config.ingest.path;
-            if path.exists() {
-                report.ok(format!("Ingest file exists: {}", path.display()));
-            } else {
-                report.error(format!(
-                    "Ingest file does not exist: {}",
-                    path.display()
+        IngestSource::File | IngestSource::Archive => {
+            for source in config.ingest.file_sources() {
+                let matches = glob::glob(&source.path)
+                    .map(|paths| paths.flatten().filter(|p| p.is_file()).count())
+                    .unwrap_or(0);
+                let label = source.label.as_deref().unwrap_or("-");
+
+                if matches > 0 {
+                    report.ok(format!(
+                        "Ingest source {} (label {}) matches {} file(s)",
+                        source.path, label, matches
+                    ));
+                } else {
+                    report.error(format!(
+                        "Ingest source {} (label {}) matches no files",
+                        source.path, label
+                    ));
+                }
+            }
+
+            if config.ingest.source == IngestSource::Archive {
+                report.ok("Archive mode: sources are read once, oldest event first");
+                return Ok(());
+            }
+
+            if config.ingest.watch == WatchMode::Poll || !cfg!(target_os = "linux") {
+                report.ok(format!(
+                    "Idle sources are polled every {} ms",
+                    config.ingest.poll_interval_ms
                 ));
+            } else {
+                report.ok("Idle sources wake on inotify events");
+            }
+
+            if let Some(cp) = &config.ingest.checkpoint {
+                let dir = cp.path.parent().filter(|d| !d.as_os_str().is_empty());
+                match dir {
+                    Some(dir) if !dir.is_dir() => report.error(format!(
+                        "Checkpoint directory does not exist: {}",
+                        dir.display()
+                    )),
+                    _ => report.ok(format!(
+                        "Checkpoints stored in {}",
+                        cp.path.display()
+                    )),
+                }
+            } else {
+                report.warn("No ingest.checkpoint: restarts skip lines logged while down");
+            }
+        }
+        IngestSource::Syslog => {
+            let Some(syslog) = &config.ingest.syslog else {
+                report.error("ingest.source = \"syslog\" needs [ingest.syslog]");
+                return Ok(());
+            };
+
+            match syslog.listen.parse::<Listen>() {
+                Ok(Listen::Unix(path)) => {
+                    let dir = path.parent().filter(|d| !d.as_os_str().is_empty());
+                    match dir {
+                        Some(dir) if !dir.is_dir() => report.error(format!(
+                            "Syslog socket directory does not exist: {}",
+                            dir.display()
+                        )),
+                        _ => report.ok(format!("Receiving syslog on {}", syslog.listen)),
+                    }
+                }
+                Ok(_) => report.ok(format!("Receiving syslog on {}", syslog.listen)),
+                Err(e) => report.error(e.to_string()),
             }
         }
         IngestSource::Stdin => {
@@ -26,6 +90,72 @@ pub fn check_ingest(
     Ok(())
 }
 
+pub fn check_signals(
+    config: &AargalConfig,
+    report: &mut DoctorReport,
+) -> anyhow::Result<()> {
+    match UserAgentSignal::new(&config.scoring.user_agent) {
+        Ok(signal) => report.ok(format!(
+            "User-agent signal: {} signatures, rotation at {} distinct agents",
+            signal.signature_count(),
+            config.scoring.user_agent.rotation_threshold
+        )),
+        Err(e) => report.error(format!("{:#}", e)),
+    }
+
+    let robots = &config.scoring.robots;
+    match RobotsSignal::new(robots) {
+        Ok(signal) if signal.is_empty() => {
+            report.warn("No scoring.robots files: robots.txt compliance is not checked")
+        }
+        Ok(signal) => report.ok(format!(
+            "Robots.txt compliance checked against {} file(s)",
+            signal.len()
+        )),
+        Err(e) => report.error(format!("{:#}", e)),
+    }
+
+    match CrawlerVerifier::new(&config.scoring.crawlers) {
+        Ok(verifier) => match verifier.counts() {
+            (0, _) => report.warn("No scoring.crawlers.verify: claimed crawlers are not verified"),
+            (crawlers, ranges) => report.ok(format!(
+                "Crawler verification: {} crawler(s), {} range(s)",
+                crawlers, ranges
+            )),
+        },
+        Err(e) => report.error(format!("{:#}", e)),
+    }
+
+    let honeypot = &config.scoring.honeypot;
+    if honeypot.paths.is_empty() {
+        report.warn("No scoring.honeypot.paths: trap paths are a cheap, precise signal");
+    } else {
+        report.ok(format!(
+            "{} honeypot path(s), {}",
+            honeypot.paths.len(),
+            if honeypot.block {
+                "acted on immediately".to_string()
+            } else {
+                format!("+{} score", honeypot.score)
+            }
+        ));
+    }
+
+    let profiles = &config.scoring.profiles;
+    if !profiles.is_empty() {
+        let names: Vec<&str> = profiles.iter().map(|p| p.name.as_str()).collect();
+        report.ok(format!("{} scoring profile(s): {}", names.len(), names.join(", ")));
+    }
+
+    let tiers = &config.scoring.tiers;
+    if !tiers.is_empty() {
+        let names: Vec<String> = tiers.iter().map(|t| format!("{}@{}", t.name, t.enter)).collect();
+        report.ok(format!("{} decision tier(s): {}", names.len(), names.join(", ")));
+    }
+
+    Ok(())
+}
+
 pub fn check_fail2ban(
     config: &AargalConfig,
     report: &mut DoctorReport,
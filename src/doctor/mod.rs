@@ -16,6 +16,7 @@ pub fn run_doctor(config_path: &Path) -> anyhow::Result<()> {
     report.ok("Config file loaded successfully");
 
     check_ingest(&config, &mut report)?;
+    check_signals(&config, &mut report)?;
     check_fail2ban(&config, &mut report)?;
     check_logging(&config, &mut report)?;
 
@@ -11,6 +11,7 @@ pub struct DoctorEntry {
     pub message: String,
 }
 
+#[derive(Default)]
 pub struct DoctorReport {
     entries: Vec<DoctorEntry>,
 }
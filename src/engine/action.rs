@@ -1,11 +1,12 @@
 use crate::config::schema::{ActionsConfig, BlockAction, GeneralConfig, RunMode};
-use crate::engine::decision::Decision;
+use crate::engine::decision::{Decision, Tier};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ActionResult {
     None,
-    DetectOnly,
-    Block(BlockAction),
+    DetectOnly(Tier),
+    Throttle(Tier),
+    Block(BlockAction, Tier),
 }
 
 pub fn map_decision_to_action(
@@ -16,11 +17,16 @@ pub fn map_decision_to_action(
     match decision {
         Decision::Allow => ActionResult::None,
 
-        Decision::Detect => ActionResult::DetectOnly,
+        Decision::Detect(tier) => ActionResult::DetectOnly(tier),
 
-        Decision::Block => match general.mode {
-            RunMode::Detect => ActionResult::DetectOnly,
-            RunMode::Enforce => ActionResult::Block(actions.on_block.clone()),
+        Decision::Throttle(tier) => match general.mode {
+            RunMode::Detect => ActionResult::DetectOnly(tier),
+            RunMode::Enforce => ActionResult::Throttle(tier),
+        },
+
+        Decision::Block(tier) => match general.mode {
+            RunMode::Detect => ActionResult::DetectOnly(tier),
+            RunMode::Enforce => ActionResult::Block(actions.on_block.clone(), tier),
         },
     }
 }
@@ -31,6 +37,14 @@ mod tests {
     use super::*;
     use crate::engine::decision::Decision;
     use crate::config::schema::{ActionsConfig, BlockAction, GeneralConfig, RunMode};
+    use std::time::Duration;
+
+    fn tier() -> Tier {
+        Tier {
+            name: "ban".into(),
+            duration: Some(Duration::from_secs(600)),
+        }
+    }
 
     fn enforce_cfg() -> (GeneralConfig, ActionsConfig) {
         (
@@ -45,15 +59,25 @@ mod tests {
     #[test]
     fn block_in_enforce_triggers_action() {
         let (g, a) = enforce_cfg();
-        let result = map_decision_to_action(Decision::Block, &g, &a);
-        assert_eq!(result, ActionResult::Block(BlockAction::Log));
+        let result = map_decision_to_action(Decision::Block(tier()), &g, &a);
+        assert_eq!(result, ActionResult::Block(BlockAction::Log, tier()));
     }
 
     #[test]
     fn block_in_detect_does_not_trigger() {
         let g = GeneralConfig { mode: RunMode::Detect,state_ttl_seconds: 3600 };
         let a = ActionsConfig { on_block: BlockAction::Fail2ban };
-        let result = map_decision_to_action(Decision::Block, &g, &a);
-        assert_eq!(result, ActionResult::DetectOnly);
+        let result = map_decision_to_action(Decision::Block(tier()), &g, &a);
+        assert_eq!(result, ActionResult::DetectOnly(tier()));
+
+        let result = map_decision_to_action(Decision::Throttle(tier()), &g, &a);
+        assert_eq!(result, ActionResult::DetectOnly(tier()));
+    }
+
+    #[test]
+    fn throttle_in_enforce_is_not_a_block() {
+        let (g, a) = enforce_cfg();
+        let result = map_decision_to_action(Decision::Throttle(tier()), &g, &a);
+        assert_eq!(result, ActionResult::Throttle(tier()));
     }
 }
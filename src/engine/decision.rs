@@ -1,37 +1,176 @@
-use crate::config::schema::{GeneralConfig, RunMode,ScoringConfig};
-use crate::engine::scoring::ScoreResult;
+use std::fmt;
+use std::time::Duration;
 
+use crate::config::schema::{GeneralConfig, RunMode, TierAction, TierConfig};
+use crate::engine::profile::Profile;
+use crate::engine::scoring::{Forced, ScoreResult};
+use crate::model::ip_state::IpState;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+
+/// The tier a decision was reached in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tier {
+    pub name: String,
+    /// Ban length, for block tiers with `ban_seconds`.
+    pub duration: Option<Duration>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Decision {
     Allow,
-    Detect,
-    Block,
+    /// Log only: a `detect` tier, or any tier in detect mode.
+    Detect(Tier),
+    Throttle(Tier),
+    Block(Tier),
+}
+
+impl Decision {
+    pub fn tier(&self) -> Option<&Tier> {
+        match self {
+            Decision::Allow => None,
+            Decision::Detect(tier) | Decision::Throttle(tier) | Decision::Block(tier) => Some(tier),
+        }
+    }
+}
+
+impl fmt::Display for Decision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Decision::Allow => "Allow",
+            Decision::Detect(_) => "Detect",
+            Decision::Throttle(_) => "Throttle",
+            Decision::Block(_) => "Block",
+        })
+    }
 }
 
+impl fmt::Display for Tier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self.duration {
+            Some(duration) => format!("{}/{}", self.name, format_duration(duration)),
+            None => self.name.clone(),
+        };
+        f.pad(&text)
+    }
+}
+
+/// `10m`, `1h`, `1d`: the largest unit that divides evenly.
+pub fn format_duration(duration: Duration) -> String {
+    match duration.as_secs() {
+        s if s > 0 && s % 86400 == 0 => format!("{}d", s / 86400),
+        s if s > 0 && s % 3600 == 0 => format!("{}h", s / 3600),
+        s if s > 0 && s % 60 == 0 => format!("{}m", s / 60),
+        s => format!("{}s", s),
+    }
+}
+
+/// Move the client between `profile`'s tiers and decide.
+///
+/// A client enters the highest tier whose `enter` its score reaches, and
+/// stays in its current tier (or falls back to a lower one) while the
+/// score is at least that tier's `exit`. Entering a block tier from
+/// outside one counts as a new ban, and later bans use later
+/// `ban_seconds` entries. Only enforced bans count: detect mode reports
+/// the ban the client would get next, and leaves the count alone.
+///
+/// A ban ends after its `ban_seconds`, or `backend_ban` (the blocking
+/// backend's own ban length) when the tier sets none; by log time, so
+/// replays agree. A client still blocked by an ended ban is placed
+/// afresh, so coming back over `enter` is a new, longer ban.
 pub fn decide(
     score: &ScoreResult,
     general: &GeneralConfig,
-    scoring: &ScoringConfig,
+    profile: &Profile,
+    state: &mut IpState,
+    backend_ban: Option<Duration>,
 ) -> Decision {
-    if score.score >= scoring.threshold {
-        match general.mode {
-            RunMode::Detect => Decision::Detect,
-            RunMode::Enforce => Decision::Block,
-        }
+    let implicit;
+    let tiers = if profile.tiers.is_empty() {
+        implicit = [TierConfig::threshold(profile.threshold)];
+        &implicit[..]
     } else {
-        Decision::Allow
+        profile.tiers
+    };
+
+    let ban_over = state.banned_until.is_some_and(|until| state.last_event >= until);
+    let current = state
+        .tier(profile.name)
+        .filter(|i| *i < tiers.len())
+        .filter(|i| !(ban_over && tiers[*i].action == TierAction::Block));
+    let reached = match score.forced {
+        Some(Forced::Allow) => None,
+        forced => {
+            let by_score = (0..tiers.len()).rev().find(|&i| {
+                score.score >= tiers[i].enter
+                    || (current.is_some_and(|c| i <= c) && score.score >= tiers[i].exit())
+            });
+            // Forced: at least the first blocking tier.
+            let by_force = (forced == Some(Forced::Act)).then(|| {
+                tiers
+                    .iter()
+                    .position(|t| t.action == TierAction::Block)
+                    .unwrap_or(tiers.len() - 1)
+            });
+            by_score.max(by_force)
+        }
+    };
+
+    let enforcing = matches!(general.mode, RunMode::Enforce);
+    // Bans are only counted when enforced, so a block tier reached in
+    // detect mode has not been carried out yet.
+    let was_blocking =
+        state.bans > 0 && current.is_some_and(|i| tiers[i].action == TierAction::Block);
+    state.set_tier(profile.name, reached);
+    let Some(config) = reached.map(|i| &tiers[i]) else {
+        return Decision::Allow;
+    };
+
+    let mut tier = Tier {
+        name: config.name.clone(),
+        duration: None,
+    };
+    if config.action == TierAction::Block {
+        if enforcing && !was_blocking {
+            state.bans += 1;
+        }
+        let nth = if enforcing {
+            (state.bans as usize).saturating_sub(1)
+        } else {
+            state.bans as usize
+        };
+        tier.duration = config
+            .ban_seconds
+            .get(nth)
+            .or(config.ban_seconds.last())
+            .map(|secs| Duration::from_secs(*secs));
+        // A new ban, or a longer one from a higher block tier.
+        if enforcing && (!was_blocking || reached > current) {
+            state.banned_until = tier
+                .duration
+                .or(backend_ban)
+                .map(|length| state.last_event + length);
+        }
+    }
+
+    match (&general.mode, config.action) {
+        (RunMode::Detect, _) | (_, TierAction::Detect) => Decision::Detect(tier),
+        (RunMode::Enforce, TierAction::Throttle) => Decision::Throttle(tier),
+        (RunMode::Enforce, TierAction::Block) => Decision::Block(tier),
     }
 }
 
 
 #[cfg(test)]
-
 mod tests {
     use super::*;
     use crate::config::schema::{GeneralConfig, RunMode};
     use crate::engine::scoring::ScoreReason;
-    use crate::config::schema::ScoringWeights;
+    use crate::model::clock::at;
+    use std::time::SystemTime;
+    use crate::config::schema::{
+        CrawlerConfig, HoneypotConfig, PathConfig, RateLimits, RobotsConfig, ScoringConfig,
+        ScoringWeights, UserAgentConfig,
+    };
 
 
     fn general_detect() -> GeneralConfig {
@@ -51,7 +190,8 @@ mod tests {
     fn score(value: u32) -> ScoreResult {
         ScoreResult {
             score: value,
-            reasons: vec![ScoreReason::HighRate { count: 100 }],
+            reasons: vec![ScoreReason::HighRate { requests: 100, window_secs: 10 }],
+            forced: None,
         }
     }
 
@@ -69,25 +209,227 @@ mod tests {
     ScoringConfig {
         threshold: 100,
         weights: dummy_weights(),
+        rates: RateLimits::default(),
+        user_agent: UserAgentConfig::default(),
+        paths: PathConfig::default(),
+        honeypot: HoneypotConfig::default(),
+        robots: RobotsConfig::default(),
+        crawlers: CrawlerConfig::default(),
+        profiles: Vec::new(),
+        tiers: Vec::new(),
+        offence_memory_seconds: 86400,
     }
 }
 
 
+    fn decide_once(score: &ScoreResult, general: &GeneralConfig) -> Decision {
+        let scoring = scoring();
+        let mut state = IpState::new("192.0.2.1".parse().unwrap(), SystemTime::UNIX_EPOCH);
+        decide(score, general, &Profile::global(&scoring), &mut state, None)
+    }
+
+    fn tier(name: &str, ban_secs: Option<u64>) -> Tier {
+        Tier {
+            name: name.into(),
+            duration: ban_secs.map(Duration::from_secs),
+        }
+    }
+
+    fn tier_config(
+        name: &str,
+        enter: u32,
+        exit: u32,
+        action: TierAction,
+        bans: &[u64],
+    ) -> TierConfig {
+        TierConfig {
+            name: name.into(),
+            enter,
+            exit: Some(exit),
+            action,
+            ban_seconds: bans.to_vec(),
+        }
+    }
+
+    /// detect at 40, throttle at 60, block 10m at 80, block 24h at 95.
+    fn tiered() -> ScoringConfig {
+        ScoringConfig {
+            tiers: vec![
+                tier_config("watch", 40, 30, TierAction::Detect, &[]),
+                tier_config("slow", 60, 50, TierAction::Throttle, &[]),
+                tier_config("ban", 80, 70, TierAction::Block, &[600, 3600, 86400]),
+                tier_config("ban-long", 95, 95, TierAction::Block, &[86400]),
+            ],
+            ..scoring()
+        }
+    }
+
     #[test]
     fn allows_when_below_threshold() {
-        let decision = decide(&score(50), &general_detect(),&scoring());
+        let decision = decide_once(&score(50), &general_detect());
         assert_eq!(decision, Decision::Allow);
     }
 
     #[test]
     fn detects_when_above_threshold_in_detect_mode() {
-        let decision = decide(&score(120), &general_detect(),&scoring());
-        assert_eq!(decision, Decision::Detect);
+        let decision = decide_once(&score(120), &general_detect());
+        assert_eq!(decision, Decision::Detect(tier("threshold", None)));
     }
 
     #[test]
     fn blocks_when_above_threshold_in_enforce_mode() {
-        let decision = decide(&score(120),  &general_enforce(),&scoring());
-        assert_eq!(decision, Decision::Block);
+        let decision = decide_once(&score(120), &general_enforce());
+        assert_eq!(decision, Decision::Block(tier("threshold", None)));
+    }
+
+    #[test]
+    fn forced_score_acts_below_threshold() {
+        let mut forced = score(10);
+        forced.force(Forced::Act);
+
+        let threshold = tier("threshold", None);
+
+        assert_eq!(decide_once(&forced, &general_detect()), Decision::Detect(threshold.clone()));
+        assert_eq!(decide_once(&forced, &general_enforce()), Decision::Block(threshold));
+    }
+
+    #[test]
+    fn forced_allow_wins_over_score_and_act() {
+        let mut allowed = score(500);
+        allowed.force(Forced::Allow);
+        allowed.force(Forced::Act);
+
+        assert_eq!(decide_once(&allowed, &general_enforce()), Decision::Allow);
+    }
+
+    #[test]
+    fn tiers_hold_until_the_score_drops_below_exit() {
+        let scoring = tiered();
+        let profile = Profile::global(&scoring);
+        let mut state = IpState::new("192.0.2.1".parse().unwrap(), SystemTime::UNIX_EPOCH);
+        let mut decide = |value| decide(&score(value), &general_enforce(), &profile, &mut state, None);
+
+        assert_eq!(decide(35), Decision::Allow);
+        assert_eq!(decide(45), Decision::Detect(tier("watch", None)));
+        assert_eq!(decide(65), Decision::Throttle(tier("slow", None)));
+        // Below `enter`, above `exit`: no flapping.
+        assert_eq!(decide(55), Decision::Throttle(tier("slow", None)));
+        assert_eq!(decide(61), Decision::Throttle(tier("slow", None)));
+        // Below `slow`'s exit, still above `watch`'s.
+        assert_eq!(decide(45), Decision::Detect(tier("watch", None)));
+        assert_eq!(decide(55), Decision::Detect(tier("watch", None)));
+        assert_eq!(decide(29), Decision::Allow);
+    }
+
+    #[test]
+    fn repeat_bans_escalate() {
+        let scoring = tiered();
+        let profile = Profile::global(&scoring);
+        let mut state = IpState::new("192.0.2.1".parse().unwrap(), SystemTime::UNIX_EPOCH);
+        let mut decide = |value| decide(&score(value), &general_enforce(), &profile, &mut state, None);
+
+        assert_eq!(decide(85), Decision::Block(tier("ban", Some(600))));
+        // Still the same ban while the score stays up.
+        assert_eq!(decide(75), Decision::Block(tier("ban", Some(600))));
+        assert_eq!(decide(99), Decision::Block(tier("ban-long", Some(86400))));
+        assert_eq!(decide(10), Decision::Allow);
+
+        assert_eq!(decide(85), Decision::Block(tier("ban", Some(3600))));
+        assert_eq!(decide(62), Decision::Throttle(tier("slow", None)));
+        assert_eq!(decide(85), Decision::Block(tier("ban", Some(86400))));
+        assert_eq!(decide(10), Decision::Allow);
+        assert_eq!(decide(85), Decision::Block(tier("ban", Some(86400))));
+
+        assert_eq!(state.bans, 4);
+    }
+
+    #[test]
+    fn returning_after_the_ban_ends_is_a_new_ban() {
+        let scoring = tiered();
+        let profile = Profile::global(&scoring);
+        let mut state = IpState::new("192.0.2.1".parse().unwrap(), SystemTime::UNIX_EPOCH);
+        let mut decide_at = |secs, value| {
+            state.last_event = at(secs);
+            decide(&score(value), &general_enforce(), &profile, &mut state, None)
+        };
+
+        assert_eq!(decide_at(0, 85), Decision::Block(tier("ban", Some(600))));
+        // A sticky signal keeps the score over `exit`: same ban until it ends.
+        assert_eq!(decide_at(300, 75), Decision::Block(tier("ban", Some(600))));
+        assert_eq!(decide_at(600, 85), Decision::Block(tier("ban", Some(3600))));
+        // Over `exit` but under `enter` once the second ban has run out.
+        assert_eq!(decide_at(4200, 75), Decision::Throttle(tier("slow", None)));
+        assert_eq!(state.bans, 2);
+    }
+
+    #[test]
+    fn bans_without_ban_seconds_end_after_the_backend_bantime() {
+        let scoring = scoring();
+        let profile = Profile::global(&scoring);
+        let mut state = IpState::new("192.0.2.1".parse().unwrap(), SystemTime::UNIX_EPOCH);
+        // Bans so far after a request at `secs`.
+        let mut bans_at = |secs, backend: Option<u64>| {
+            state.last_event = at(secs);
+            let backend = backend.map(Duration::from_secs);
+            decide(&score(120), &general_enforce(), &profile, &mut state, backend);
+            state.bans
+        };
+
+        assert_eq!(bans_at(0, Some(600)), 1);
+        assert_eq!(bans_at(300, Some(600)), 1);
+        assert_eq!(bans_at(600, None), 2);
+        // Length unknown: the ban holds while the score does.
+        assert_eq!(bans_at(100_000, None), 2);
+    }
+
+    #[test]
+    fn forced_act_enters_the_first_block_tier_and_detect_mode_keeps_durations() {
+        let scoring = tiered();
+        let profile = Profile::global(&scoring);
+        let mut state = IpState::new("192.0.2.1".parse().unwrap(), SystemTime::UNIX_EPOCH);
+        let mut forced = score(10);
+        forced.force(Forced::Act);
+
+        assert_eq!(
+            decide(&forced, &general_detect(), &profile, &mut state, None),
+            Decision::Detect(tier("ban", Some(600)))
+        );
+        assert_eq!(
+            decide(&score(99), &general_detect(), &profile, &mut state, None),
+            Decision::Detect(tier("ban-long", Some(86400)))
+        );
+    }
+
+    #[test]
+    fn detect_mode_bans_are_not_counted() {
+        let scoring = tiered();
+        let profile = Profile::global(&scoring);
+        let mut state = IpState::new("192.0.2.1".parse().unwrap(), SystemTime::UNIX_EPOCH);
+        let mut decide = |value, general: &GeneralConfig| {
+            decide(&score(value), general, &profile, &mut state, None)
+        };
+
+        for _ in 0..3 {
+            assert_eq!(decide(85, &general_detect()), Decision::Detect(tier("ban", Some(600))));
+            assert_eq!(decide(10, &general_detect()), Decision::Allow);
+        }
+        assert_eq!(decide(85, &general_detect()), Decision::Detect(tier("ban", Some(600))));
+
+        // Switched to enforce while in the block tier: the first real ban.
+        assert_eq!(decide(85, &general_enforce()), Decision::Block(tier("ban", Some(600))));
+        assert_eq!(decide(75, &general_enforce()), Decision::Block(tier("ban", Some(600))));
+        assert_eq!(decide(10, &general_enforce()), Decision::Allow);
+        assert_eq!(decide(85, &general_enforce()), Decision::Block(tier("ban", Some(3600))));
+        assert_eq!(state.bans, 2);
+    }
+
+    #[test]
+    fn formats_durations_in_their_largest_unit() {
+        let format = |secs| format_duration(Duration::from_secs(secs));
+        assert_eq!(format(600), "10m");
+        assert_eq!(format(3600), "1h");
+        assert_eq!(format(172800), "2d");
+        assert_eq!(format(90), "90s");
+        assert_eq!(tier("ban", Some(3600)).to_string(), "ban/1h");
     }
 }
@@ -0,0 +1,256 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use anyhow::Context;
+use regex::Regex;
+
+use crate::config::schema::{FilterConfig, ParserConfig};
+use crate::parser::ParsedEvent;
+
+/// Events dropped by each filter since startup.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct FilterStats {
+    pub status: u64,
+    pub path_prefix: u64,
+    pub path_pattern: u64,
+    pub method: u64,
+    pub host: u64,
+    pub user_agent: u64,
+}
+
+impl FilterStats {
+    pub fn total(&self) -> u64 {
+        self.status
+            + self.path_prefix
+            + self.path_pattern
+            + self.method
+            + self.host
+            + self.user_agent
+    }
+}
+
+impl fmt::Display for FilterStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "status={} path_prefix={} path_pattern={} method={} host={} user_agent={}",
+            self.status,
+            self.path_prefix,
+            self.path_pattern,
+            self.method,
+            self.host,
+            self.user_agent
+        )
+    }
+}
+
+/// Decides which parsed events are allowed to reach the state store.
+///
+/// Built once from `parser.ignore_status` and `[filter]`; regular
+/// expressions are compiled up front so bad patterns fail at load time.
+#[derive(Debug)]
+pub struct RequestFilter {
+    ignore_status: HashSet<u16>,
+    include_path_prefixes: Vec<String>,
+    exclude_path_prefixes: Vec<String>,
+    exclude_path_patterns: Vec<Regex>,
+    include_methods: HashSet<String>,
+    exclude_methods: HashSet<String>,
+    include_hosts: HashSet<String>,
+    exclude_hosts: HashSet<String>,
+    exclude_user_agents: Vec<Regex>,
+    stats: FilterStats,
+}
+
+impl RequestFilter {
+    pub fn new(parser: &ParserConfig, cfg: &FilterConfig) -> anyhow::Result<Self> {
+        Ok(Self {
+            ignore_status: parser.ignore_status.iter().copied().collect(),
+            include_path_prefixes: cfg.include_path_prefixes.clone(),
+            exclude_path_prefixes: cfg.exclude_path_prefixes.clone(),
+            exclude_path_patterns: compile_all(
+                &cfg.exclude_path_patterns,
+                "filter.exclude_path_patterns",
+            )?,
+            include_methods: uppercase_set(&cfg.include_methods),
+            exclude_methods: uppercase_set(&cfg.exclude_methods),
+            include_hosts: lowercase_set(&cfg.include_hosts),
+            exclude_hosts: lowercase_set(&cfg.exclude_hosts),
+            exclude_user_agents: compile_all(
+                &cfg.exclude_user_agents,
+                "filter.exclude_user_agents",
+            )?,
+            stats: FilterStats::default(),
+        })
+    }
+
+    /// Returns `true` if the event should be scored. Dropped events are
+    /// counted against the first filter that rejected them.
+    pub fn accept(&mut self, event: &ParsedEvent) -> bool {
+        match self.rejected_by(event) {
+            Some(counter) => {
+                *counter += 1;
+                false
+            }
+            None => true,
+        }
+    }
+
+    pub fn stats(&self) -> &FilterStats {
+        &self.stats
+    }
+
+    fn rejected_by(&mut self, event: &ParsedEvent) -> Option<&mut u64> {
+        if self.ignore_status.contains(&event.status) {
+            return Some(&mut self.stats.status);
+        }
+
+        let method = event.method.as_deref().unwrap_or("").to_ascii_uppercase();
+        if !allowed(&method, &self.include_methods, &self.exclude_methods) {
+            return Some(&mut self.stats.method);
+        }
+
+        let host = event.host.as_deref().unwrap_or("").to_ascii_lowercase();
+        if !allowed(&host, &self.include_hosts, &self.exclude_hosts) {
+            return Some(&mut self.stats.host);
+        }
+
+        let path = event.path.as_str();
+        let included = self.include_path_prefixes.is_empty()
+            || self.include_path_prefixes.iter().any(|p| path.starts_with(p.as_str()));
+        if !included || self.exclude_path_prefixes.iter().any(|p| path.starts_with(p.as_str())) {
+            return Some(&mut self.stats.path_prefix);
+        }
+
+        if self.exclude_path_patterns.iter().any(|re| re.is_match(path)) {
+            return Some(&mut self.stats.path_pattern);
+        }
+
+        if let Some(ua) = event.user_agent.as_deref() {
+            if self.exclude_user_agents.iter().any(|re| re.is_match(ua)) {
+                return Some(&mut self.stats.user_agent);
+            }
+        }
+
+        None
+    }
+}
+
+fn allowed(value: &str, include: &HashSet<String>, exclude: &HashSet<String>) -> bool {
+    (include.is_empty() || include.contains(value)) && !exclude.contains(value)
+}
+
+fn compile_all(patterns: &[String], field: &str) -> anyhow::Result<Vec<Regex>> {
+    patterns
+        .iter()
+        .map(|p| Regex::new(p).with_context(|| format!("Invalid regex in {}: {:?}", field, p)))
+        .collect()
+}
+
+fn uppercase_set(values: &[String]) -> HashSet<String> {
+    values.iter().map(|v| v.to_ascii_uppercase()).collect()
+}
+
+fn lowercase_set(values: &[String]) -> HashSet<String> {
+    values.iter().map(|v| v.to_ascii_lowercase()).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::schema::{JsonFieldMap, ParserFormat};
+    use std::time::SystemTime;
+
+    fn parser_cfg(ignore_status: Vec<u16>) -> ParserConfig {
+        ParserConfig {
+            format: ParserFormat::NginxCombined,
+            ignore_status,
+            log_format: None,
+            json: JsonFieldMap::default(),
+            client_ip: None,
+        }
+    }
+
+    fn event(method: &str, path: &str, status: u16) -> ParsedEvent {
+        ParsedEvent {
+            method: Some(method.into()),
+            status,
+            user_agent: Some("Mozilla/5.0".into()),
+            host: Some("shop.example.com".into()),
+            ..ParsedEvent::test("1.2.3.4", path, SystemTime::now())
+        }
+    }
+
+    #[test]
+    fn ignores_configured_status_codes() {
+        let mut filter = RequestFilter::new(&parser_cfg(vec![200, 301]), &FilterConfig::default()).unwrap();
+
+        assert!(!filter.accept(&event("GET", "/", 200)));
+        assert!(!filter.accept(&event("GET", "/", 301)));
+        assert!(filter.accept(&event("GET", "/", 404)));
+        assert_eq!(filter.stats().status, 2);
+    }
+
+    #[test]
+    fn applies_path_filters() {
+        let cfg = FilterConfig {
+            exclude_path_prefixes: vec!["/healthz".into()],
+            exclude_path_patterns: vec![r"\.(css|js|png)$".into()],
+            ..FilterConfig::default()
+        };
+        let mut filter = RequestFilter::new(&parser_cfg(vec![]), &cfg).unwrap();
+
+        assert!(!filter.accept(&event("GET", "/healthz/live", 404)));
+        assert!(!filter.accept(&event("GET", "/static/app.js", 404)));
+        assert!(filter.accept(&event("GET", "/products", 404)));
+        assert_eq!(filter.stats().path_prefix, 1);
+        assert_eq!(filter.stats().path_pattern, 1);
+    }
+
+    #[test]
+    fn include_lists_restrict_traffic() {
+        let cfg = FilterConfig {
+            include_path_prefixes: vec!["/api/".into()],
+            include_methods: vec!["post".into()],
+            ..FilterConfig::default()
+        };
+        let mut filter = RequestFilter::new(&parser_cfg(vec![]), &cfg).unwrap();
+
+        assert!(filter.accept(&event("POST", "/api/login", 401)));
+        assert!(!filter.accept(&event("GET", "/api/login", 401)));
+        assert!(!filter.accept(&event("POST", "/about", 401)));
+        assert_eq!(filter.stats().method, 1);
+        assert_eq!(filter.stats().path_prefix, 1);
+    }
+
+    #[test]
+    fn excludes_hosts_and_user_agents() {
+        let cfg = FilterConfig {
+            exclude_hosts: vec!["Shop.Example.com".into()],
+            exclude_user_agents: vec!["(?i)uptimerobot".into()],
+            ..FilterConfig::default()
+        };
+        let mut filter = RequestFilter::new(&parser_cfg(vec![]), &cfg).unwrap();
+
+        assert!(!filter.accept(&event("GET", "/", 404)));
+
+        let mut monitor = event("GET", "/", 404);
+        monitor.host = None;
+        monitor.user_agent = Some("Mozilla/5.0 (compatible; UptimeRobot/2.0)".into());
+        assert!(!filter.accept(&monitor));
+
+        assert_eq!(filter.stats().host, 1);
+        assert_eq!(filter.stats().user_agent, 1);
+        assert_eq!(filter.stats().total(), 2);
+    }
+
+    #[test]
+    fn rejects_invalid_patterns() {
+        let cfg = FilterConfig {
+            exclude_user_agents: vec!["(unclosed".into()],
+            ..FilterConfig::default()
+        };
+        assert!(RequestFilter::new(&parser_cfg(vec![]), &cfg).is_err());
+    }
+}
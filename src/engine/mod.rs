@@ -2,5 +2,9 @@ pub mod scoring;
 pub mod decision;
 pub mod pipeline;
 pub mod action;
+pub mod filter;
+pub mod profile;
+pub mod rules;
+pub mod signals;
 
 
@@ -1,7 +1,13 @@
-use crate::config::schema::AargalConfig;
-use crate::engine::action::map_decision_to_action;
-use crate::engine::decision::{decide};
+use std::net::IpAddr;
+use std::time::Duration;
+
+use crate::config::schema::{AargalConfig, BlockAction};
+use crate::engine::action::{map_decision_to_action, ActionResult};
+use crate::engine::decision::{decide, Decision};
+use crate::engine::profile::Profile;
+use crate::engine::rules;
 use crate::engine::scoring::{score_ip, ScoreResult};
+use crate::engine::signals::Signals;
 use crate::model::state_store::StateStore;
 use crate::parser::ParsedEvent;
 use crate::output::executor::execute_action;
@@ -14,55 +20,108 @@ pub enum PipelineError {
     Action,
 }
 
-pub fn process_event(
-    event: ParsedEvent,
+/// What the pipeline concluded for one event.
+#[derive(Debug, Clone)]
+pub struct Outcome {
+    pub ip: IpAddr,
+    pub score: ScoreResult,
+    pub decision: Decision,
+    pub action: ActionResult,
+    /// The client started a ban or moved up a tier with this event.
+    /// Block actions run only then, not on every request while banned.
+    pub escalated: bool,
+}
+
+/// Steps 1–3: update state, score and decide. No side effects, so
+/// replays can run exactly what the daemon runs.
+pub fn evaluate_event(
+    event: &ParsedEvent,
     state: &mut StateStore,
     config: &AargalConfig,
-) -> Result<(), PipelineError> {
+    signals: &Signals,
+) -> Outcome {
     /*
-     * STEP 1 — Update IP state
+     * STEP 1 — Update IP state, in the windows of the request's profile
      */
+    let profile = Profile::select(&config.scoring, event);
     let ip_state = state
-        .update(&event);
+        .update_in(event, profile.name);
+    signals.observe(event, ip_state);
 
-    println!("IP state in process_event() : {:?}", ip_state);
+    log::debug!("IP state in process_event() : {:?}", ip_state);
 
     /*
      * STEP 2 — Score behavior
      */
-    let score: ScoreResult =
-        score_ip(&ip_state, &config.scoring);
+    let mut score: ScoreResult =
+        score_ip(ip_state, &profile, &config.scoring);
+    signals.score(event, ip_state, config, &profile, &mut score);
+    rules::score(&config.rules, event, ip_state, profile.name, &mut score);
+    rules::score(profile.rules, event, ip_state, profile.name, &mut score);
 
-    println!("Score in process_event() : {:?}", score);
+    log::debug!("Score in process_event() : {:?}", score);
 
     /*
      * STEP 3 — Make decision
      */
+    let before = (ip_state.bans, ip_state.tier(profile.name));
     let decision = decide(
         &score,
         &config.general,
-        &config.scoring,
+        &profile,
+        ip_state,
+        backend_ban(config),
     );
+    let escalated = ip_state.bans > before.0 || ip_state.tier(profile.name) > before.1;
 
-    println!("IP decision in process_event() : {:?}", decision);
+    log::debug!("IP decision in process_event() : {:?}", decision);
 
     let action = map_decision_to_action(
-    decision,
-    &config.general,
-    &config.actions,
-);
-   println!("IP action in process_event() : {:?}", action);
+        decision.clone(),
+        &config.general,
+        &config.actions,
+    );
+    log::debug!("IP action in process_event() : {:?}", action);
+
+    Outcome {
+        ip: ip_state.ip,
+        score,
+        decision,
+        action,
+        escalated,
+    }
+}
+
+/// How long the blocking backend bans for when a tier has no
+/// `ban_seconds`: the fail2ban jail's `bantime`, if configured.
+fn backend_ban(config: &AargalConfig) -> Option<Duration> {
+    match config.actions.on_block {
+        BlockAction::Fail2ban => config.fail2ban.bantime.map(Duration::from_secs),
+        _ => None,
+    }
+}
+
+pub fn process_event(
+    event: ParsedEvent,
+    state: &mut StateStore,
+    config: &AargalConfig,
+    signals: &Signals,
+) -> Result<Outcome, PipelineError> {
+    let outcome = evaluate_event(&event, state, config, signals);
 
     /*
      * STEP 4 — Execute action (side-effects only here)
      */
+    if matches!(outcome.action, ActionResult::Block(..)) && !outcome.escalated {
+        return Ok(outcome);
+    }
     execute_action(
-        action,
-        &ip_state.ip,
-        &score,
+        outcome.action.clone(),
+        outcome.ip,
+        &outcome.score,
         Some(&config.fail2ban),
     )
     .map_err(|_| PipelineError::Action)?;
 
-    Ok(())
+    Ok(outcome)
 }
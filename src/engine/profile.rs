@@ -0,0 +1,146 @@
+use crate::config::schema::{
+    ProfileConfig, RuleConfig, ScoringConfig, ScoringWeights, TierConfig,
+};
+use crate::parser::ParsedEvent;
+
+/// The scoring settings that apply to one request: a
+/// `[[scoring.profiles]]` entry, or the top-level `[scoring]`.
+#[derive(Debug, Clone, Copy)]
+pub struct Profile<'a> {
+    /// `None` for the top-level `[scoring]`; also selects the client's
+    /// rate windows.
+    pub name: Option<&'a str>,
+    pub threshold: u32,
+    pub weights: &'a ScoringWeights,
+    /// Profile rules, scored after the top-level `[[rules]]`.
+    pub rules: &'a [RuleConfig],
+    /// Empty: a single tier at `threshold`.
+    pub tiers: &'a [TierConfig],
+}
+
+impl<'a> Profile<'a> {
+    /// The top-level `[scoring]` settings.
+    pub fn global(scoring: &'a ScoringConfig) -> Self {
+        Self {
+            name: None,
+            threshold: scoring.threshold,
+            weights: &scoring.weights,
+            rules: &[],
+            tiers: &scoring.tiers,
+        }
+    }
+
+    /// The first profile `event` matches, or the top-level settings.
+    pub fn select(scoring: &'a ScoringConfig, event: &ParsedEvent) -> Self {
+        let Some(profile) = scoring.profiles.iter().find(|p| matches(p, event)) else {
+            return Self::global(scoring);
+        };
+
+        Self {
+            name: Some(&profile.name),
+            threshold: profile.threshold.unwrap_or(scoring.threshold),
+            weights: profile.weights.as_ref().unwrap_or(&scoring.weights),
+            rules: &profile.rules,
+            tiers: if profile.tiers.is_empty() {
+                &scoring.tiers
+            } else {
+                &profile.tiers
+            },
+        }
+    }
+}
+
+fn matches(profile: &ProfileConfig, event: &ParsedEvent) -> bool {
+    let host = event.host.as_deref().map(strip_port);
+    let host_matches = profile.hosts.is_empty()
+        || host.is_some_and(|host| profile.hosts.iter().any(|h| h.eq_ignore_ascii_case(host)));
+
+    let path_matches = profile.path_prefixes.is_empty()
+        || profile.path_prefixes.iter().any(|p| event.path.starts_with(p.as_str()));
+
+    let source_matches = profile.sources.is_empty()
+        || event
+            .source
+            .as_deref()
+            .is_some_and(|source| profile.sources.iter().any(|s| s == source));
+
+    host_matches && path_matches && source_matches
+}
+
+/// `example.com:8080` -> `example.com`; IPv6 literals keep their brackets.
+fn strip_port(host: &str) -> &str {
+    match host.rsplit_once(':') {
+        Some((name, _)) if name.starts_with('[') && name.ends_with(']') => name,
+        Some((name, _)) if !name.starts_with('[') && !name.contains(':') => name,
+        _ => host,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    const SCORING: &str = r#"
+        threshold = 60
+
+        [weights]
+        rate = 40
+        error = 30
+        user_agent = 20
+        path_entropy = 10
+
+        [[profiles]]
+        name = "login"
+        path_prefixes = ["/login", "/wp-login.php"]
+        threshold = 30
+
+        [[profiles]]
+        name = "api"
+        hosts = ["api.example.com"]
+        sources = ["api"]
+        weights = { rate = 70, error = 30, user_agent = 0, path_entropy = 0 }
+    "#;
+
+    fn event(host: Option<&str>, path: &str, source: Option<&str>) -> ParsedEvent {
+        ParsedEvent {
+            host: host.map(Into::into),
+            source: source.map(Into::into),
+            ..ParsedEvent::test("192.0.2.1", path, UNIX_EPOCH)
+        }
+    }
+
+    #[test]
+    fn selects_first_profile_matching_every_criterion() {
+        let scoring: ScoringConfig = toml::from_str(SCORING).unwrap();
+        let name = |e: ParsedEvent| Profile::select(&scoring, &e).name;
+
+        assert_eq!(name(event(None, "/login?next=/", None)), Some("login"));
+        assert_eq!(name(event(Some("API.example.com:443"), "/login", Some("api"))), Some("login"));
+        assert_eq!(name(event(Some("API.example.com:443"), "/v1/items", Some("api"))), Some("api"));
+        // Host matches, source does not.
+        assert_eq!(name(event(Some("api.example.com"), "/v1/items", None)), None);
+        assert_eq!(name(event(Some("www.example.com"), "/", None)), None);
+    }
+
+    #[test]
+    fn profiles_fall_back_to_top_level_settings() {
+        let scoring: ScoringConfig = toml::from_str(SCORING).unwrap();
+
+        let login = Profile::select(&scoring, &event(None, "/login", None));
+        assert_eq!((login.threshold, login.weights.rate), (30, 40));
+
+        let api = Profile::select(&scoring, &event(Some("api.example.com"), "/", Some("api")));
+        assert_eq!((api.threshold, api.weights.rate), (60, 70));
+    }
+
+    #[test]
+    fn strips_ports_but_not_ipv6_addresses() {
+        assert_eq!(strip_port("example.com:8080"), "example.com");
+        assert_eq!(strip_port("example.com"), "example.com");
+        assert_eq!(strip_port("[2001:db8::1]:443"), "[2001:db8::1]");
+        assert_eq!(strip_port("[2001:db8::1]"), "[2001:db8::1]");
+        assert_eq!(strip_port("2001:db8::1"), "2001:db8::1");
+    }
+}
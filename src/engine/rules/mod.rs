@@ -0,0 +1,479 @@
+//! Declarative `[[rules]]`: site-specific conditions over the request
+//! being scored and its client's state.
+//!
+//! Conditions are compiled when the config is deserialized, so a typo or
+//! a bad regex fails `load_config` instead of the first matching request.
+
+mod parser;
+
+use std::cmp::Ordering;
+use std::fmt;
+
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::config::schema::{RuleConfig, RuleDecision};
+use crate::engine::scoring::{Forced, ScoreReason, ScoreResult};
+use crate::model::ip_state::IpState;
+use crate::parser::ParsedEvent;
+
+/// A compiled rule condition, e.g.
+/// `path ~ "^/api/" && window_1m.requests > 120 && ua.is_empty`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Condition {
+    source: String,
+    expr: Expr,
+}
+
+impl TryFrom<String> for Condition {
+    type Error = String;
+
+    fn try_from(source: String) -> Result<Self, String> {
+        let expr = parser::parse(&source)?;
+        Ok(Self { source, expr })
+    }
+}
+
+impl Condition {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        Self::try_from(source.to_string())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Window lengths (seconds) the condition reads; each must be one of
+    /// `scoring.rates.windows_seconds`.
+    pub fn windows(&self) -> Vec<u64> {
+        let mut windows = Vec::new();
+        self.expr.visit(&mut |field| {
+            if let Field::Window { secs, .. } = field {
+                if !windows.contains(&secs) {
+                    windows.push(secs);
+                }
+            }
+        });
+        windows
+    }
+
+    /// Window fields read the client's windows in scoring profile
+    /// `profile`.
+    pub fn matches(&self, event: &ParsedEvent, state: &IpState, profile: Option<&str>) -> bool {
+        self.expr.eval(&Context { event, state, profile })
+    }
+}
+
+/// What a condition is evaluated against.
+struct Context<'a> {
+    event: &'a ParsedEvent,
+    state: &'a IpState,
+    profile: Option<&'a str>,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    /// A flag on its own: `ua.is_empty`.
+    Flag(Field),
+    Compare(Field, CmpOp, Literal),
+    /// `field ~ "regex"`, or `!~` when the flag is set.
+    Matches(Field, Regex, bool),
+}
+
+impl Expr {
+    fn visit(&self, f: &mut impl FnMut(Field)) {
+        match self {
+            Expr::And(a, b) | Expr::Or(a, b) => {
+                a.visit(f);
+                b.visit(f);
+            }
+            Expr::Not(inner) => inner.visit(f),
+            Expr::Flag(field) | Expr::Compare(field, ..) | Expr::Matches(field, ..) => f(*field),
+        }
+    }
+
+    fn eval(&self, ctx: &Context) -> bool {
+        match self {
+            Expr::And(a, b) => a.eval(ctx) && b.eval(ctx),
+            Expr::Or(a, b) => a.eval(ctx) || b.eval(ctx),
+            Expr::Not(inner) => !inner.eval(ctx),
+            Expr::Flag(field) => matches!(field.value(ctx), Value::Bool(true)),
+            Expr::Compare(field, op, literal) => {
+                let ordering = match (field.value(ctx), literal) {
+                    (Value::Num(a), Literal::Num(b)) => a.total_cmp(b),
+                    (Value::Str(a), Literal::Str(b)) => a.cmp(b.as_str()),
+                    (Value::Bool(a), Literal::Bool(b)) => a.cmp(b),
+                    _ => return false,
+                };
+                op.holds(ordering)
+            }
+            Expr::Matches(field, regex, negate) => match field.value(ctx) {
+                Value::Str(text) => regex.is_match(text) != *negate,
+                _ => false,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CmpOp {
+    fn holds(self, ordering: Ordering) -> bool {
+        match self {
+            CmpOp::Eq => ordering == Ordering::Equal,
+            CmpOp::Ne => ordering != Ordering::Equal,
+            CmpOp::Lt => ordering == Ordering::Less,
+            CmpOp::Le => ordering != Ordering::Greater,
+            CmpOp::Gt => ordering == Ordering::Greater,
+            CmpOp::Ge => ordering != Ordering::Less,
+        }
+    }
+}
+
+impl fmt::Display for CmpOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CmpOp::Eq => "==",
+            CmpOp::Ne => "!=",
+            CmpOp::Lt => "<",
+            CmpOp::Le => "<=",
+            CmpOp::Gt => ">",
+            CmpOp::Ge => ">=",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Literal {
+    Num(f64),
+    Str(String),
+    Bool(bool),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Num,
+    Str,
+    Bool,
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Kind::Num => "a number",
+            Kind::Str => "text",
+            Kind::Bool => "a flag",
+        })
+    }
+}
+
+enum Value<'a> {
+    Num(f64),
+    Str(&'a str),
+    Bool(bool),
+}
+
+/// What a condition can look at. Missing event fields read as `""`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Path,
+    Method,
+    Status,
+    Host,
+    Source,
+    Ua,
+    Referer,
+    Bytes,
+    /// Missing, empty or `-` user agent.
+    UaIsEmpty,
+    UaDistinct,
+    /// Lifetime totals.
+    Requests,
+    Errors,
+    /// `window_1m.requests` / `window_1m.errors`.
+    Window { secs: u64, errors: bool },
+    PathsDistinct,
+    PathsUniqueRatio,
+    PathsLongestRun,
+    RobotsFetched,
+    Honeypot,
+}
+
+const FIELDS: &[(&str, Field)] = &[
+    ("path", Field::Path),
+    ("method", Field::Method),
+    ("status", Field::Status),
+    ("host", Field::Host),
+    ("source", Field::Source),
+    ("ua", Field::Ua),
+    ("referer", Field::Referer),
+    ("bytes", Field::Bytes),
+    ("ua.is_empty", Field::UaIsEmpty),
+    ("ua.distinct", Field::UaDistinct),
+    ("requests", Field::Requests),
+    ("errors", Field::Errors),
+    ("paths.distinct", Field::PathsDistinct),
+    ("paths.unique_ratio", Field::PathsUniqueRatio),
+    ("paths.longest_run", Field::PathsLongestRun),
+    ("robots.fetched", Field::RobotsFetched),
+    ("honeypot", Field::Honeypot),
+];
+
+impl Field {
+    fn parse(name: &str) -> Result<Field, String> {
+        if let Some((_, field)) = FIELDS.iter().find(|(n, _)| *n == name) {
+            return Ok(*field);
+        }
+        parse_window(name).ok_or_else(|| format!("unknown field `{}`", name))
+    }
+
+    fn kind(self) -> Kind {
+        match self {
+            Field::Path
+            | Field::Method
+            | Field::Host
+            | Field::Source
+            | Field::Ua
+            | Field::Referer => Kind::Str,
+            Field::UaIsEmpty | Field::RobotsFetched | Field::Honeypot => Kind::Bool,
+            _ => Kind::Num,
+        }
+    }
+
+    fn value<'a>(self, ctx: &Context<'a>) -> Value<'a> {
+        let (event, state) = (ctx.event, ctx.state);
+        let text = |value: &'a Option<String>| Value::Str(value.as_deref().unwrap_or(""));
+        let count = |n: u64| Value::Num(n as f64);
+        let paths = state.paths_for(ctx.profile);
+
+        match self {
+            Field::Path => Value::Str(&event.path),
+            Field::Method => text(&event.method),
+            Field::Status => count(event.status.into()),
+            Field::Host => text(&event.host),
+            Field::Source => text(&event.source),
+            Field::Ua => text(&event.user_agent),
+            Field::Referer => text(&event.referer),
+            Field::Bytes => count(event.bytes_sent),
+            Field::UaIsEmpty => Value::Bool(matches!(
+                event.user_agent.as_deref().map(str::trim),
+                None | Some("") | Some("-")
+            )),
+            Field::UaDistinct => count(state.distinct_user_agents() as u64),
+            Field::Requests => count(state.request_count),
+            Field::Errors => count(state.error_count),
+            Field::Window { secs, errors } => {
+                let window = state
+                    .windows_for(ctx.profile)
+                    .iter()
+                    .find(|w| w.requests.window_secs() == secs);
+                count(window.map_or(0, |w| {
+                    let counter = if errors { &w.errors } else { &w.requests };
+                    counter.count(state.last_event)
+                }))
+            }
+            Field::PathsDistinct => count(paths.map_or(0, |p| p.distinct())),
+            Field::PathsUniqueRatio => Value::Num(paths.map_or(0.0, |p| p.unique_ratio())),
+            Field::PathsLongestRun => count(paths.map_or(0, |p| p.longest_run().into())),
+            Field::RobotsFetched => Value::Bool(state.robots.fetched_any()),
+            Field::Honeypot => Value::Bool(state.honeypot.is_some()),
+        }
+    }
+}
+
+/// `window_<n><s|m|h>.<requests|errors>`
+fn parse_window(name: &str) -> Option<Field> {
+    let (window, counter) = name.strip_prefix("window_")?.split_once('.')?;
+    let errors = match counter {
+        "requests" => false,
+        "errors" => true,
+        _ => return None,
+    };
+    let unit = match window.chars().last()? {
+        's' => 1,
+        'm' => 60,
+        'h' => 3600,
+        _ => return None,
+    };
+    let n: u64 = window[..window.len() - 1].parse().ok()?;
+    Some(Field::Window {
+        secs: n.checked_mul(unit)?,
+        errors,
+    })
+}
+
+/// `window_1m` rather than `window_60s`.
+pub fn window_name(secs: u64) -> String {
+    match secs {
+        s if s % 3600 == 0 => format!("window_{}h", s / 3600),
+        s if s % 60 == 0 => format!("window_{}m", s / 60),
+        s => format!("window_{}s", s),
+    }
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Field::Window { secs, errors } => {
+                let counter = if *errors { "errors" } else { "requests" };
+                write!(f, "{}.{}", window_name(*secs), counter)
+            }
+            field => {
+                let (name, _) = FIELDS.iter().find(|(_, f)| f == field).expect("listed field");
+                f.write_str(name)
+            }
+        }
+    }
+}
+
+/// Add every matching rule to `score`: its score, a
+/// [`ScoreReason::Rule`], and its decision if it has one.
+pub fn score(
+    rules: &[RuleConfig],
+    event: &ParsedEvent,
+    state: &IpState,
+    profile: Option<&str>,
+    score: &mut ScoreResult,
+) {
+    for rule in rules {
+        if !rule.condition.matches(event, state, profile) {
+            continue;
+        }
+        score.score += rule.score;
+        score.reasons.push(ScoreReason::Rule {
+            name: rule.name.clone(),
+        });
+        match rule.decision {
+            Some(RuleDecision::Block) => score.force(Forced::Act),
+            Some(RuleDecision::Allow) => score.force(Forced::Allow),
+            None => {}
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::clock::at;
+
+    fn event(path: &str, ua: Option<&str>, secs: u64) -> ParsedEvent {
+        ParsedEvent {
+            method: Some("POST".into()),
+            bytes_sent: 512,
+            user_agent: ua.map(Into::into),
+            host: Some("shop.example.com".into()),
+            ..ParsedEvent::test("203.0.113.7", path, at(secs))
+        }
+    }
+
+    fn rule(name: &str, condition: &str, score: u32, decision: Option<RuleDecision>) -> RuleConfig {
+        RuleConfig {
+            name: name.into(),
+            condition: Condition::parse(condition).unwrap(),
+            score,
+            decision,
+        }
+    }
+
+    #[test]
+    fn evaluates_event_and_window_fields() {
+        let condition =
+            Condition::parse(r#"path ~ "^/api/" && window_1m.requests > 120 && ua.is_empty"#)
+                .unwrap();
+        assert_eq!(condition.windows(), vec![60]);
+
+        let mut state = IpState::new("203.0.113.7".parse().unwrap(), at(0));
+        for s in 0..121 {
+            state.record(&event("/api/cart", None, s / 4));
+        }
+        assert!(condition.matches(&event("/api/cart", Some("-"), 30), &state, None));
+        assert!(!condition.matches(&event("/cart", None, 30), &state, None));
+        assert!(!condition.matches(&event("/api/cart", Some("curl/8.0"), 30), &state, None));
+
+        // Two minutes later the minute window has emptied.
+        state.record(&event("/api/cart", None, 150));
+        assert!(!condition.matches(&event("/api/cart", None, 150), &state, None));
+
+        let check = |src: &str| {
+            Condition::parse(src)
+                .unwrap()
+                .matches(&event("/login", Some("Mozilla/5.0"), 0), &state, None)
+        };
+        assert!(check(r#"method == "POST" && host != "www.example.com" && status < 300"#));
+        assert!(check(r#"referer == "" && !honeypot && robots.fetched == false"#));
+        assert!(check(r#"ua !~ "(?i)curl" && bytes >= 512 && requests == 122"#));
+        assert!(!check(r#"source == "api" || window_10s.errors > 0"#));
+    }
+
+    #[test]
+    fn finds_windows_not_a_multiple_of_ten_seconds() {
+        let condition = Condition::parse("window_15s.requests >= 5").unwrap();
+
+        let mut state = IpState::with_windows("203.0.113.7".parse().unwrap(), &[15, 60], at(0));
+        for s in 0..5 {
+            state.record(&event("/", None, s));
+        }
+        assert!(condition.matches(&event("/", None, 4), &state, None));
+    }
+
+    #[test]
+    fn matching_rules_add_score_reason_and_decision() {
+        let rules = vec![
+            rule("login-flood", r#"path == "/login" && method == "POST""#, 40, None),
+            rule("blocked-agent", r#"ua ~ "BadBot""#, 0, Some(RuleDecision::Block)),
+            rule("office", r#"host == "intranet""#, 0, Some(RuleDecision::Allow)),
+        ];
+        let state = IpState::new("203.0.113.7".parse().unwrap(), at(0));
+        let mut result = ScoreResult {
+            score: 10,
+            reasons: Vec::new(),
+            forced: None,
+        };
+
+        score(&rules, &event("/login", Some("BadBot/1.0"), 0), &state, None, &mut result);
+
+        assert_eq!(result.score, 50);
+        assert_eq!(
+            result.reasons,
+            vec![
+                ScoreReason::Rule { name: "login-flood".into() },
+                ScoreReason::Rule { name: "blocked-agent".into() },
+            ]
+        );
+        assert_eq!(result.forced, Some(Forced::Act));
+    }
+
+    #[test]
+    fn bad_conditions_fail_deserialization() {
+        let parsed: Result<RuleConfig, _> =
+            toml::from_str("name = \"api\"\ncondition = \"path ~ \\\"^/api/\\\" &&\"\nscore = 5\n");
+
+        let err = parsed.unwrap_err().to_string();
+        assert!(err.contains("expected a field, found end of condition at column 19"), "{}", err);
+    }
+
+    #[test]
+    fn names_windows_in_their_largest_unit() {
+        assert_eq!(window_name(10), "window_10s");
+        assert_eq!(window_name(60), "window_1m");
+        assert_eq!(window_name(600), "window_10m");
+        assert_eq!(window_name(7200), "window_2h");
+        assert_eq!(
+            Condition::parse("window_60s.errors > 1").unwrap().windows(),
+            vec![60]
+        );
+    }
+}
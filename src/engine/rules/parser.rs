@@ -0,0 +1,336 @@
+//! Tokenizer and recursive-descent parser for rule conditions.
+//!
+//! ```text
+//! expr       := and ("||" and)*
+//! and        := unary ("&&" unary)*
+//! unary      := "!" unary | "(" expr ")" | comparison
+//! comparison := field (op literal)?
+//! op         := "==" | "!=" | "<" | "<=" | ">" | ">=" | "~" | "!~"
+//! literal    := number | "string" | true | false
+//! ```
+
+use regex::Regex;
+
+use super::{CmpOp, Expr, Field, Kind, Literal};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Num(f64),
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+    Op(CmpOp),
+    Match { negate: bool },
+}
+
+fn describe(token: Option<&Token>) -> String {
+    match token {
+        None => "end of condition".into(),
+        Some(Token::Ident(name)) => format!("`{}`", name),
+        Some(Token::Str(s)) => format!("string {:?}", s),
+        Some(Token::Num(n)) => format!("number {}", n),
+        Some(Token::And) => "`&&`".into(),
+        Some(Token::Or) => "`||`".into(),
+        Some(Token::Not) => "`!`".into(),
+        Some(Token::LParen) => "`(`".into(),
+        Some(Token::RParen) => "`)`".into(),
+        Some(Token::Op(op)) => format!("`{}`", op),
+        Some(Token::Match { negate: false }) => "`~`".into(),
+        Some(Token::Match { negate: true }) => "`!~`".into(),
+    }
+}
+
+/// Split `src` into tokens, each with its (1-based) column.
+fn tokenize(src: &str) -> Result<Vec<(Token, usize)>, String> {
+    let chars: Vec<char> = src.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i + 1;
+        let next = chars.get(i + 1).copied();
+
+        let (token, len) = match (c, next) {
+            (c, _) if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            ('&', Some('&')) => (Token::And, 2),
+            ('|', Some('|')) => (Token::Or, 2),
+            ('!', Some('~')) => (Token::Match { negate: true }, 2),
+            ('!', Some('=')) => (Token::Op(CmpOp::Ne), 2),
+            ('!', _) => (Token::Not, 1),
+            ('=', Some('=')) => (Token::Op(CmpOp::Eq), 2),
+            ('<', Some('=')) => (Token::Op(CmpOp::Le), 2),
+            ('<', _) => (Token::Op(CmpOp::Lt), 1),
+            ('>', Some('=')) => (Token::Op(CmpOp::Ge), 2),
+            ('>', _) => (Token::Op(CmpOp::Gt), 1),
+            ('~', _) => (Token::Match { negate: false }, 1),
+            ('(', _) => (Token::LParen, 1),
+            (')', _) => (Token::RParen, 1),
+            ('"', _) => {
+                let mut value = String::new();
+                let mut j = i + 1;
+                loop {
+                    match chars.get(j) {
+                        None => return Err(format!("unterminated string at column {}", column)),
+                        Some('"') => break,
+                        Some('\\') => {
+                            match chars.get(j + 1) {
+                                Some('"') => value.push('"'),
+                                Some('\\') => value.push('\\'),
+                                // Keep other escapes for the regex engine.
+                                Some(other) => {
+                                    value.push('\\');
+                                    value.push(*other);
+                                }
+                                None => {
+                                    return Err(format!("unterminated string at column {}", column))
+                                }
+                            }
+                            j += 2;
+                        }
+                        Some(c) => {
+                            value.push(*c);
+                            j += 1;
+                        }
+                    }
+                }
+                (Token::Str(value), j + 1 - i)
+            }
+            (c, _) if c.is_ascii_digit() => {
+                let len = chars[i..]
+                    .iter()
+                    .take_while(|c| c.is_ascii_digit() || **c == '.')
+                    .count();
+                let raw: String = chars[i..i + len].iter().collect();
+                let value = raw
+                    .parse()
+                    .map_err(|_| format!("invalid number {:?} at column {}", raw, column))?;
+                (Token::Num(value), len)
+            }
+            (c, _) if c.is_ascii_alphabetic() || c == '_' => {
+                let len = chars[i..]
+                    .iter()
+                    .take_while(|c| c.is_ascii_alphanumeric() || **c == '_' || **c == '.')
+                    .count();
+                (Token::Ident(chars[i..i + len].iter().collect()), len)
+            }
+            (c, _) => return Err(format!("unexpected {:?} at column {}", c, column)),
+        };
+
+        tokens.push((token, column));
+        i += len;
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(t, _)| t)
+    }
+
+    fn column(&self) -> usize {
+        self.tokens.get(self.pos).map_or(self.end, |(_, c)| *c)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).map(|(t, _)| t.clone());
+        self.pos += 1;
+        token
+    }
+
+    fn unexpected<T>(&self, expected: &str) -> Result<T, String> {
+        Err(format!(
+            "expected {}, found {} at column {}",
+            expected,
+            describe(self.peek()),
+            self.column()
+        ))
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        let mut left = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            left = Expr::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut left = self.unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            left = Expr::And(Box::new(left), Box::new(self.unary()?));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        match self.peek() {
+            Some(Token::Not) => {
+                self.next();
+                Ok(Expr::Not(Box::new(self.unary()?)))
+            }
+            Some(Token::LParen) => {
+                self.next();
+                let inner = self.expr()?;
+                if self.peek() != Some(&Token::RParen) {
+                    return self.unexpected("`)`");
+                }
+                self.next();
+                Ok(inner)
+            }
+            _ => self.comparison(),
+        }
+    }
+
+    fn comparison(&mut self) -> Result<Expr, String> {
+        let column = self.column();
+        let Some(Token::Ident(name)) = self.peek().cloned() else {
+            return self.unexpected("a field");
+        };
+        self.next();
+        let field = Field::parse(&name)
+            .map_err(|e| format!("{} at column {}", e, column))?;
+
+        match self.peek().cloned() {
+            Some(Token::Op(op)) => {
+                self.next();
+                let literal = self.literal()?;
+                check_comparison(field, op, &literal)
+                    .map_err(|e| format!("{} at column {}", e, column))?;
+                Ok(Expr::Compare(field, op, literal))
+            }
+            Some(Token::Match { negate }) => {
+                self.next();
+                if field.kind() != Kind::Str {
+                    return Err(format!(
+                        "`{}` is not text and cannot be matched with `~` at column {}",
+                        name, column
+                    ));
+                }
+                let Some(Token::Str(pattern)) = self.peek().cloned() else {
+                    return self.unexpected("a \"regex\"");
+                };
+                let pattern_column = self.column();
+                self.next();
+                let regex = Regex::new(&pattern)
+                    .map_err(|e| format!("invalid regex at column {}: {}", pattern_column, e))?;
+                Ok(Expr::Matches(field, regex, negate))
+            }
+            _ if field.kind() == Kind::Bool => Ok(Expr::Flag(field)),
+            _ => Err(format!(
+                "`{}` is not a flag; compare it with a value at column {}",
+                name, column
+            )),
+        }
+    }
+
+    fn literal(&mut self) -> Result<Literal, String> {
+        match self.peek().cloned() {
+            Some(Token::Num(n)) => {
+                self.next();
+                Ok(Literal::Num(n))
+            }
+            Some(Token::Str(s)) => {
+                self.next();
+                Ok(Literal::Str(s))
+            }
+            Some(Token::Ident(word)) if word == "true" || word == "false" => {
+                self.next();
+                Ok(Literal::Bool(word == "true"))
+            }
+            _ => self.unexpected("a value"),
+        }
+    }
+}
+
+fn check_comparison(field: Field, op: CmpOp, literal: &Literal) -> Result<(), String> {
+    let kind = match literal {
+        Literal::Num(_) => Kind::Num,
+        Literal::Str(_) => Kind::Str,
+        Literal::Bool(_) => Kind::Bool,
+    };
+    if kind != field.kind() {
+        return Err(format!(
+            "`{}` is {} but is compared with {}",
+            field,
+            field.kind(),
+            kind
+        ));
+    }
+    if kind != Kind::Num && !matches!(op, CmpOp::Eq | CmpOp::Ne) {
+        return Err(format!("`{}` only supports `==` and `!=`", field));
+    }
+    Ok(())
+}
+
+pub fn parse(src: &str) -> Result<Expr, String> {
+    let tokens = tokenize(src)?;
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        end: src.chars().count() + 1,
+    };
+
+    let expr = parser.expr()?;
+    if parser.peek().is_some() {
+        return parser.unexpected("`&&`, `||` or end of condition");
+    }
+    Ok(expr)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_precedence_and_grouping() {
+        let expr = parse(r#"!ua.is_empty && (status >= 400 || path ~ "^/api/") || honeypot"#)
+            .unwrap();
+
+        let Expr::Or(left, right) = expr else { panic!("expected ||") };
+        assert!(matches!(*right, Expr::Flag(Field::Honeypot)));
+        let Expr::And(not, group) = *left else { panic!("expected &&") };
+        assert!(matches!(*not, Expr::Not(_)));
+        assert!(matches!(*group, Expr::Or(_, _)));
+    }
+
+    #[test]
+    fn reports_errors_with_columns() {
+        let err = |src: &str| parse(src).unwrap_err();
+
+        assert_eq!(err("status >"), "expected a value, found end of condition at column 9");
+        assert_eq!(err(r#"status == "200""#), "`status` is a number but is compared with text at column 1");
+        assert_eq!(err("path"), "`path` is not a flag; compare it with a value at column 1");
+        assert_eq!(err("window_5d.requests > 1"), "unknown field `window_5d.requests` at column 1");
+        assert_eq!(err("pth == \"/\""), "unknown field `pth` at column 1");
+        assert_eq!(err("(honeypot"), "expected `)`, found end of condition at column 10");
+        assert_eq!(err("honeypot honeypot"), "expected `&&`, `||` or end of condition, found `honeypot` at column 10");
+        assert!(err(r#"path ~ "(""#).starts_with("invalid regex at column 8"));
+        assert_eq!(err("status > 1 # comment"), "unexpected '#' at column 12");
+    }
+
+    #[test]
+    fn unescapes_quotes_but_keeps_regex_escapes() {
+        let Expr::Matches(_, regex, false) = parse(r#"ua ~ "\"x\"\.\d""#).unwrap() else {
+            panic!("expected ~");
+        };
+        assert_eq!(regex.as_str(), r#""x"\.\d"#);
+    }
+}
@@ -1,53 +1,179 @@
 use crate::config::schema::ScoringConfig;
+use crate::engine::profile::Profile;
 use crate::model::ip_state::IpState;
+use crate::model::paths::PathTracker;
+use crate::model::window::WindowCounter;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ScoreResult {
     pub score: u32,
     pub reasons: Vec<ScoreReason>,
+    /// Overrides the threshold when set.
+    pub forced: Option<Forced>,
 }
 
-#[derive(Debug, Clone)]
+/// A verdict that does not depend on the score.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Forced {
+    /// Act whatever the score (a honeypot hit with `block = true`).
+    Act,
+    /// Never act (a verified search-engine crawler). Wins over `Act`.
+    Allow,
+}
+
+impl ScoreResult {
+    pub fn force(&mut self, forced: Forced) {
+        if self.forced != Some(Forced::Allow) {
+            self.forced = Some(forced);
+        }
+    }
+}
+
+/// Why a client scored; counts are taken over the window that scored
+/// highest.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ScoreReason {
-    HighRate { count: u64 },
-    HighErrorRate { errors: u64 },
+    HighRate { requests: u64, window_secs: u64 },
+    HighErrorRate { errors: u64, window_secs: u64 },
+    /// Missing, empty or `-` user agent.
+    EmptyUserAgent,
+    ScrapingLibrary { signature: String },
+    AiCrawler { signature: String },
+    /// One IP presenting many distinct user agents.
+    UserAgentRotation { distinct: u64 },
+    /// Mostly never-before-seen paths: a breadth-first crawl.
+    BreadthCrawl { distinct: u64, requests: u64 },
+    /// Fetched a sitemap, then many distinct paths.
+    SitemapWalk { distinct: u64 },
+    /// Consecutive numeric IDs under one path template.
+    IdEnumeration { run: u32 },
+    /// Requested a trap path.
+    Honeypot { path: String },
+    /// A robots.txt reader (or an agent robots.txt names) fetched a path
+    /// it disallows.
+    RobotsDisallowed { path: String },
+    /// Repeatedly requested faster than the robots.txt `Crawl-delay`.
+    CrawlDelayIgnored { delay_ms: u64, violations: u32 },
+    /// Claims a search-engine crawler from inside its published ranges.
+    VerifiedCrawler { name: String },
+    /// Claims a search-engine crawler from outside its published ranges.
+    SpoofedCrawler { claimed: String },
+    /// A `[[rules]]` entry matched.
+    Rule { name: String },
 }
 
-pub fn score_ip(state: &IpState, cfg: &ScoringConfig) -> ScoreResult {
+/// Busiest window relative to `limit_per_second`:
+/// (fraction of the limit, events, window length).
+fn busiest<'a>(
+    counters: impl Iterator<Item = &'a WindowCounter>,
+    state: &IpState,
+    limit_per_second: f64,
+) -> Option<(f64, u64, u64)> {
+    counters
+        .map(|c| {
+            let load = c.per_second(state.last_event) / limit_per_second;
+            (load, c.count(state.last_event), c.window_secs())
+        })
+        .max_by(|a, b| a.0.total_cmp(&b.0))
+}
+
+fn weighted(load: f64, weight: u32) -> u32 {
+    (load.min(1.0) * weight as f64).round() as u32
+}
+
+/// Score the client's state as seen by `profile`: its weights, and the
+/// rate windows and paths the client has in that profile.
+pub fn score_ip(state: &IpState, profile: &Profile, cfg: &ScoringConfig) -> ScoreResult {
     let mut score: u32 = 0;
     let mut reasons = Vec::new();
+    let limits = &cfg.rates;
+    let weights = profile.weights;
+    let windows = state.windows_for(profile.name);
 
     // Request rate scoring
-    if state.request_count > 0 {
-        let rate_score = (state.request_count as u32)
-            .min(cfg.weights.rate);
-        score += rate_score;
-
-        reasons.push(ScoreReason::HighRate {
-            count: state.request_count,
-        });
+    let requests = windows.iter().map(|w| &w.requests);
+    if let Some((load, count, window_secs)) =
+        busiest(requests, state, limits.max_requests_per_second)
+    {
+        let rate_score = weighted(load, weights.rate);
+        if rate_score > 0 {
+            score += rate_score;
+            reasons.push(ScoreReason::HighRate {
+                requests: count,
+                window_secs,
+            });
+        }
     }
 
     // Error scoring
-    if state.error_count > 0 {
-        let error_score = (state.error_count as u32)
-            .min(cfg.weights.error);
-        score += error_score;
+    let errors = windows.iter().map(|w| &w.errors);
+    if let Some((load, count, window_secs)) =
+        busiest(errors, state, limits.max_errors_per_minute / 60.0)
+    {
+        let error_score = weighted(load, weights.error);
+        if error_score > 0 {
+            score += error_score;
+            reasons.push(ScoreReason::HighErrorRate {
+                errors: count,
+                window_secs,
+            });
+        }
+    }
+
+    // Crawl-pattern scoring
+    let path_reasons = state
+        .paths_for(profile.name)
+        .map_or_else(Vec::new, |paths| crawl_reasons(paths, cfg));
+    if !path_reasons.is_empty() {
+        score += weights.path_entropy;
+        reasons.extend(path_reasons);
+    }
+
+    ScoreResult {
+        score,
+        reasons,
+        forced: None,
+    }
+}
+
+fn crawl_reasons(paths: &PathTracker, cfg: &ScoringConfig) -> Vec<ScoreReason> {
+    let limits = &cfg.paths;
+    let mut reasons = Vec::new();
 
-        reasons.push(ScoreReason::HighErrorRate {
-            errors: state.error_count,
+    if paths.total() >= limits.min_requests {
+        if paths.unique_ratio() >= limits.unique_ratio {
+            reasons.push(ScoreReason::BreadthCrawl {
+                distinct: paths.distinct(),
+                requests: paths.total(),
+            });
+        }
+        if paths.fetched_sitemap() && paths.distinct() >= limits.min_requests {
+            reasons.push(ScoreReason::SitemapWalk {
+                distinct: paths.distinct(),
+            });
+        }
+    }
+
+    if paths.longest_run() >= limits.enumeration_run {
+        reasons.push(ScoreReason::IdEnumeration {
+            run: paths.longest_run(),
         });
     }
 
-    ScoreResult { score, reasons }
+    reasons
 }
 
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::schema::{ScoringConfig, ScoringWeights};
+    use crate::config::schema::{
+        CrawlerConfig, HoneypotConfig, PathConfig, RateLimits, RobotsConfig, ScoringConfig,
+        ScoringWeights, UserAgentConfig,
+    };
+    use crate::model::clock::at;
     use crate::model::ip_state::IpState;
+    use crate::parser::ParsedEvent;
 
 
     fn test_config() -> ScoringConfig {
@@ -56,56 +182,251 @@ mod tests {
             weights: ScoringWeights {
                 rate: 40,
                 error: 30,
-                user_agent: 20,     // unused in phase 1
-                path_entropy: 10,   // unused in phase 1
+                user_agent: 20,
+                path_entropy: 10,
+            },
+            rates: RateLimits {
+                windows_seconds: vec![10, 600],
+                max_requests_per_second: 2.0,
+                max_errors_per_minute: 60.0,
             },
+            user_agent: UserAgentConfig::default(),
+            paths: PathConfig::default(),
+            honeypot: HoneypotConfig::default(),
+            robots: RobotsConfig::default(),
+            crawlers: CrawlerConfig::default(),
+            profiles: Vec::new(),
+            tiers: Vec::new(),
+            offence_memory_seconds: 86400,
         }
     }
 
+    fn score(state: &IpState) -> ScoreResult {
+        let cfg = test_config();
+        score_ip(state, &Profile::global(&cfg), &cfg)
+    }
+
     fn test_state() -> IpState {
-        IpState::new("1.2.3.4".to_string())
+        let windows = test_config().rates.windows_seconds;
+        IpState::with_windows("1.2.3.4".parse().unwrap(), &windows, at(0))
     }
 
 
     #[test]
     fn scores_request_rate() {
         let mut state = test_state();
-        state.request_count = 50;
+        // 10 requests in 10s: 1 req/s, half the limit.
+        for s in 0..10 {
+            state.record_request(at(s));
+        }
 
-        let result = score_ip(&state, &test_config());
+        let result = score(&state);
 
-        assert!(result.score > 0);
-        assert_eq!(result.reasons.len(), 1);
+        assert_eq!(result.score, 20);
+        assert_eq!(
+            result.reasons,
+            vec![ScoreReason::HighRate { requests: 10, window_secs: 10 }]
+        );
+    }
+
+    #[test]
+    fn reasons_name_the_configured_window() {
+        let mut state = IpState::with_windows("1.2.3.4".parse().unwrap(), &[15], at(0));
+        for s in 0..16 {
+            state.record_request(at(s));
+            state.record_request(at(s));
+        }
+
+        let result = score(&state);
+
+        assert_eq!(result.score, 40);
+        assert_eq!(
+            result.reasons,
+            vec![ScoreReason::HighRate { requests: 32, window_secs: 15 }]
+        );
+    }
+
+    #[test]
+    fn rate_score_is_capped_at_weight() {
+        let mut state = test_state();
+        for _ in 0..100 {
+            state.record_request(at(0));
+        }
+
+        let result = score(&state);
+
+        assert_eq!(result.score, 40);
     }
 
     #[test]
     fn scores_error_rate() {
         let mut state = test_state();
-        state.error_count = 10;
+        for s in 0..5 {
+            state.record_error(at(s));
+        }
 
-        let result = score_ip(&state, &test_config());
+        let result = score(&state);
 
-        assert!(result.score > 0);
+        assert_eq!(result.score, 15);
         assert_eq!(result.reasons.len(), 1);
     }
 
     #[test]
     fn scores_multiple_signals() {
         let mut state = test_state();
-        state.request_count = 30;
-        state.error_count = 10;
+        for s in 0..10 {
+            state.record_request(at(s));
+            state.record_error(at(s));
+        }
 
-        let result = score_ip(&state, &test_config());
+        let result = score(&state);
 
-        assert!(result.score > 0);
+        assert_eq!(result.score, 20 + 30);
         assert_eq!(result.reasons.len(), 2);
     }
 
+    #[test]
+    fn long_lived_slow_client_is_not_scored_as_a_burst() {
+        let mut state = test_state();
+        // One request a minute for ten hours: a large lifetime total,
+        // a negligible rate.
+        for m in 0..600 {
+            state.record_request(at(m * 60));
+        }
+
+        let result = score(&state);
+
+        // Only the latest request counts: 0.1 req/s over the last 10s.
+        assert_eq!(state.request_count, 600);
+        assert_eq!(result.score, 2);
+    }
+
+    #[test]
+    fn burst_scores_over_the_short_window() {
+        let mut state = test_state();
+        for s in 0..300 {
+            state.record_request(at(s * 2));
+        }
+        // 42 requests within the last 10 seconds.
+        for _ in 0..40 {
+            state.record_request(at(605));
+        }
+
+        let result = score(&state);
+
+        assert_eq!(result.score, 40);
+        assert_eq!(
+            result.reasons,
+            vec![ScoreReason::HighRate { requests: 42, window_secs: 10 }]
+        );
+    }
+
+    #[test]
+    fn scores_breadth_first_crawl() {
+        let sections = ["news", "shop", "blog", "help", "docs"];
+        let pages = ["one", "two", "three", "four"];
+
+        // 40 requests over 9 paths: a human browsing.
+        let mut state = test_state();
+        for _ in 0..4 {
+            for page in pages.iter().chain(&sections) {
+                state.paths.record(&format!("/{}", page));
+            }
+        }
+        assert!(score(&state).reasons.is_empty());
+
+        let mut state = test_state();
+        for section in sections {
+            for page in pages {
+                state.paths.record(&format!("/{}/{}", section, page));
+            }
+        }
+
+        let result = score(&state);
+
+        assert_eq!(result.score, 10);
+        assert_eq!(
+            result.reasons,
+            vec![ScoreReason::BreadthCrawl { distinct: 20, requests: 20 }]
+        );
+    }
+
+    #[test]
+    fn scores_sitemap_walk_and_enumeration_once() {
+        let mut state = test_state();
+        state.paths.record("/sitemap.xml");
+        for id in 1..=25 {
+            state.paths.record(&format!("/product/{}", id));
+            state.paths.record(&format!("/product/{}", id));
+        }
+
+        let result = score(&state);
+
+        // Both reasons, but the signal is worth its weight only once.
+        assert_eq!(result.score, 10);
+        assert_eq!(
+            result.reasons,
+            vec![
+                ScoreReason::SitemapWalk { distinct: 26 },
+                ScoreReason::IdEnumeration { run: 25 },
+            ]
+        );
+    }
+
+    #[test]
+    fn profiles_score_their_own_windows_and_weights() {
+        let cfg = test_config();
+        let weights = ScoringWeights { rate: 80, error: 20, user_agent: 0, path_entropy: 0 };
+        let api = Profile { name: Some("api"), weights: &weights, ..Profile::global(&cfg) };
+
+        let event = |path: &str, secs: u64| ParsedEvent::test("1.2.3.4", path, at(secs));
+
+        // 10 API calls in 10s and one page view.
+        let mut state = test_state();
+        for s in 0..10 {
+            state.record_in(&event("/api/items", s), Some("api"));
+        }
+        state.record(&event("/", 9));
+
+        // 1 req/s: half the limit, at the API profile's rate weight.
+        assert_eq!(score_ip(&state, &api, &cfg).score, 40);
+        // The API calls do not count against page views.
+        assert_eq!(
+            score(&state).reasons,
+            vec![ScoreReason::HighRate { requests: 1, window_secs: 10 }]
+        );
+    }
+
+    #[test]
+    fn profiles_score_their_own_crawl_patterns() {
+        let cfg = test_config();
+        let api = Profile { name: Some("api"), ..Profile::global(&cfg) };
+
+        // Walking IDs through the API, one page view.
+        let mut state = test_state();
+        for id in 1..=25 {
+            let event = ParsedEvent::test("1.2.3.4", &format!("/api/items/{}", id), at(id * 60));
+            state.record_in(&event, Some("api"));
+        }
+        state.record(&ParsedEvent::test("1.2.3.4", "/", at(1500)));
+
+        assert!(score_ip(&state, &api, &cfg)
+            .reasons
+            .contains(&ScoreReason::IdEnumeration { run: 25 }));
+        assert!(!score(&state)
+            .reasons
+            .iter()
+            .any(|r| matches!(r, ScoreReason::IdEnumeration { .. })));
+        assert!(score_ip(&state, &Profile { name: Some("login"), ..api }, &cfg)
+            .reasons
+            .is_empty());
+    }
+
     #[test]
     fn zero_activity_scores_zero() {
         let state = test_state();
 
-        let result = score_ip(&state, &test_config());
+        let result = score(&state);
 
         assert_eq!(result.score, 0);
         assert!(result.reasons.is_empty());
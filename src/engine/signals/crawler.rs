@@ -0,0 +1,250 @@
+use std::fs;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use crate::config::schema::{CrawlerConfig, CrawlerSource};
+use crate::net::Cidr;
+
+/// Google, Bing and Apple publish their ranges in this shape
+/// (`googlebot.json`, `bingbot.json`, `applebot.json`).
+#[derive(Debug, Deserialize)]
+struct PublishedRanges {
+    prefixes: Vec<PublishedPrefix>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PublishedPrefix {
+    #[serde(rename = "ipv4Prefix")]
+    ipv4: Option<String>,
+    #[serde(rename = "ipv6Prefix")]
+    ipv6: Option<String>,
+}
+
+/// Parse a range file: the published JSON format, or one CIDR per line
+/// with `#` comments.
+fn parse_ranges(text: &str) -> anyhow::Result<Vec<Cidr>> {
+    if text.trim_start().starts_with('{') {
+        let published: PublishedRanges =
+            serde_json::from_str(text).context("invalid JSON range file")?;
+        return published
+            .prefixes
+            .into_iter()
+            .flat_map(|p| p.ipv4.into_iter().chain(p.ipv6))
+            .map(|raw| raw.parse::<Cidr>().map_err(anyhow::Error::from))
+            .collect();
+    }
+
+    text.lines()
+        .map(|line| line.split('#').next().unwrap_or("").trim())
+        .filter(|line| !line.is_empty())
+        .map(|line| line.parse::<Cidr>().map_err(anyhow::Error::from))
+        .collect()
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn load(path: &Path) -> anyhow::Result<Vec<Cidr>> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("reading crawler ranges {}", path.display()))?;
+    parse_ranges(&text).with_context(|| format!("parsing crawler ranges {}", path.display()))
+}
+
+#[derive(Debug)]
+struct Crawler {
+    name: String,
+    /// Lowercase user-agent tokens that claim to be this crawler.
+    tokens: Vec<String>,
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    ranges: Vec<Cidr>,
+}
+
+impl Crawler {
+    fn new(source: &CrawlerSource) -> anyhow::Result<Self> {
+        Ok(Self {
+            name: source.name.clone(),
+            tokens: source.user_agents.iter().map(|t| t.to_ascii_lowercase()).collect(),
+            path: source.ranges.clone(),
+            modified: modified(&source.ranges),
+            ranges: load(&source.ranges)?,
+        })
+    }
+
+    fn claimed_by(&self, ua: &str) -> bool {
+        self.tokens.iter().any(|token| ua.contains(token.as_str()))
+    }
+
+    fn owns(&self, ip: &IpAddr) -> bool {
+        self.ranges.iter().any(|range| range.contains(ip))
+    }
+}
+
+/// Outcome for a client whose user agent claims a known crawler.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CrawlerCheck {
+    /// The address is in the crawler's published ranges.
+    Verified(String),
+    /// It is not: someone borrowing the crawler's name.
+    Spoofed(String),
+}
+
+/// Checks claimed search-engine crawlers against locally stored range
+/// files, built from `[scoring.crawlers]`. No DNS or network lookups.
+#[derive(Debug)]
+pub struct CrawlerVerifier {
+    crawlers: Vec<Crawler>,
+    check_every: Duration,
+    last_check: Instant,
+}
+
+impl CrawlerVerifier {
+    pub fn new(cfg: &CrawlerConfig) -> anyhow::Result<Self> {
+        Ok(Self {
+            crawlers: cfg.verify.iter().map(Crawler::new).collect::<anyhow::Result<_>>()?,
+            check_every: Duration::from_secs(cfg.reload_check_seconds),
+            last_check: Instant::now(),
+        })
+    }
+
+    /// Crawlers configured, and the ranges loaded for all of them.
+    pub fn counts(&self) -> (usize, usize) {
+        let ranges = self.crawlers.iter().map(|c| c.ranges.len()).sum();
+        (self.crawlers.len(), ranges)
+    }
+
+    pub fn check(&self, ip: &IpAddr, ua: Option<&str>) -> Option<CrawlerCheck> {
+        let ua = ua?.to_ascii_lowercase();
+        let mut claimed = self.crawlers.iter().filter(|c| c.claimed_by(&ua)).peekable();
+        let first = claimed.peek()?.name.clone();
+
+        match claimed.find(|c| c.owns(ip)) {
+            Some(crawler) => Some(CrawlerCheck::Verified(crawler.name.clone())),
+            None => Some(CrawlerCheck::Spoofed(first)),
+        }
+    }
+
+    /// Re-read range files whose modification time changed, at most once
+    /// per `reload_check_seconds`. A file that fails to load keeps its
+    /// previous ranges.
+    pub fn reload_changed(&mut self) {
+        if self.last_check.elapsed() < self.check_every {
+            return;
+        }
+        self.last_check = Instant::now();
+
+        for crawler in &mut self.crawlers {
+            let current = modified(&crawler.path);
+            if current == crawler.modified {
+                continue;
+            }
+            crawler.modified = current;
+
+            match load(&crawler.path) {
+                Ok(ranges) => {
+                    log::info!(
+                        "Reloaded {} ranges for {} from {}",
+                        ranges.len(),
+                        crawler.name,
+                        crawler.path.display()
+                    );
+                    crawler.ranges = ranges;
+                }
+                Err(e) => log::warn!("Keeping previous {} ranges: {:#}", crawler.name, e),
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GOOGLEBOT: &str = r#"{
+        "creationTime": "2024-10-01T23:00:00.000000",
+        "prefixes": [
+            {"ipv6Prefix": "2001:4860:4801:10::/64"},
+            {"ipv4Prefix": "66.249.64.0/27"}
+        ]
+    }"#;
+
+    fn source(name: &str, tokens: &[&str], ranges: &Path) -> CrawlerSource {
+        CrawlerSource {
+            name: name.into(),
+            user_agents: tokens.iter().map(|t| t.to_string()).collect(),
+            ranges: ranges.to_path_buf(),
+        }
+    }
+
+    #[test]
+    fn parses_published_json_and_plain_lists() {
+        assert_eq!(parse_ranges(GOOGLEBOT).unwrap().len(), 2);
+        assert_eq!(
+            parse_ranges("# bingbot\n157.55.39.0/24\n\n40.77.167.0/24 # more\n").unwrap(),
+            vec!["157.55.39.0/24".parse().unwrap(), "40.77.167.0/24".parse().unwrap()]
+        );
+        assert!(parse_ranges("not-a-network\n").is_err());
+    }
+
+    #[test]
+    fn verifies_or_flags_claimed_crawlers() {
+        let dir = tempfile::tempdir().unwrap();
+        let google = dir.path().join("googlebot.json");
+        fs::write(&google, GOOGLEBOT).unwrap();
+
+        let verifier = CrawlerVerifier::new(&CrawlerConfig {
+            verify: vec![source("Googlebot", &["Googlebot"], &google)],
+            ..CrawlerConfig::default()
+        })
+        .unwrap();
+        let ua = Some("Mozilla/5.0 (compatible; Googlebot/2.1; +http://www.google.com/bot.html)");
+
+        assert_eq!(
+            verifier.check(&"66.249.64.5".parse().unwrap(), ua),
+            Some(CrawlerCheck::Verified("Googlebot".into()))
+        );
+        assert_eq!(
+            verifier.check(&"2001:4860:4801:10::1".parse().unwrap(), ua),
+            Some(CrawlerCheck::Verified("Googlebot".into()))
+        );
+        assert_eq!(
+            verifier.check(&"203.0.113.7".parse().unwrap(), ua),
+            Some(CrawlerCheck::Spoofed("Googlebot".into()))
+        );
+        assert_eq!(verifier.check(&"203.0.113.7".parse().unwrap(), Some("curl/8.5.0")), None);
+    }
+
+    #[test]
+    fn reloads_changed_files_and_keeps_ranges_on_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let bing = dir.path().join("bingbot.txt");
+        fs::write(&bing, "157.55.39.0/24\n").unwrap();
+
+        let mut verifier = CrawlerVerifier::new(&CrawlerConfig {
+            verify: vec![source("bingbot", &["bingbot"], &bing)],
+            reload_check_seconds: 0,
+            ..CrawlerConfig::default()
+        })
+        .unwrap();
+        let ip: IpAddr = "40.77.167.1".parse().unwrap();
+        let claim = Some("Mozilla/5.0 (compatible; bingbot/2.0)");
+        assert_eq!(verifier.check(&ip, claim), Some(CrawlerCheck::Spoofed("bingbot".into())));
+
+        // Force a different mtime even on coarse-grained filesystems.
+        verifier.crawlers[0].modified = None;
+        fs::write(&bing, "157.55.39.0/24\n40.77.167.0/24\n").unwrap();
+        verifier.reload_changed();
+        assert_eq!(verifier.check(&ip, claim), Some(CrawlerCheck::Verified("bingbot".into())));
+
+        verifier.crawlers[0].modified = None;
+        fs::write(&bing, "garbage\n").unwrap();
+        verifier.reload_changed();
+        assert_eq!(verifier.counts(), (1, 2));
+    }
+}
@@ -0,0 +1,63 @@
+use crate::config::schema::HoneypotConfig;
+
+/// Trap paths no legitimate visitor requests: links hidden from humans,
+/// `Disallow`ed paths, software the site does not run.
+#[derive(Debug)]
+pub struct HoneypotSignal {
+    exact: Vec<String>,
+    prefixes: Vec<String>,
+}
+
+impl HoneypotSignal {
+    pub fn new(cfg: &HoneypotConfig) -> Self {
+        let (prefixes, exact): (Vec<_>, Vec<_>) =
+            cfg.paths.iter().partition(|path| path.ends_with('*'));
+
+        Self {
+            exact: exact.into_iter().cloned().collect(),
+            prefixes: prefixes
+                .into_iter()
+                .map(|path| path.trim_end_matches('*').to_string())
+                .collect(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.exact.len() + self.prefixes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether `path` (query string ignored) is a trap.
+    pub fn is_trap(&self, path: &str) -> bool {
+        let path = path.split('?').next().unwrap_or(path);
+
+        self.exact.iter().any(|trap| trap == path)
+            || self.prefixes.iter().any(|prefix| path.starts_with(prefix.as_str()))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_exact_paths_and_prefixes() {
+        let signal = HoneypotSignal::new(&HoneypotConfig {
+            paths: vec!["/wp-login.php".into(), "/trap/*".into()],
+            ..HoneypotConfig::default()
+        });
+
+        assert_eq!(signal.len(), 2);
+        assert!(signal.is_trap("/wp-login.php"));
+        assert!(signal.is_trap("/wp-login.php?redirect_to=/wp-admin"));
+        assert!(signal.is_trap("/trap/"));
+        assert!(signal.is_trap("/trap/deep/link.html"));
+        assert!(!signal.is_trap("/wp-login.php.bak"));
+        assert!(!signal.is_trap("/trap"));
+        assert!(!signal.is_trap("/"));
+    }
+}
@@ -0,0 +1,104 @@
+pub mod crawler;
+pub mod honeypot;
+pub mod robots;
+pub mod user_agent;
+
+use crate::config::schema::AargalConfig;
+use crate::engine::profile::Profile;
+use crate::engine::scoring::{Forced, ScoreReason, ScoreResult};
+use crate::model::ip_state::IpState;
+use crate::parser::ParsedEvent;
+
+use crawler::{CrawlerCheck, CrawlerVerifier};
+use honeypot::HoneypotSignal;
+use robots::RobotsSignal;
+use user_agent::UserAgentSignal;
+
+/// Request-level signals, built once at startup from the config.
+///
+/// Unlike the rate signals in [`score_ip`](crate::engine::scoring::score_ip),
+/// these look at the request being scored (and whatever its client's
+/// state remembers), and need compiled lookup tables.
+#[derive(Debug)]
+pub struct Signals {
+    pub user_agent: UserAgentSignal,
+    pub honeypot: HoneypotSignal,
+    pub robots: RobotsSignal,
+    pub crawlers: CrawlerVerifier,
+}
+
+impl Signals {
+    pub fn new(config: &AargalConfig) -> anyhow::Result<Self> {
+        Ok(Self {
+            user_agent: UserAgentSignal::new(&config.scoring.user_agent)?,
+            honeypot: HoneypotSignal::new(&config.scoring.honeypot),
+            robots: RobotsSignal::new(&config.scoring.robots)?,
+            crawlers: CrawlerVerifier::new(&config.scoring.crawlers)?,
+        })
+    }
+
+    /// Pick up changed signal files (crawler ranges). Cheap enough to
+    /// call on every loop iteration.
+    pub fn reload_changed(&mut self) {
+        self.crawlers.reload_changed();
+    }
+
+    /// Record on the client's state what `event` revealed, before it is
+    /// scored.
+    pub fn observe(&self, event: &ParsedEvent, state: &mut IpState) {
+        if state.honeypot.is_none() && self.honeypot.is_trap(&event.path) {
+            state.honeypot = Some(event.path.clone());
+        }
+        self.robots.observe(event, &mut state.robots);
+    }
+
+    /// Add the request-level signals for `event` to `score`. Each signal
+    /// contributes at most its weight, however many reasons it finds.
+    pub fn score(
+        &self,
+        event: &ParsedEvent,
+        state: &IpState,
+        config: &AargalConfig,
+        profile: &Profile,
+        score: &mut ScoreResult,
+    ) {
+        let weights = profile.weights;
+
+        add(score, weights.user_agent, self.user_agent.reasons(event, state));
+
+        if let Some(path) = &state.honeypot {
+            let honeypot = &config.scoring.honeypot;
+            score.score += honeypot.score;
+            score.reasons.push(ScoreReason::Honeypot { path: path.clone() });
+            if honeypot.block {
+                score.force(Forced::Act);
+            }
+        }
+
+        let robots = self.robots.reasons(&state.robots);
+        if !robots.is_empty() {
+            score.score += config.scoring.robots.score;
+            score.reasons.extend(robots);
+        }
+
+        match self.crawlers.check(&event.ip, event.user_agent.as_deref()) {
+            Some(CrawlerCheck::Verified(name)) => {
+                score.reasons.push(ScoreReason::VerifiedCrawler { name });
+                score.force(Forced::Allow);
+            }
+            Some(CrawlerCheck::Spoofed(claimed)) => {
+                score.score += config.scoring.crawlers.score;
+                score.reasons.push(ScoreReason::SpoofedCrawler { claimed });
+            }
+            None => {}
+        }
+    }
+}
+
+fn add(score: &mut ScoreResult, weight: u32, reasons: Vec<ScoreReason>) {
+    if reasons.is_empty() || weight == 0 {
+        return;
+    }
+    score.score += weight;
+    score.reasons.extend(reasons);
+}
@@ -0,0 +1,406 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::time::Duration;
+
+use anyhow::Context;
+
+use crate::config::schema::RobotsConfig;
+use crate::engine::scoring::ScoreReason;
+use crate::model::ip_state::RobotsTrack;
+use crate::parser::ParsedEvent;
+
+/// Requests faster than the crawl delay before a client is reported;
+/// one short gap is more often a retry than a policy.
+pub const CRAWL_DELAY_VIOLATIONS: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Verdict {
+    Allow,
+    Disallow,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Rule {
+    pattern: String,
+    verdict: Verdict,
+}
+
+/// Rules for one or more `User-agent` lines.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Group {
+    agents: Vec<String>,
+    rules: Vec<Rule>,
+    crawl_delay: Option<Duration>,
+}
+
+impl Group {
+    /// RFC 9309: the longest matching rule wins, `Allow` on a tie.
+    pub fn allows(&self, path: &str) -> bool {
+        let best = self
+            .rules
+            .iter()
+            .filter(|rule| matches(&rule.pattern, path))
+            .max_by_key(|rule| (rule.pattern.len(), rule.verdict == Verdict::Allow));
+
+        best.is_none_or(|rule| rule.verdict == Verdict::Allow)
+    }
+
+    pub fn crawl_delay(&self) -> Option<Duration> {
+        self.crawl_delay
+    }
+}
+
+/// Whether robots.txt `pattern` (`*` wildcards, `$` end anchor) matches
+/// the start of `path`.
+fn matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(p) => (p, true),
+        None => (pattern, false),
+    };
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or("");
+    let Some(mut rest) = path.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    for (i, part) in parts.iter().enumerate() {
+        let last = i + 1 == parts.len();
+        if last && anchored {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+
+    !anchored || rest.is_empty()
+}
+
+/// A parsed robots.txt.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RobotsTxt {
+    groups: Vec<Group>,
+}
+
+impl RobotsTxt {
+    pub fn parse(text: &str) -> Self {
+        let mut groups: Vec<Group> = Vec::new();
+        let mut current = Group::default();
+        let mut in_agents = false;
+
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+
+            match key.trim().to_ascii_lowercase().as_str() {
+                "user-agent" => {
+                    // A user-agent line after rules starts a new group.
+                    if !in_agents && !current.agents.is_empty() {
+                        groups.push(std::mem::take(&mut current));
+                    }
+                    current.agents.push(value.to_ascii_lowercase());
+                    in_agents = true;
+                }
+                key @ ("allow" | "disallow") => {
+                    in_agents = false;
+                    // An empty Disallow allows everything; nothing to store.
+                    if current.agents.is_empty() || value.is_empty() {
+                        continue;
+                    }
+                    current.rules.push(Rule {
+                        pattern: value.to_string(),
+                        verdict: if key == "allow" { Verdict::Allow } else { Verdict::Disallow },
+                    });
+                }
+                "crawl-delay" => {
+                    in_agents = false;
+                    if current.agents.is_empty() {
+                        continue;
+                    }
+                    // Negative, NaN or too large for a `Duration`: ignored.
+                    if let Ok(secs) = value.parse::<f64>() {
+                        if secs > 0.0 {
+                            current.crawl_delay = Duration::try_from_secs_f64(secs).ok();
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        if !current.agents.is_empty() {
+            groups.push(current);
+        }
+
+        Self { groups }
+    }
+
+    /// The group for a client, and whether it was picked by name (rather
+    /// than `*`). The longest agent token contained in `ua` wins.
+    pub fn group_for(&self, ua: &str) -> Option<(&Group, bool)> {
+        let ua = ua.to_ascii_lowercase();
+        let named = self
+            .groups
+            .iter()
+            .flat_map(|g| g.agents.iter().map(move |a| (a, g)))
+            .filter(|(agent, _)| agent.as_str() != "*" && !agent.is_empty())
+            .filter(|(agent, _)| ua.contains(agent.as_str()))
+            .max_by_key(|(agent, _)| agent.len())
+            .map(|(_, g)| (g, true));
+
+        named.or_else(|| {
+            self.groups
+                .iter()
+                .find(|g| g.agents.iter().any(|a| a == "*"))
+                .map(|g| (g, false))
+        })
+    }
+}
+
+/// The robots.txt compliance signal, built once from `[scoring.robots]`.
+#[derive(Debug, Default)]
+pub struct RobotsSignal {
+    default: Option<RobotsTxt>,
+    vhosts: BTreeMap<String, RobotsTxt>,
+}
+
+impl RobotsSignal {
+    pub fn new(cfg: &RobotsConfig) -> anyhow::Result<Self> {
+        let load = |path: &std::path::Path| -> anyhow::Result<RobotsTxt> {
+            let text = fs::read_to_string(path)
+                .with_context(|| format!("reading robots.txt {}", path.display()))?;
+            Ok(RobotsTxt::parse(&text))
+        };
+
+        let mut vhosts = BTreeMap::new();
+        for (host, path) in &cfg.vhosts {
+            vhosts.insert(host.to_ascii_lowercase(), load(path)?);
+        }
+
+        Ok(Self {
+            default: cfg.file.as_deref().map(load).transpose()?,
+            vhosts,
+        })
+    }
+
+    /// Number of robots.txt files loaded.
+    pub fn len(&self) -> usize {
+        self.vhosts.len() + usize::from(self.default.is_some())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The robots.txt for the site `event` went to, with its key: the
+    /// `$host`, then the source label, then the default file.
+    fn site(&self, event: &ParsedEvent) -> Option<(&str, &RobotsTxt)> {
+        let by_name = |name: Option<&str>| {
+            let name = name?.to_ascii_lowercase();
+            self.vhosts.get_key_value(&name).map(|(k, v)| (k.as_str(), v))
+        };
+
+        by_name(event.host.as_deref())
+            .or_else(|| by_name(event.source.as_deref()))
+            .or_else(|| self.default.as_ref().map(|robots| ("", robots)))
+    }
+
+    /// Fold `event` into what is known about its client.
+    ///
+    /// Only clients that read robots.txt, or whose user agent a named
+    /// group addresses, are held to it: humans follow links, not rules.
+    pub fn observe(&self, event: &ParsedEvent, track: &mut RobotsTrack) {
+        let Some((site, robots)) = self.site(event) else {
+            return;
+        };
+
+        let path = event.path.split('?').next().unwrap_or(&event.path);
+        if path == "/robots.txt" {
+            track.mark_fetched(site);
+            return;
+        }
+
+        let ua = event.user_agent.as_deref().unwrap_or("");
+        let Some((group, named)) = robots.group_for(ua) else {
+            return;
+        };
+        if !track.fetched(site) && !named {
+            return;
+        }
+
+        if track.disallowed.is_none() && !group.allows(&event.path) {
+            track.disallowed = Some(event.path.clone());
+        }
+
+        if let Some(delay) = group.crawl_delay() {
+            if track.gap_since_last(site, event.timestamp).is_some_and(|gap| gap < delay) {
+                track.delay_violations += 1;
+                track.crawl_delay = Some(delay);
+            }
+        }
+    }
+
+    pub fn reasons(&self, track: &RobotsTrack) -> Vec<ScoreReason> {
+        let mut reasons = Vec::new();
+
+        if let Some(path) = &track.disallowed {
+            reasons.push(ScoreReason::RobotsDisallowed { path: path.clone() });
+        }
+        if track.delay_violations >= CRAWL_DELAY_VIOLATIONS {
+            reasons.push(ScoreReason::CrawlDelayIgnored {
+                delay_ms: track.crawl_delay.map_or(0, |d| d.as_millis() as u64),
+                violations: track.delay_violations,
+            });
+        }
+
+        reasons
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::clock::at;
+
+    const ROBOTS: &str = "\
+# Example
+User-agent: *
+Disallow: /cart
+Disallow: /search
+Allow: /search/help
+
+User-agent: GPTBot
+User-agent: CCBot
+Disallow: /
+
+User-agent: examplebot
+Crawl-delay: 10
+Disallow: /*.pdf$
+";
+
+    fn event(path: &str, ua: &str, secs: u64) -> ParsedEvent {
+        ParsedEvent {
+            user_agent: Some(ua.into()),
+            host: Some("shop.example.com".into()),
+            ..ParsedEvent::test("192.0.2.1", path, at(secs))
+        }
+    }
+
+    fn signal() -> RobotsSignal {
+        RobotsSignal {
+            default: Some(RobotsTxt::parse(ROBOTS)),
+            vhosts: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn parses_groups_and_applies_longest_match() {
+        let robots = RobotsTxt::parse(ROBOTS);
+
+        let (any, named) = robots.group_for("Mozilla/5.0 (X11)").unwrap();
+        assert!(!named);
+        assert!(any.allows("/"));
+        assert!(!any.allows("/cart/checkout"));
+        assert!(!any.allows("/search?q=boots"));
+        assert!(any.allows("/search/help"));
+
+        let (gpt, named) = robots.group_for("Mozilla/5.0 (compatible; GPTBot/1.2)").unwrap();
+        assert!(named);
+        assert!(!gpt.allows("/products"));
+        assert_eq!(robots.group_for("CCBot/2.0").unwrap().0, gpt);
+
+        let (example, _) = robots.group_for("ExampleBot/1.0").unwrap();
+        assert_eq!(example.crawl_delay(), Some(Duration::from_secs(10)));
+        assert!(!example.allows("/files/manual.pdf"));
+        assert!(example.allows("/files/manual.pdf?download=1"));
+        assert!(example.allows("/cart"));
+    }
+
+    #[test]
+    fn matches_wildcards_and_anchors() {
+        assert!(matches("/a*/c", "/abbb/cd"));
+        assert!(matches("/*.php$", "/x/index.php"));
+        assert!(!matches("/*.php$", "/x/index.php5"));
+        assert!(matches("/exact$", "/exact"));
+        assert!(!matches("/exact$", "/exactly"));
+        assert!(!matches("/b", "/a/b"));
+    }
+
+    #[test]
+    fn holds_only_robots_readers_and_named_agents_to_the_rules() {
+        let signal = signal();
+        let browser = "Mozilla/5.0 (X11; Linux x86_64) Firefox/128.0";
+
+        let mut human = RobotsTrack::default();
+        signal.observe(&event("/cart", browser, 0), &mut human);
+        assert!(signal.reasons(&human).is_empty());
+
+        let mut reader = RobotsTrack::default();
+        signal.observe(&event("/robots.txt", "politebot/1.0", 0), &mut reader);
+        signal.observe(&event("/search?q=a", "politebot/1.0", 5), &mut reader);
+        assert_eq!(
+            signal.reasons(&reader),
+            vec![ScoreReason::RobotsDisallowed { path: "/search?q=a".into() }]
+        );
+
+        let mut gpt = RobotsTrack::default();
+        signal.observe(&event("/", "GPTBot/1.2", 0), &mut gpt);
+        assert_eq!(gpt.disallowed.as_deref(), Some("/"));
+    }
+
+    #[test]
+    fn robots_fetches_count_for_their_own_site_only() {
+        let mut signal = signal();
+        signal.vhosts.insert("blog.example.com".into(), RobotsTxt::parse(ROBOTS));
+        let on = |host: &str, path: &str| ParsedEvent {
+            host: Some(host.into()),
+            ..event(path, "politebot/1.0", 0)
+        };
+
+        let mut track = RobotsTrack::default();
+        signal.observe(&on("blog.example.com", "/robots.txt"), &mut track);
+        signal.observe(&on("shop.example.com", "/cart"), &mut track);
+        assert!(track.fetched_any());
+        assert!(!track.fetched(""));
+        assert!(signal.reasons(&track).is_empty());
+
+        signal.observe(&on("blog.example.com", "/cart"), &mut track);
+        assert_eq!(track.disallowed.as_deref(), Some("/cart"));
+    }
+
+    #[test]
+    fn ignores_crawl_delays_a_duration_cannot_hold() {
+        let robots = RobotsTxt::parse("User-agent: *\nCrawl-delay: 1e20\nDisallow: /x\n");
+        let (group, _) = robots.group_for("anybot").unwrap();
+        assert_eq!(group.crawl_delay(), None);
+        assert!(!group.allows("/x"));
+
+        let robots = RobotsTxt::parse("User-agent: *\nCrawl-delay: NaN\n");
+        assert_eq!(robots.group_for("anybot").unwrap().0.crawl_delay(), None);
+    }
+
+    #[test]
+    fn reports_repeated_crawl_delay_violations() {
+        let signal = signal();
+        let mut track = RobotsTrack::default();
+
+        for secs in [0, 10, 20, 22, 40] {
+            signal.observe(&event("/p", "examplebot/1.0", secs), &mut track);
+        }
+        assert_eq!(track.delay_violations, 1);
+        assert!(signal.reasons(&track).is_empty());
+
+        for secs in [41, 42] {
+            signal.observe(&event("/p", "examplebot/1.0", secs), &mut track);
+        }
+        assert_eq!(
+            signal.reasons(&track),
+            vec![ScoreReason::CrawlDelayIgnored { delay_ms: 10_000, violations: 3 }]
+        );
+    }
+}
@@ -0,0 +1,273 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use crate::config::schema::UserAgentConfig;
+use crate::engine::scoring::ScoreReason;
+use crate::model::ip_state::IpState;
+use crate::parser::ParsedEvent;
+
+/// HTTP client libraries and headless browsers; real browsers never send
+/// these tokens.
+const LIBRARIES: &[&str] = &[
+    "python-requests",
+    "python-urllib",
+    "aiohttp",
+    "httpx",
+    "curl/",
+    "Wget/",
+    "Go-http-client",
+    "Scrapy",
+    "HeadlessChrome",
+    "PhantomJS",
+    "libwww-perl",
+    "okhttp",
+    "Apache-HttpClient",
+    "Java/",
+    "node-fetch",
+    "axios/",
+];
+
+/// Crawlers collecting training or answer-engine data.
+const AI_CRAWLERS: &[&str] = &[
+    "GPTBot",
+    "ChatGPT-User",
+    "OAI-SearchBot",
+    "CCBot",
+    "ClaudeBot",
+    "Claude-Web",
+    "anthropic-ai",
+    "Bytespider",
+    "PerplexityBot",
+    "Google-Extended",
+    "Amazonbot",
+    "Applebot-Extended",
+    "meta-externalagent",
+    "cohere-ai",
+    "Diffbot",
+    "ImagesiftBot",
+    "Omgilibot",
+    "YouBot",
+];
+
+/// Contents of `scoring.user_agent.signatures_file`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SignatureFile {
+    /// Keep the built-in lists and add to them.
+    #[serde(default = "default_builtin")]
+    builtin: bool,
+    #[serde(default)]
+    libraries: Vec<String>,
+    #[serde(default)]
+    ai_crawlers: Vec<String>,
+}
+
+fn default_builtin() -> bool {
+    true
+}
+
+/// A signature: matched case-insensitively anywhere in the user agent.
+#[derive(Debug, Clone)]
+struct Signature {
+    name: String,
+    needle: String,
+}
+
+impl Signature {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            needle: name.to_ascii_lowercase(),
+        }
+    }
+}
+
+/// What a single user agent string looks like.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UaClass {
+    /// Missing, empty or `-`.
+    Empty,
+    ScrapingLibrary(String),
+    AiCrawler(String),
+}
+
+/// The user-agent signal, built once from `[scoring.user_agent]`.
+#[derive(Debug)]
+pub struct UserAgentSignal {
+    libraries: Vec<Signature>,
+    ai_crawlers: Vec<Signature>,
+    rotation_threshold: usize,
+}
+
+impl UserAgentSignal {
+    pub fn new(cfg: &UserAgentConfig) -> anyhow::Result<Self> {
+        let file = match &cfg.signatures_file {
+            Some(path) => Some(load_signatures(path)?),
+            None => None,
+        };
+        let builtin = file.as_ref().is_none_or(|f| f.builtin);
+
+        let list = |builtin_list: &[&str], extra: Option<&Vec<String>>| {
+            let builtin_list = if builtin { builtin_list } else { &[] };
+            builtin_list
+                .iter()
+                .copied()
+                .chain(extra.into_iter().flatten().map(String::as_str))
+                .filter(|name| !name.is_empty())
+                .map(Signature::new)
+                .collect::<Vec<_>>()
+        };
+
+        Ok(Self {
+            libraries: list(LIBRARIES, file.as_ref().map(|f| &f.libraries)),
+            ai_crawlers: list(AI_CRAWLERS, file.as_ref().map(|f| &f.ai_crawlers)),
+            rotation_threshold: cfg.rotation_threshold,
+        })
+    }
+
+    /// Number of library and AI crawler signatures loaded.
+    pub fn signature_count(&self) -> usize {
+        self.libraries.len() + self.ai_crawlers.len()
+    }
+
+    pub fn classify(&self, ua: Option<&str>) -> Option<UaClass> {
+        let ua = ua.map(str::trim).unwrap_or("");
+        if ua.is_empty() || ua == "-" {
+            return Some(UaClass::Empty);
+        }
+
+        let lower = ua.to_ascii_lowercase();
+        let find = |list: &[Signature]| {
+            list.iter()
+                .find(|sig| lower.contains(&sig.needle))
+                .map(|sig| sig.name.clone())
+        };
+
+        // AI crawlers first: several of them embed a library token too.
+        find(&self.ai_crawlers)
+            .map(UaClass::AiCrawler)
+            .or_else(|| find(&self.libraries).map(UaClass::ScrapingLibrary))
+    }
+
+    /// Reasons the user agent of `event` (and the agents its client has
+    /// used so far) give for suspicion.
+    pub fn reasons(&self, event: &ParsedEvent, state: &IpState) -> Vec<ScoreReason> {
+        let mut reasons = Vec::new();
+
+        match self.classify(event.user_agent.as_deref()) {
+            Some(UaClass::Empty) => reasons.push(ScoreReason::EmptyUserAgent),
+            Some(UaClass::ScrapingLibrary(signature)) => {
+                reasons.push(ScoreReason::ScrapingLibrary { signature })
+            }
+            Some(UaClass::AiCrawler(signature)) => {
+                reasons.push(ScoreReason::AiCrawler { signature })
+            }
+            None => {}
+        }
+
+        let distinct = state.distinct_user_agents();
+        if distinct >= self.rotation_threshold {
+            reasons.push(ScoreReason::UserAgentRotation {
+                distinct: distinct as u64,
+            });
+        }
+
+        reasons
+    }
+}
+
+fn load_signatures(path: &Path) -> anyhow::Result<SignatureFile> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("reading user-agent signatures {}", path.display()))?;
+    toml::from_str(&text)
+        .with_context(|| format!("parsing user-agent signatures {}", path.display()))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    fn signal() -> UserAgentSignal {
+        UserAgentSignal::new(&UserAgentConfig::default()).unwrap()
+    }
+
+    #[test]
+    fn classifies_builtin_signatures() {
+        let signal = signal();
+
+        assert_eq!(signal.classify(None), Some(UaClass::Empty));
+        assert_eq!(signal.classify(Some("-")), Some(UaClass::Empty));
+        assert_eq!(
+            signal.classify(Some("python-requests/2.31.0")),
+            Some(UaClass::ScrapingLibrary("python-requests".into()))
+        );
+        assert_eq!(
+            signal.classify(Some(
+                "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) HeadlessChrome/120.0.0.0 Safari/537.36"
+            )),
+            Some(UaClass::ScrapingLibrary("HeadlessChrome".into()))
+        );
+        assert_eq!(
+            signal.classify(Some(
+                "Mozilla/5.0 AppleWebKit/537.36 (KHTML, like Gecko; compatible; GPTBot/1.2; +https://openai.com/gptbot)"
+            )),
+            Some(UaClass::AiCrawler("GPTBot".into()))
+        );
+        assert_eq!(
+            signal.classify(Some("Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:128.0) Gecko/20100101 Firefox/128.0")),
+            None
+        );
+    }
+
+    #[test]
+    fn signatures_file_extends_or_replaces_builtins() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ua.toml");
+
+        fs::write(&path, "ai_crawlers = [\"NewAIBot\"]\n").unwrap();
+        let cfg = UserAgentConfig {
+            signatures_file: Some(path.clone()),
+            ..UserAgentConfig::default()
+        };
+        let signal = UserAgentSignal::new(&cfg).unwrap();
+        assert_eq!(
+            signal.classify(Some("newaibot/0.1")),
+            Some(UaClass::AiCrawler("NewAIBot".into()))
+        );
+        assert!(signal.classify(Some("curl/8.5.0")).is_some());
+
+        fs::write(&path, "builtin = false\nlibraries = [\"my-fetcher\"]\n").unwrap();
+        let signal = UserAgentSignal::new(&cfg).unwrap();
+        assert_eq!(signal.signature_count(), 1);
+        assert_eq!(signal.classify(Some("curl/8.5.0")), None);
+
+        fs::write(&path, "librarys = []\n").unwrap();
+        assert!(UserAgentSignal::new(&cfg).is_err());
+    }
+
+    #[test]
+    fn reports_rotation_once_threshold_is_reached() {
+        let signal = signal();
+        let mut state = IpState::new("1.2.3.4".parse().unwrap(), UNIX_EPOCH);
+        let event = ParsedEvent {
+            user_agent: Some("Mozilla/5.0 (Macintosh) Safari/605.1.15".into()),
+            ..ParsedEvent::test("1.2.3.4", "/", UNIX_EPOCH)
+        };
+
+        for n in 0..4 {
+            state.record_user_agent(&format!("Mozilla/5.0 Firefox/12{}.0", n));
+        }
+        assert!(signal.reasons(&event, &state).is_empty());
+
+        state.record_user_agent("Mozilla/5.0 Firefox/130.0");
+        assert_eq!(
+            signal.reasons(&event, &state),
+            vec![ScoreReason::UserAgentRotation { distinct: 5 }]
+        );
+    }
+}
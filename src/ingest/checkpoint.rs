@@ -0,0 +1,143 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// Where reading of one log file stopped.
+///
+/// The device/inode pair says which file the offset belongs to; the hash
+/// of its first line guards against the inode being reused by a newer
+/// file after rotation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub dev: u64,
+    pub ino: u64,
+    pub offset: u64,
+    pub first_line_hash: Option<u64>,
+}
+
+/// All checkpoints of one daemon, keyed by log path, persisted as JSON.
+#[derive(Debug)]
+pub struct CheckpointStore {
+    path: PathBuf,
+    entries: BTreeMap<PathBuf, Checkpoint>,
+}
+
+pub type SharedCheckpoints = Arc<Mutex<CheckpointStore>>;
+
+impl CheckpointStore {
+    /// Load the state file; a missing file is an empty store.
+    pub fn load(path: PathBuf) -> io::Result<Self> {
+        let entries = match fs::read_to_string(&path) {
+            Ok(raw) => serde_json::from_str(&raw)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e),
+        };
+
+        Ok(Self { path, entries })
+    }
+
+    pub fn get(&self, log: &Path) -> Option<&Checkpoint> {
+        self.entries.get(log)
+    }
+
+    pub fn set(&mut self, log: PathBuf, checkpoint: Checkpoint) {
+        self.entries.insert(log, checkpoint);
+    }
+
+    /// Write the state file atomically (temp file + rename).
+    pub fn save(&self) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(&self.entries)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+/// Checkpointing settings handed to a file ingestor.
+#[derive(Debug, Clone)]
+pub struct Checkpointing {
+    pub store: SharedCheckpoints,
+    pub interval: Duration,
+    /// Skip ahead when more than this many bytes are waiting on resume.
+    pub max_backlog_bytes: u64,
+}
+
+/// Hash of the first line of `path` (FNV-1a, stable across builds).
+///
+/// `None` while the file has no complete first line yet.
+pub fn first_line_hash(path: &Path) -> io::Result<Option<u64>> {
+    let mut line = Vec::new();
+    BufReader::new(File::open(path)?)
+        .take(4096)
+        .read_until(b'\n', &mut line)?;
+
+    if !line.ends_with(b"\n") {
+        return Ok(None);
+    }
+
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in line {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    Ok(Some(hash))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_state_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = dir.path().join("checkpoints.json");
+        let checkpoint = Checkpoint {
+            dev: 1,
+            ino: 2,
+            offset: 3,
+            first_line_hash: Some(4),
+        };
+
+        let mut store = CheckpointStore::load(state.clone()).unwrap();
+        assert!(store.get(Path::new("/var/log/nginx/access.log")).is_none());
+
+        store.set("/var/log/nginx/access.log".into(), checkpoint.clone());
+        store.save().unwrap();
+
+        let reloaded = CheckpointStore::load(state).unwrap();
+        assert_eq!(
+            reloaded.get(Path::new("/var/log/nginx/access.log")),
+            Some(&checkpoint)
+        );
+    }
+
+    #[test]
+    fn hashes_only_complete_first_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("access.log");
+
+        fs::write(&log, "partial").unwrap();
+        assert_eq!(first_line_hash(&log).unwrap(), None);
+
+        fs::write(&log, "first\nsecond\n").unwrap();
+        let a = first_line_hash(&log).unwrap();
+        fs::write(&log, "first\nother\n").unwrap();
+        let b = first_line_hash(&log).unwrap();
+        fs::write(&log, "changed\nsecond\n").unwrap();
+        let c = first_line_hash(&log).unwrap();
+
+        assert!(a.is_some());
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+}
@@ -1,41 +1,572 @@
-use std::fs::File;
-use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom};
+use std::os::unix::fs::MetadataExt;
 use std::path::PathBuf;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 
-use crate::parser::{parse_line, ParsedEvent};
-use crate::ingest::Ingestor;
+use crate::parser::LogParser;
+use crate::ingest::checkpoint::{first_line_hash, Checkpoint, Checkpointing};
+use crate::ingest::{Ingestor, Poll};
 
+/// Device + inode pair identifying the file behind a path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FileId {
+    dev: u64,
+    ino: u64,
+}
+
+impl FileId {
+    pub fn of(meta: &fs::Metadata) -> Self {
+        Self {
+            dev: meta.dev(),
+            ino: meta.ino(),
+        }
+    }
+}
+
+/// Tails a log file across logrotate runs.
+///
+/// At EOF the path is re-examined:
+/// - a different inode behind the path means the file was renamed away
+///   (`create` mode). Everything left in the old file has already been
+///   read, so the new file is opened from the start.
+/// - the same inode but shorter than our offset means it was truncated
+///   in place (`copytruncate`), so reading restarts at offset 0.
+///
+/// With checkpointing enabled the read position is persisted and a
+/// restarted daemon resumes where it stopped instead of at EOF.
 pub struct FileIngestor {
+    path: PathBuf,
     reader: BufReader<File>,
+    id: FileId,
+    offset: u64,
+    /// Bytes of a line the writer has not finished yet.
+    partial: Vec<u8>,
+    parser: Box<dyn LogParser>,
     poll_interval: Duration,
+    checkpointing: Option<Checkpointing>,
+    first_line_hash: Option<u64>,
+    last_saved: Instant,
 }
 
 impl FileIngestor {
-    pub fn new(path: PathBuf, poll_interval_ms: u64) -> std::io::Result<Self> {
-        let mut file = File::open(path)?;
-        file.seek(SeekFrom::End(0))?;
+    /// Tail `path` from its current end.
+    pub fn new(
+        path: PathBuf,
+        poll_interval_ms: u64,
+        parser: Box<dyn LogParser>,
+    ) -> std::io::Result<Self> {
+        Self::open(path, poll_interval_ms, parser, None)
+    }
+
+    /// Tail `path`, resuming from its checkpoint if one is stored.
+    ///
+    /// Without a checkpoint for this path, reading starts at EOF. If the
+    /// file was replaced since the checkpoint, it is read from the start.
+    /// Either way at most `max_backlog_bytes` of backlog are read.
+    pub fn open(
+        path: PathBuf,
+        poll_interval_ms: u64,
+        parser: Box<dyn LogParser>,
+        checkpointing: Option<Checkpointing>,
+    ) -> std::io::Result<Self> {
+        Self::open_at(path, poll_interval_ms, parser, checkpointing, true)
+    }
+
+    /// Like [`FileIngestor::open`], but a file without a checkpoint is
+    /// read from the start. Used for files that appear while running.
+    pub fn open_new(
+        path: PathBuf,
+        poll_interval_ms: u64,
+        parser: Box<dyn LogParser>,
+        checkpointing: Option<Checkpointing>,
+    ) -> std::io::Result<Self> {
+        Self::open_at(path, poll_interval_ms, parser, checkpointing, false)
+    }
+
+    fn open_at(
+        path: PathBuf,
+        poll_interval_ms: u64,
+        parser: Box<dyn LogParser>,
+        checkpointing: Option<Checkpointing>,
+        tail: bool,
+    ) -> std::io::Result<Self> {
+        let file = File::open(&path)?;
+        let meta = file.metadata()?;
+        let id = FileId::of(&meta);
+        let hash = first_line_hash(&path)?;
+        let len = meta.len();
+        let fresh = if tail { len } else { 0 };
+
+        let start = match &checkpointing {
+            Some(cp) => resume_offset(&path, id, hash, len, cp).unwrap_or(fresh),
+            None => fresh,
+        };
+
+        let mut reader = BufReader::new(file);
+        let mut offset = reader.seek(SeekFrom::Start(start))?;
+
+        // Cutting the backlog can land mid-line; skip to the next one.
+        if checkpointing.is_some() && start > 0 && start != len {
+            let mut skipped = Vec::new();
+            reader.seek(SeekFrom::Start(start - 1))?;
+            offset = start - 1 + reader.read_until(b'\n', &mut skipped)? as u64;
+        }
 
         Ok(Self {
-            reader: BufReader::new(file),
+            path,
+            reader,
+            id,
+            offset,
+            partial: Vec::new(),
+            parser,
             poll_interval: Duration::from_millis(poll_interval_ms),
+            checkpointing,
+            first_line_hash: hash,
+            last_saved: Instant::now(),
         })
     }
+
+    /// Persist the current position (complete lines only).
+    pub fn save_checkpoint(&mut self) -> io::Result<()> {
+        let Some(cp) = &self.checkpointing else {
+            return Ok(());
+        };
+
+        if self.first_line_hash.is_none() {
+            self.first_line_hash = first_line_hash(&self.path)?;
+        }
+
+        let checkpoint = Checkpoint {
+            dev: self.id.dev,
+            ino: self.id.ino,
+            offset: self.offset - self.partial.len() as u64,
+            first_line_hash: self.first_line_hash,
+        };
+
+        let mut store = cp.store.lock().expect("checkpoint store poisoned");
+        store.set(self.path.clone(), checkpoint);
+        store.save()?;
+
+        self.last_saved = Instant::now();
+        Ok(())
+    }
+
+    fn maybe_save_checkpoint(&mut self) {
+        let due = match &self.checkpointing {
+            Some(cp) => self.last_saved.elapsed() >= cp.interval,
+            None => false,
+        };
+
+        if due {
+            if let Err(e) = self.save_checkpoint() {
+                log::warn!("Failed to save checkpoint for {}: {}", self.path.display(), e);
+                self.last_saved = Instant::now();
+            }
+        }
+    }
+
+    /// Next complete line, or `None` at EOF. Invalid UTF-8 is replaced
+    /// rather than rejected, so the offset always matches the bytes read.
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        let read = self.reader.read_until(b'\n', &mut self.partial)?;
+        self.offset += read as u64;
+
+        if self.partial.ends_with(b"\n") {
+            let line = std::mem::take(&mut self.partial);
+            Ok(Some(String::from_utf8_lossy(&line).into_owned()))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Called at EOF. Returns `true` if the file was rotated or truncated
+    /// and reading should resume immediately.
+    fn check_rotation(&mut self) -> io::Result<bool> {
+        let meta = match fs::metadata(&self.path) {
+            Ok(meta) => meta,
+            // Renamed away and the new file is not there yet.
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e),
+        };
+
+        if FileId::of(&meta) != self.id {
+            let file = File::open(&self.path)?;
+            let id = FileId::of(&file.metadata()?);
+            log::info!("{} was rotated; reopening from the start", self.path.display());
+
+            self.discard_partial();
+            self.reader = BufReader::new(file);
+            self.id = id;
+            self.offset = 0;
+            self.first_line_hash = None;
+            return Ok(true);
+        }
+
+        if meta.len() < self.offset {
+            log::info!("{} was truncated; rewinding", self.path.display());
+
+            self.discard_partial();
+            self.reader.seek(SeekFrom::Start(0))?;
+            self.offset = 0;
+            return Ok(true);
+        }
+
+        Ok(false)
+    }
+
+    fn discard_partial(&mut self) {
+        if !self.partial.is_empty() {
+            log::debug!(
+                "Dropping unterminated line from {}: {:?}",
+                self.path.display(),
+                String::from_utf8_lossy(&self.partial)
+            );
+            self.partial.clear();
+        }
+    }
 }
 
-impl Ingestor for FileIngestor {
-    fn next_event(&mut self) -> Option<ParsedEvent> {
-        let mut line = String::new();
+/// Where to start reading `path` given its stored checkpoint, or `None`
+/// if there is no checkpoint for it.
+fn resume_offset(
+    path: &std::path::Path,
+    id: FileId,
+    hash: Option<u64>,
+    len: u64,
+    cp: &Checkpointing,
+) -> Option<u64> {
+    let saved = cp
+        .store
+        .lock()
+        .expect("checkpoint store poisoned")
+        .get(path)
+        .cloned();
+
+    let from = match saved {
+        Some(saved)
+            if saved.dev == id.dev
+                && saved.ino == id.ino
+                && saved.offset <= len
+                && (saved.first_line_hash.is_none() || saved.first_line_hash == hash) =>
+        {
+            log::info!("Resuming {} at offset {}", path.display(), saved.offset);
+            saved.offset
+        }
+        Some(_) => {
+            log::info!(
+                "{} changed since the last checkpoint; reading from the start",
+                path.display()
+            );
+            0
+        }
+        None => return None,
+    };
 
-        match self.reader.read_line(&mut line) {
-            Ok(0) => {
-                thread::sleep(self.poll_interval);
-                None
+    let backlog = len - from;
+    if backlog > cp.max_backlog_bytes {
+        log::warn!(
+            "{} has {} bytes of backlog; skipping {} bytes (max_backlog_bytes = {})",
+            path.display(),
+            backlog,
+            backlog - cp.max_backlog_bytes,
+            cp.max_backlog_bytes
+        );
+        Some(len - cp.max_backlog_bytes)
+    } else {
+        Some(from)
+    }
+}
+
+impl FileIngestor {
+    /// Read at most one line without sleeping.
+    ///
+    /// Rotation and truncation are handled here; a reopened file is read
+    /// right away, so `Idle` really means there is nothing to read. A
+    /// file is never finished, so this never returns `Eof`.
+    pub fn poll(&mut self) -> Poll {
+        self.maybe_save_checkpoint();
+
+        for _ in 0..2 {
+            match self.read_line() {
+                Ok(Some(line)) => {
+                    return match self.parser.parse(&line) {
+                        Some(event) => Poll::Event(event),
+                        None => Poll::Unparsed(line),
+                    };
+                }
+                Ok(None) => match self.check_rotation() {
+                    Ok(true) => continue,
+                    Ok(false) => return Poll::Idle,
+                    Err(e) => return Poll::Error(self.error("stat", e)),
+                },
+                Err(e) => return Poll::Error(self.error("read", e)),
             }
-            Ok(_) => parse_line(&line),
-            Err(_) => None,
         }
+
+        Poll::Idle
+    }
+
+    /// Attach the path to an I/O error.
+    fn error(&self, what: &str, e: io::Error) -> io::Error {
+        io::Error::new(
+            e.kind(),
+            format!("Failed to {} {}: {}", what, self.path.display(), e),
+        )
+    }
+
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+
+    /// The file currently being read, which changes on rotation.
+    pub fn file_id(&self) -> FileId {
+        self.id
+    }
+}
+
+impl Ingestor for FileIngestor {
+    fn next_event(&mut self) -> Poll {
+        let poll = self.poll();
+        // Back off on errors too, or a file that keeps failing to stat or
+        // read would be retried in a busy loop.
+        if matches!(poll, Poll::Idle | Poll::Error(_)) {
+            thread::sleep(self.poll_interval);
+        }
+        poll
+    }
+
+    fn shutdown(&mut self) {
+        if let Err(e) = self.save_checkpoint() {
+            log::warn!("Failed to save checkpoint for {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::NginxCombinedParser;
+    use std::fs::OpenOptions;
+    use std::io::Write;
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+    use crate::ingest::checkpoint::CheckpointStore;
+
+    fn line(path: &str) -> String {
+        format!(
+            "1.2.3.4 - - [02/Oct/2024:00:48:26 +0900] \"GET {} HTTP/1.1\" 404 0 \"-\" \"-\"\n",
+            path
+        )
+    }
+
+    fn append(path: &Path, text: &str) {
+        let mut file = OpenOptions::new().create(true).append(true).open(path).unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+    }
+
+    fn ingestor(path: &Path) -> FileIngestor {
+        FileIngestor::new(path.to_path_buf(), 1, Box::new(NginxCombinedParser)).unwrap()
+    }
+
+    /// Poll until an event arrives; gives up after a few idle rounds.
+    fn next_path(ingestor: &mut FileIngestor) -> Option<String> {
+        (0..10).find_map(|_| match ingestor.next_event() {
+            Poll::Event(event) => Some(event.path),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn starts_at_end_of_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("access.log");
+        append(&path, &line("/old"));
+
+        let mut ingestor = ingestor(&path);
+        append(&path, &line("/new"));
+
+        assert_eq!(next_path(&mut ingestor).as_deref(), Some("/new"));
+        assert_eq!(next_path(&mut ingestor), None);
+    }
+
+    #[test]
+    fn waits_for_unterminated_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("access.log");
+        append(&path, "");
+
+        let mut ingestor = ingestor(&path);
+        let full = line("/slow");
+        let (head, tail) = full.split_at(20);
+
+        append(&path, head);
+        assert_eq!(next_path(&mut ingestor), None);
+
+        append(&path, tail);
+        assert_eq!(next_path(&mut ingestor).as_deref(), Some("/slow"));
+    }
+
+    #[test]
+    fn invalid_utf8_does_not_shift_the_offset() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("access.log");
+        append(&path, "");
+
+        let mut ingestor = ingestor(&path);
+        let mut bad = line("/caf\u{e9}").into_bytes();
+        let at = bad.iter().position(|b| *b == 0xc3).unwrap();
+        bad.remove(at);
+        OpenOptions::new().append(true).open(&path).unwrap().write_all(&bad).unwrap();
+        append(&path, &line("/next"));
+
+        assert_eq!(next_path(&mut ingestor).as_deref(), Some("/caf\u{fffd}"));
+        assert_eq!(next_path(&mut ingestor).as_deref(), Some("/next"));
+        assert_eq!(ingestor.offset, fs::metadata(&path).unwrap().len());
+    }
+
+    #[test]
+    fn follows_create_mode_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("access.log");
+        let rotated = dir.path().join("access.log.1");
+        append(&path, "");
+
+        let mut ingestor = ingestor(&path);
+        append(&path, &line("/before"));
+        assert_eq!(next_path(&mut ingestor).as_deref(), Some("/before"));
+
+        // logrotate renames the file; nginx keeps writing to the old
+        // inode until it reopens its logs.
+        fs::rename(&path, &rotated).unwrap();
+        append(&rotated, &line("/late-write"));
+        append(&path, &line("/after"));
+
+        assert_eq!(next_path(&mut ingestor).as_deref(), Some("/late-write"));
+        assert_eq!(next_path(&mut ingestor).as_deref(), Some("/after"));
+        assert_eq!(next_path(&mut ingestor), None);
+    }
+
+    #[test]
+    fn survives_gap_before_new_file_appears() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("access.log");
+        append(&path, "");
+
+        let mut ingestor = ingestor(&path);
+        fs::rename(&path, dir.path().join("access.log.1")).unwrap();
+        assert_eq!(next_path(&mut ingestor), None);
+
+        append(&path, &line("/after"));
+        assert_eq!(next_path(&mut ingestor).as_deref(), Some("/after"));
+    }
+
+    #[test]
+    fn follows_copytruncate_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("access.log");
+        append(&path, "");
+
+        let mut ingestor = ingestor(&path);
+        append(&path, &line("/before-1"));
+        append(&path, &line("/before-2"));
+        assert_eq!(next_path(&mut ingestor).as_deref(), Some("/before-1"));
+        assert_eq!(next_path(&mut ingestor).as_deref(), Some("/before-2"));
+
+        fs::copy(&path, dir.path().join("access.log.1")).unwrap();
+        OpenOptions::new().write(true).open(&path).unwrap().set_len(0).unwrap();
+        append(&path, &line("/after"));
+
+        assert_eq!(next_path(&mut ingestor).as_deref(), Some("/after"));
+        assert_eq!(next_path(&mut ingestor), None);
+    }
+
+    fn checkpointing(state: &Path, max_backlog_bytes: u64) -> Checkpointing {
+        Checkpointing {
+            store: Arc::new(Mutex::new(CheckpointStore::load(state.to_path_buf()).unwrap())),
+            interval: Duration::from_secs(3600),
+            max_backlog_bytes,
+        }
+    }
+
+    fn resumed(path: &Path, state: &Path, max_backlog_bytes: u64) -> FileIngestor {
+        FileIngestor::open(
+            path.to_path_buf(),
+            1,
+            Box::new(NginxCombinedParser),
+            Some(checkpointing(state, max_backlog_bytes)),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn resumes_from_checkpoint_after_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("access.log");
+        let state = dir.path().join("checkpoints.json");
+        append(&path, &line("/first"));
+
+        // First run: no checkpoint yet, so tail from the end.
+        let mut first = resumed(&path, &state, u64::MAX);
+        append(&path, &line("/seen"));
+        assert_eq!(next_path(&mut first).as_deref(), Some("/seen"));
+        first.shutdown();
+        drop(first);
+
+        // Written while the daemon was down.
+        append(&path, &line("/while-down-1"));
+        append(&path, &line("/while-down-2"));
+
+        let mut second = resumed(&path, &state, u64::MAX);
+        assert_eq!(next_path(&mut second).as_deref(), Some("/while-down-1"));
+        assert_eq!(next_path(&mut second).as_deref(), Some("/while-down-2"));
+        assert_eq!(next_path(&mut second), None);
+    }
+
+    #[test]
+    fn caps_backlog_on_resume() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("access.log");
+        let state = dir.path().join("checkpoints.json");
+        append(&path, &line("/first"));
+
+        let mut first = resumed(&path, &state, u64::MAX);
+        first.shutdown();
+        drop(first);
+
+        for i in 0..10 {
+            append(&path, &line(&format!("/missed-{}", i)));
+        }
+
+        // Room for roughly one and a half lines: the cut lands mid-line
+        // and only the last complete line is read.
+        let max = (line("/missed-9").len() * 3 / 2) as u64;
+        let mut second = resumed(&path, &state, max);
+        assert_eq!(next_path(&mut second).as_deref(), Some("/missed-9"));
+        assert_eq!(next_path(&mut second), None);
+    }
+
+    #[test]
+    fn replaced_file_is_read_from_start() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("access.log");
+        let state = dir.path().join("checkpoints.json");
+        append(&path, &line("/old"));
+
+        let mut first = resumed(&path, &state, u64::MAX);
+        first.shutdown();
+        drop(first);
+
+        // Rotated while the daemon was down.
+        fs::rename(&path, dir.path().join("access.log.1")).unwrap();
+        append(&path, &line("/new-1"));
+        append(&path, &line("/new-2"));
+
+        let mut second = resumed(&path, &state, u64::MAX);
+        assert_eq!(next_path(&mut second).as_deref(), Some("/new-1"));
+        assert_eq!(next_path(&mut second).as_deref(), Some("/new-2"));
     }
 }
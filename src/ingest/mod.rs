@@ -1,8 +1,66 @@
+use std::fmt;
+use std::io;
+
 use crate::parser::ParsedEvent;
 
+/// Outcome of one [`Ingestor::next_event`] call.
+#[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
+pub enum Poll {
+    Event(ParsedEvent),
+    /// Nothing new to read right now.
+    Idle,
+    /// A line was read but the parser rejected it.
+    Unparsed(String),
+    /// A finite source (e.g. a pipe on stdin) has ended for good.
+    Eof,
+    /// Reading failed. Not fatal: the next call retries, and an ingestor
+    /// that cannot go on reports `Eof` instead.
+    Error(io::Error),
+}
+
 pub trait Ingestor {
-    fn next_event(&mut self) -> Option<ParsedEvent>;
+    /// Next thing read from the source. When idle this may block for up
+    /// to the poll interval, so the caller can simply call it again.
+    fn next_event(&mut self) -> Poll;
+
+    /// Called once before the daemon exits, e.g. to persist read offsets.
+    fn shutdown(&mut self) {}
+}
+
+/// Running totals over everything an ingestor returned.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct IngestStats {
+    pub events: u64,
+    pub unparsed: u64,
+    pub errors: u64,
+}
+
+impl IngestStats {
+    pub fn record(&mut self, poll: &Poll) {
+        match poll {
+            Poll::Event(_) => self.events += 1,
+            Poll::Unparsed(_) => self.unparsed += 1,
+            Poll::Error(_) => self.errors += 1,
+            Poll::Idle | Poll::Eof => {}
+        }
+    }
+}
+
+impl fmt::Display for IngestStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} events, {} unparseable lines, {} read errors",
+            self.events, self.unparsed, self.errors
+        )
+    }
 }
 
+pub mod archive;
+pub mod checkpoint;
 pub mod file;
+pub mod multi;
 pub mod stdin;
+pub mod syslog;
+pub mod watch;
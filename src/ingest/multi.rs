@@ -0,0 +1,416 @@
+use std::collections::HashSet;
+use std::fs;
+use std::time::{Duration, Instant};
+
+use crate::config::schema::{ParserConfig, SourceConfig, WatchMode};
+use crate::ingest::checkpoint::Checkpointing;
+use crate::ingest::file::{FileId, FileIngestor};
+use crate::ingest::watch::Waiter;
+use crate::ingest::{Ingestor, Poll};
+use crate::parser::build_parser;
+
+struct WatchedSource {
+    pattern: String,
+    label: Option<String>,
+    parser: ParserConfig,
+}
+
+struct TrackedFile {
+    label: Option<String>,
+    ingestor: FileIngestor,
+}
+
+/// Tails every file matched by a set of `[[ingest.sources]]` and merges
+/// their events into one stream.
+///
+/// Files are read round-robin, one event at a time, so a busy vhost
+/// cannot starve a quiet one. Glob patterns are re-expanded every
+/// `rescan_interval`; files found at startup are tailed from the end (or
+/// their checkpoint), files that appear later are read from the start.
+/// Files are told apart by device and inode, so a log rotated to a name
+/// the glob also matches is not read a second time; files whose path has
+/// disappeared are dropped. When every file is idle, the [`Waiter`]
+/// blocks until one changes.
+pub struct MultiIngestor {
+    sources: Vec<WatchedSource>,
+    files: Vec<TrackedFile>,
+    /// Files already read, open or not, among those still on disk.
+    known: HashSet<FileId>,
+    cursor: usize,
+    poll_interval_ms: u64,
+    rescan_interval: Duration,
+    last_scan: Instant,
+    checkpointing: Option<Checkpointing>,
+    waiter: Waiter,
+}
+
+impl MultiIngestor {
+    pub fn new(
+        sources: &[SourceConfig],
+        parser: &ParserConfig,
+        poll_interval_ms: u64,
+        rescan_interval: Duration,
+        checkpointing: Option<Checkpointing>,
+        watch: WatchMode,
+    ) -> anyhow::Result<Self> {
+        let sources = sources
+            .iter()
+            .map(|source| {
+                let parser = parser.for_source(source);
+                build_parser(&parser)?;
+                Ok(WatchedSource {
+                    pattern: source.path.clone(),
+                    label: source.label.clone(),
+                    parser,
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let waiter = Waiter::new(watch, Duration::from_millis(poll_interval_ms))?;
+        if waiter.is_inotify() {
+            log::info!("Watching log files with inotify");
+        }
+
+        let mut ingestor = Self {
+            sources,
+            files: Vec::new(),
+            known: HashSet::new(),
+            cursor: 0,
+            poll_interval_ms,
+            rescan_interval,
+            last_scan: Instant::now(),
+            checkpointing,
+            waiter,
+        };
+        ingestor.scan(true);
+
+        Ok(ingestor)
+    }
+
+    /// Number of files currently being tailed.
+    pub fn file_count(&self) -> usize {
+        self.files.len()
+    }
+
+    fn scan(&mut self, initial: bool) {
+        self.last_scan = Instant::now();
+
+        // Before dropping anything: a file rotated since the last scan
+        // has its ingestor on the new inode, the old one is known already.
+        self.known.extend(self.files.iter().map(|file| file.ingestor.file_id()));
+        let waiter = &mut self.waiter;
+        self.files.retain(|file| {
+            let exists = file.ingestor.path().exists();
+            if !exists {
+                log::info!("Stopped tailing {}: file is gone", file.ingestor.path().display());
+                waiter.unwatch(file.ingestor.path());
+            }
+            exists
+        });
+
+        let mut present: HashSet<FileId> =
+            self.files.iter().map(|file| file.ingestor.file_id()).collect();
+
+        for source in &self.sources {
+            let paths = match glob::glob(&source.pattern) {
+                Ok(paths) => paths,
+                Err(e) => {
+                    log::warn!("Invalid ingest source {:?}: {}", source.pattern, e);
+                    continue;
+                }
+            };
+
+            let mut matched = 0;
+            for path in paths.flatten() {
+                let id = match fs::metadata(&path) {
+                    Ok(meta) if meta.is_file() => FileId::of(&meta),
+                    _ => continue,
+                };
+                matched += 1;
+                present.insert(id);
+
+                // An open ingestor follows its path across rotations.
+                let tailed = self.files.iter().any(|file| file.ingestor.path() == path);
+                if tailed || self.known.contains(&id) {
+                    continue;
+                }
+
+                let parser = match build_parser(&source.parser) {
+                    Ok(parser) => parser,
+                    Err(e) => {
+                        log::warn!("Cannot build parser for {}: {}", path.display(), e);
+                        continue;
+                    }
+                };
+
+                let opened = if initial {
+                    FileIngestor::open(path.clone(), self.poll_interval_ms, parser, self.checkpointing.clone())
+                } else {
+                    FileIngestor::open_new(path.clone(), self.poll_interval_ms, parser, self.checkpointing.clone())
+                };
+
+                match opened {
+                    Ok(ingestor) => {
+                        log::info!("Tailing {} (source {:?})", path.display(), source.label);
+                        self.waiter.watch(&path);
+                        self.known.insert(ingestor.file_id());
+                        self.files.push(TrackedFile {
+                            label: source.label.clone(),
+                            ingestor,
+                        });
+                    }
+                    Err(e) => log::warn!("Cannot open {}: {}", path.display(), e),
+                }
+            }
+
+            if initial && matched == 0 {
+                log::warn!("Ingest source {:?} matches no files yet", source.pattern);
+            }
+        }
+
+        // Inode numbers are reused once a file is deleted.
+        self.known.retain(|id| present.contains(id));
+    }
+}
+
+impl Ingestor for MultiIngestor {
+    fn next_event(&mut self) -> Poll {
+        if self.last_scan.elapsed() >= self.rescan_interval {
+            self.scan(false);
+        }
+
+        let count = self.files.len();
+        for step in 0..count {
+            let idx = (self.cursor + step) % count;
+            let file = &mut self.files[idx];
+
+            match file.ingestor.poll() {
+                Poll::Idle | Poll::Eof => {}
+                Poll::Event(mut event) => {
+                    event.source = file.label.clone();
+                    self.cursor = (idx + 1) % count;
+                    return Poll::Event(event);
+                }
+                other => {
+                    self.cursor = (idx + 1) % count;
+                    return other;
+                }
+            }
+        }
+
+        let until_rescan = self.rescan_interval.saturating_sub(self.last_scan.elapsed());
+        self.waiter.wait(until_rescan);
+        Poll::Idle
+    }
+
+    fn shutdown(&mut self) {
+        for file in &mut self.files {
+            file.ingestor.shutdown();
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::schema::{IngestConfig, IngestSource, JsonFieldMap, ParserFormat};
+    use std::fs::OpenOptions;
+    use std::io::Write;
+    use std::path::Path;
+
+    fn parser_cfg() -> ParserConfig {
+        ParserConfig {
+            format: ParserFormat::NginxCombined,
+            ignore_status: vec![],
+            log_format: None,
+            json: JsonFieldMap::default(),
+            client_ip: None,
+        }
+    }
+
+    fn source(pattern: &Path, label: &str, format: Option<ParserFormat>) -> SourceConfig {
+        SourceConfig {
+            path: pattern.to_string_lossy().into_owned(),
+            format,
+            log_format: None,
+            label: Some(label.into()),
+        }
+    }
+
+    fn append(path: &Path, text: &str) {
+        let mut file = OpenOptions::new().create(true).append(true).open(path).unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+    }
+
+    fn line(path: &str) -> String {
+        format!(
+            "1.2.3.4 - - [02/Oct/2024:00:48:26 +0900] \"GET {} HTTP/1.1\" 404 0 \"-\" \"-\"\n",
+            path
+        )
+    }
+
+    fn drain(ingestor: &mut MultiIngestor) -> Vec<(String, String)> {
+        let mut seen = Vec::new();
+        for _ in 0..20 {
+            if let Poll::Event(event) = ingestor.next_event() {
+                seen.push((event.source.unwrap_or_default(), event.path));
+            }
+        }
+        seen.sort();
+        seen
+    }
+
+    #[test]
+    fn merges_labelled_sources_with_their_own_format() {
+        let dir = tempfile::tempdir().unwrap();
+        let shop = dir.path().join("shop.access.log");
+        let api = dir.path().join("api.json.log");
+        append(&shop, "");
+        append(&api, "");
+
+        let sources = vec![
+            source(&dir.path().join("*.access.log"), "shop", None),
+            source(&api, "api", Some(ParserFormat::Json)),
+        ];
+        let mut ingestor =
+            MultiIngestor::new(&sources, &parser_cfg(), 1, Duration::from_secs(3600), None, WatchMode::Poll).unwrap();
+        assert_eq!(ingestor.file_count(), 2);
+
+        append(&shop, &line("/cart"));
+        append(
+            &api,
+            "{\"remote_addr\":\"5.6.7.8\",\"request\":\"GET /v1/items HTTP/1.1\",\"status\":\"200\",\"time_iso8601\":\"2024-10-02T00:48:26+09:00\"}\n",
+        );
+
+        assert_eq!(
+            drain(&mut ingestor),
+            vec![
+                ("api".to_string(), "/v1/items".to_string()),
+                ("shop".to_string(), "/cart".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn picks_up_new_files_matching_glob() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("a.access.log");
+        append(&first, &line("/existing"));
+
+        let sources = vec![source(&dir.path().join("*.access.log"), "sites", None)];
+        let mut ingestor =
+            MultiIngestor::new(&sources, &parser_cfg(), 1, Duration::ZERO, None, WatchMode::Poll).unwrap();
+        assert_eq!(ingestor.file_count(), 1);
+
+        // Appeared after startup: read from the start.
+        let second = dir.path().join("b.access.log");
+        append(&second, &line("/new-site"));
+
+        assert_eq!(
+            drain(&mut ingestor),
+            vec![("sites".to_string(), "/new-site".to_string())]
+        );
+        assert_eq!(ingestor.file_count(), 2);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn vanished_files_are_no_longer_watched() {
+        let dir = tempfile::tempdir().unwrap();
+        let kept = dir.path().join("a.access.log");
+        let gone = dir.path().join("b.access.log");
+        append(&kept, "");
+        append(&gone, "");
+
+        let sources = vec![source(&dir.path().join("*.access.log"), "sites", None)];
+        let mut ingestor =
+            MultiIngestor::new(&sources, &parser_cfg(), 20, Duration::ZERO, None, WatchMode::Inotify).unwrap();
+        assert_eq!(ingestor.file_count(), 2);
+
+        fs::remove_file(&gone).unwrap();
+        assert_eq!(drain(&mut ingestor), vec![]);
+        assert_eq!(ingestor.file_count(), 1);
+
+        // Every remaining path is watched again: no fallback to polling.
+        let start = Instant::now();
+        ingestor.waiter.wait(Duration::from_millis(300));
+        assert!(start.elapsed() >= Duration::from_millis(250));
+    }
+
+    #[test]
+    fn a_bare_path_is_not_a_glob() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("access[1].log");
+        append(&path, "");
+        append(&dir.path().join("access1.log"), "");
+
+        let ingest = IngestConfig {
+            source: IngestSource::File,
+            path: Some(path.clone()),
+            poll_interval_ms: 1,
+            sources: vec![],
+            glob_rescan_seconds: 0,
+            checkpoint: None,
+            watch: WatchMode::Poll,
+            syslog: None,
+        };
+        let mut ingestor = MultiIngestor::new(
+            &ingest.file_sources(),
+            &parser_cfg(),
+            1,
+            Duration::ZERO,
+            None,
+            WatchMode::Poll,
+        )
+        .unwrap();
+        assert_eq!(ingestor.file_count(), 1);
+
+        append(&path, &line("/bracketed"));
+        assert_eq!(drain(&mut ingestor), vec![(String::new(), "/bracketed".to_string())]);
+    }
+
+    #[test]
+    fn rotated_files_matching_the_glob_are_not_reread() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("access.log");
+        let rotated = dir.path().join("access.log.1");
+        append(&path, "");
+
+        let sources = vec![source(&dir.path().join("access.log*"), "site", None)];
+        let mut ingestor =
+            MultiIngestor::new(&sources, &parser_cfg(), 1, Duration::ZERO, None, WatchMode::Poll).unwrap();
+
+        append(&path, &line("/before"));
+        assert_eq!(drain(&mut ingestor), vec![("site".to_string(), "/before".to_string())]);
+
+        fs::rename(&path, &rotated).unwrap();
+        append(&path, &line("/after"));
+        assert_eq!(drain(&mut ingestor), vec![("site".to_string(), "/after".to_string())]);
+        assert_eq!(ingestor.file_count(), 1);
+
+        // The next rotation deletes the oldest file and renames again.
+        fs::remove_file(&rotated).unwrap();
+        fs::rename(&path, &rotated).unwrap();
+        append(&path, &line("/later"));
+        assert_eq!(drain(&mut ingestor), vec![("site".to_string(), "/later".to_string())]);
+        assert_eq!(ingestor.file_count(), 1);
+    }
+
+    #[test]
+    fn drops_files_whose_path_is_gone() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("a.access.log");
+        let second = dir.path().join("b.access.log");
+        append(&first, "");
+        append(&second, "");
+
+        let sources = vec![source(&dir.path().join("*.access.log"), "sites", None)];
+        let mut ingestor =
+            MultiIngestor::new(&sources, &parser_cfg(), 1, Duration::ZERO, None, WatchMode::Poll).unwrap();
+        assert_eq!(ingestor.file_count(), 2);
+
+        fs::remove_file(&second).unwrap();
+        ingestor.next_event();
+        assert_eq!(ingestor.file_count(), 1);
+    }
+}
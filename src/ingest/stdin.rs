@@ -1,30 +1,125 @@
 use std::io::{self, BufRead};
 
-use crate::parser::{parse_line, ParsedEvent};
-use crate::ingest::Ingestor;
+use crate::parser::LogParser;
+use crate::ingest::{Ingestor, Poll};
 
+/// Read errors in a row after which the stream is treated as closed.
+const MAX_CONSECUTIVE_ERRORS: u32 = 10;
 
 
+/// Reads one log line per call from stdin (or any other stream).
 pub struct StdinIngestor {
-    reader: io::StdinLock<'static>,
+    reader: Box<dyn BufRead>,
+    parser: Box<dyn LogParser>,
+    /// Read errors since the last successful read, not counting lines
+    /// that were consumed but are not UTF-8.
+    failures: u32,
 }
 
 impl StdinIngestor {
-    pub fn new() -> Self {
-        let stdin = Box::leak(Box::new(io::stdin()));
+    pub fn new(parser: Box<dyn LogParser>) -> Self {
+        Self::from_reader(io::stdin().lock(), parser)
+    }
+
+    pub fn from_reader(reader: impl BufRead + 'static, parser: Box<dyn LogParser>) -> Self {
         Self {
-            reader: stdin.lock(),
+            reader: Box::new(reader),
+            parser,
+            failures: 0,
         }
     }
 }
 
 impl Ingestor for StdinIngestor {
-    fn next_event(&mut self) -> Option<ParsedEvent> {
+    /// Blocks until a line arrives; `Eof` once the writer closes the pipe
+    /// or the stream keeps failing.
+    fn next_event(&mut self) -> Poll {
+        if self.failures >= MAX_CONSECUTIVE_ERRORS {
+            log::error!("Giving up on input after {} read errors in a row", self.failures);
+            return Poll::Eof;
+        }
+
         let mut line = String::new();
         match self.reader.read_line(&mut line) {
-            Ok(0) => None,
-            Ok(_) => parse_line(&line),
-            Err(_) => None,
+            Ok(0) => Poll::Eof,
+            Ok(_) => {
+                self.failures = 0;
+                match self.parser.parse(&line) {
+                    Some(event) => Poll::Event(event),
+                    None => Poll::Unparsed(line),
+                }
+            }
+            // The bad line was consumed; the next read starts after it.
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => Poll::Error(e),
+            Err(e) => {
+                self.failures += 1;
+                Poll::Error(e)
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ingest::IngestStats;
+    use crate::parser::NginxCombinedParser;
+    use std::io::{Cursor, Read};
+
+    #[test]
+    fn reports_unparsed_lines_and_eof() {
+        let mut bytes = concat!(
+            "1.2.3.4 - - [02/Oct/2024:00:48:26 +0900] \"GET / HTTP/1.1\" 200 0 \"-\" \"-\"\n",
+            "not an access log line\n",
+        )
+        .as_bytes()
+        .to_vec();
+        // Not UTF-8: a read error, but reading carries on.
+        bytes.extend_from_slice(b"\xff\xfe\n");
+        let mut ingestor =
+            StdinIngestor::from_reader(Cursor::new(bytes), Box::new(NginxCombinedParser));
+
+        let mut stats = IngestStats::default();
+        loop {
+            let poll = ingestor.next_event();
+            stats.record(&poll);
+            if matches!(poll, Poll::Eof) {
+                break;
+            }
+        }
+
+        assert_eq!(
+            stats,
+            IngestStats {
+                events: 1,
+                unparsed: 1,
+                errors: 1,
+            }
+        );
+        assert!(matches!(ingestor.next_event(), Poll::Eof));
+    }
+
+    struct Broken;
+
+    impl Read for Broken {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::other("device gone"))
         }
     }
+
+    #[test]
+    fn gives_up_on_persistent_read_errors() {
+        let mut ingestor = StdinIngestor::from_reader(
+            io::BufReader::new(Broken),
+            Box::new(NginxCombinedParser),
+        );
+
+        let errors = (0..100)
+            .take_while(|_| matches!(ingestor.next_event(), Poll::Error(_)))
+            .count();
+
+        assert_eq!(errors, MAX_CONSECUTIVE_ERRORS as usize);
+        assert!(matches!(ingestor.next_event(), Poll::Eof));
+    }
 }
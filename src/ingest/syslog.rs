@@ -0,0 +1,515 @@
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read};
+use std::net::{SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Context;
+
+use crate::config::schema::SyslogConfig;
+use crate::ingest::{Ingestor, Poll};
+use crate::parser::LogParser;
+
+/// Largest message accepted, the usual UDP payload limit.
+const MAX_MESSAGE: usize = 64 * 1024;
+/// Messages buffered between the socket threads and the pipeline.
+const QUEUE_LEN: usize = 4096;
+/// How often socket threads blocked on input check whether to stop.
+const STOP_CHECK: Duration = Duration::from_millis(500);
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Where the syslog receiver listens, from `ingest.syslog.listen`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Listen {
+    Udp(SocketAddr),
+    Tcp(SocketAddr),
+    /// Unix datagram socket, as used by `syslog:server=unix:/path`.
+    Unix(PathBuf),
+}
+
+impl FromStr for Listen {
+    type Err = anyhow::Error;
+
+    fn from_str(raw: &str) -> anyhow::Result<Self> {
+        let addr = |s: &str| {
+            s.parse::<SocketAddr>()
+                .with_context(|| format!("Invalid ingest.syslog.listen address {:?}", raw))
+        };
+
+        if let Some(rest) = raw.strip_prefix("udp://") {
+            Ok(Listen::Udp(addr(rest)?))
+        } else if let Some(rest) = raw.strip_prefix("tcp://") {
+            Ok(Listen::Tcp(addr(rest)?))
+        } else if let Some(path) = raw.strip_prefix("unix:").filter(|p| !p.is_empty()) {
+            Ok(Listen::Unix(PathBuf::from(path)))
+        } else {
+            anyhow::bail!(
+                "Invalid ingest.syslog.listen {:?}: expected udp://ADDR, tcp://ADDR or unix:PATH",
+                raw
+            )
+        }
+    }
+}
+
+/// The parts of a syslog message Aargal cares about.
+#[derive(Debug, PartialEq, Eq)]
+pub struct SyslogMessage<'a> {
+    /// RFC 3164 TAG (without `[pid]`) or RFC 5424 APP-NAME.
+    pub tag: Option<&'a str>,
+    /// The message itself, i.e. the access log line.
+    pub body: &'a str,
+}
+
+/// Strip the RFC 3164 or RFC 5424 header from one message.
+///
+/// `None` when there is no `<PRI>` or the RFC 5424 structured data is
+/// malformed.
+pub fn parse_message(raw: &str) -> Option<SyslogMessage<'_>> {
+    let raw = raw.trim_end_matches(['\n', '\r', '\0']);
+    let (pri, rest) = raw.strip_prefix('<')?.split_once('>')?;
+    if pri.is_empty() || pri.len() > 3 || !pri.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    match rest.strip_prefix("1 ") {
+        Some(rest) => parse_rfc5424(rest),
+        None => Some(parse_rfc3164(rest)),
+    }
+}
+
+/// `TIMESTAMP HOSTNAME APP-NAME PROCID MSGID STRUCTURED-DATA [MSG]`
+fn parse_rfc5424(rest: &str) -> Option<SyslogMessage<'_>> {
+    let mut fields = rest.splitn(6, ' ');
+    let _timestamp = fields.next()?;
+    let _hostname = fields.next()?;
+    let app = fields.next()?;
+    let _procid = fields.next()?;
+    let _msgid = fields.next()?;
+    let body = skip_structured_data(fields.next().unwrap_or(""))?;
+
+    Some(SyslogMessage {
+        tag: Some(app).filter(|app| *app != "-"),
+        body: body.strip_prefix('\u{feff}').unwrap_or(body),
+    })
+}
+
+/// Skip `-` or a run of `[id key="value" ...]` elements plus the space
+/// after them. Values may contain escaped `\"` and `\]`.
+fn skip_structured_data(s: &str) -> Option<&str> {
+    if let Some(rest) = s.strip_prefix('-') {
+        return Some(rest.strip_prefix(' ').unwrap_or(rest));
+    }
+
+    let mut in_element = false;
+    let mut in_quotes = false;
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match c {
+            '\\' if in_quotes => escaped = true,
+            '"' if in_element => in_quotes = !in_quotes,
+            ']' if in_element && !in_quotes => in_element = false,
+            '[' if !in_element => in_element = true,
+            ' ' if !in_element && i > 0 => return Some(&s[i + 1..]),
+            _ if !in_element => return None,
+            _ => {}
+        }
+    }
+
+    (!in_element && !s.is_empty()).then_some("")
+}
+
+/// `Mmm dd hh:mm:ss [HOSTNAME] TAG: MSG`
+///
+/// Messages from the local syslog(3) have no hostname; without a
+/// recognisable timestamp the whole remainder is the message.
+fn parse_rfc3164(rest: &str) -> SyslogMessage<'_> {
+    let Some(rest) = skip_rfc3164_timestamp(rest) else {
+        return split_tag(rest).unwrap_or(SyslogMessage { tag: None, body: rest });
+    };
+
+    if let Some(message) = split_tag(rest) {
+        return message;
+    }
+    // First word was the hostname.
+    let after_host = rest.split_once(' ').map_or("", |(_, after)| after);
+    split_tag(after_host).unwrap_or(SyslogMessage {
+        tag: None,
+        body: after_host,
+    })
+}
+
+/// `TAG[pid]: MSG` or `TAG: MSG`.
+fn split_tag(s: &str) -> Option<SyslogMessage<'_>> {
+    let (word, body) = s.split_once(' ').unwrap_or((s, ""));
+    let tag = word.strip_suffix(':')?;
+    let tag = tag.split('[').next().unwrap_or(tag);
+    if tag.is_empty() {
+        return None;
+    }
+    Some(SyslogMessage { tag: Some(tag), body })
+}
+
+fn skip_rfc3164_timestamp(s: &str) -> Option<&str> {
+    // "Oct  2 00:48:26 "
+    let bytes = s.as_bytes();
+    if bytes.len() > 16
+        && MONTHS.iter().any(|m| bytes.starts_with(m.as_bytes()))
+        && bytes[3] == b' '
+        && bytes[15] == b' '
+    {
+        return s.get(16..);
+    }
+
+    // Some relays (rsyslog) put an RFC 3339 timestamp here instead.
+    let (word, rest) = s.split_once(' ')?;
+    let looks_iso = word.len() >= 19 && word.as_bytes()[4] == b'-' && word.contains('T');
+    looks_iso.then_some(rest)
+}
+
+/// Read one RFC 6587 frame: octet-counted (`LEN SP MSG`) or terminated
+/// by a newline. `None` at end of stream.
+fn read_frame(reader: &mut impl BufRead) -> io::Result<Option<String>> {
+    let octet_counted = match reader.fill_buf()? {
+        [] => return Ok(None),
+        [first, ..] => first.is_ascii_digit(),
+    };
+
+    let mut frame = Vec::new();
+    if octet_counted {
+        reader.by_ref().take(8).read_until(b' ', &mut frame)?;
+        let len = std::str::from_utf8(&frame)
+            .ok()
+            .and_then(|s| s.trim_end().parse::<usize>().ok())
+            .filter(|len| *len <= MAX_MESSAGE)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid octet count"))?;
+        frame.clear();
+        frame.resize(len, 0);
+        reader.read_exact(&mut frame)?;
+    } else {
+        reader.by_ref().take(MAX_MESSAGE as u64).read_until(b'\n', &mut frame)?;
+    }
+
+    Ok(Some(String::from_utf8_lossy(&frame).into_owned()))
+}
+
+/// Receives access log lines over syslog.
+///
+/// Socket threads push raw messages into a bounded queue; the header is
+/// stripped and the body handed to the configured parser here, so the
+/// rest of the pipeline sees the same events as from a file.
+pub struct SyslogIngestor {
+    messages: Receiver<String>,
+    parser: Box<dyn LogParser>,
+    tag: Option<String>,
+    poll_interval: Duration,
+    local_addr: Option<SocketAddr>,
+    socket_path: Option<PathBuf>,
+    stop: Arc<AtomicBool>,
+}
+
+impl SyslogIngestor {
+    pub fn bind(
+        cfg: &SyslogConfig,
+        poll_interval_ms: u64,
+        parser: Box<dyn LogParser>,
+    ) -> anyhow::Result<Self> {
+        let listen: Listen = cfg.listen.parse()?;
+        let (tx, messages) = mpsc::sync_channel(QUEUE_LEN);
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut local_addr = None;
+        let mut socket_path = None;
+
+        match listen {
+            Listen::Udp(addr) => {
+                let socket = UdpSocket::bind(addr)
+                    .with_context(|| format!("Failed to bind syslog socket {}", addr))?;
+                socket.set_read_timeout(Some(STOP_CHECK))?;
+                local_addr = Some(socket.local_addr()?);
+                spawn_datagram_reader(move |buf| socket.recv(buf), tx, Arc::clone(&stop));
+            }
+            Listen::Tcp(addr) => {
+                let listener = TcpListener::bind(addr)
+                    .with_context(|| format!("Failed to bind syslog socket {}", addr))?;
+                listener.set_nonblocking(true)?;
+                local_addr = Some(listener.local_addr()?);
+                spawn_tcp_acceptor(listener, tx, Arc::clone(&stop));
+            }
+            Listen::Unix(path) => {
+                remove_stale_socket(&path)?;
+                let socket = UnixDatagram::bind(&path)
+                    .with_context(|| format!("Failed to bind syslog socket {}", path.display()))?;
+                socket.set_read_timeout(Some(STOP_CHECK))?;
+                spawn_datagram_reader(move |buf| socket.recv(buf), tx, Arc::clone(&stop));
+                socket_path = Some(path);
+            }
+        }
+
+        log::info!("Receiving syslog on {}", cfg.listen);
+
+        Ok(Self {
+            messages,
+            parser,
+            tag: cfg.tag.clone(),
+            poll_interval: Duration::from_millis(poll_interval_ms),
+            local_addr,
+            socket_path,
+            stop,
+        })
+    }
+
+    /// Bound address of a UDP/TCP listener (useful with port 0).
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+}
+
+fn remove_stale_socket(path: &Path) -> anyhow::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_socket() => fs::remove_file(path)
+            .with_context(|| format!("Failed to remove stale socket {}", path.display())),
+        Ok(_) => anyhow::bail!("{} exists and is not a socket", path.display()),
+        Err(_) => Ok(()),
+    }
+}
+
+fn spawn_datagram_reader<F>(mut recv: F, tx: SyncSender<String>, stop: Arc<AtomicBool>)
+where
+    F: FnMut(&mut [u8]) -> io::Result<usize> + Send + 'static,
+{
+    thread::spawn(move || {
+        let mut buf = vec![0; MAX_MESSAGE];
+        while !stop.load(Ordering::Relaxed) {
+            match recv(&mut buf) {
+                Ok(n) => {
+                    let message = String::from_utf8_lossy(&buf[..n]).into_owned();
+                    if tx.send(message).is_err() {
+                        return;
+                    }
+                }
+                Err(e) if is_timeout(&e) => {}
+                Err(e) => {
+                    log::warn!("Failed to receive syslog message: {}", e);
+                    thread::sleep(STOP_CHECK);
+                }
+            }
+        }
+    });
+}
+
+fn spawn_tcp_acceptor(listener: TcpListener, tx: SyncSender<String>, stop: Arc<AtomicBool>) {
+    thread::spawn(move || {
+        while !stop.load(Ordering::Relaxed) {
+            match listener.accept() {
+                Ok((stream, peer)) => {
+                    log::debug!("Syslog connection from {}", peer);
+                    if let Err(e) = stream.set_nonblocking(false) {
+                        log::warn!("Dropping syslog connection from {}: {}", peer, e);
+                        continue;
+                    }
+                    let tx = tx.clone();
+                    thread::spawn(move || read_stream(stream, tx));
+                }
+                Err(e) if is_timeout(&e) => thread::sleep(Duration::from_millis(100)),
+                Err(e) => {
+                    log::warn!("Failed to accept syslog connection: {}", e);
+                    thread::sleep(STOP_CHECK);
+                }
+            }
+        }
+    });
+}
+
+fn read_stream(stream: TcpStream, tx: SyncSender<String>) {
+    let mut reader = BufReader::new(stream);
+    loop {
+        match read_frame(&mut reader) {
+            Ok(Some(frame)) => {
+                if tx.send(frame).is_err() {
+                    return;
+                }
+            }
+            Ok(None) => return,
+            Err(e) => {
+                log::warn!("Dropping syslog connection: {}", e);
+                return;
+            }
+        }
+    }
+}
+
+fn is_timeout(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut | io::ErrorKind::Interrupted
+    )
+}
+
+impl Ingestor for SyslogIngestor {
+    fn next_event(&mut self) -> Poll {
+        let raw = match self.messages.recv_timeout(self.poll_interval) {
+            Ok(raw) => raw,
+            Err(RecvTimeoutError::Timeout) => return Poll::Idle,
+            // Every socket thread is gone.
+            Err(RecvTimeoutError::Disconnected) => return Poll::Eof,
+        };
+        let Some(message) = parse_message(&raw) else {
+            return Poll::Unparsed(raw);
+        };
+
+        if let Some(tag) = &self.tag {
+            if message.tag != Some(tag.as_str()) {
+                return Poll::Idle;
+            }
+        }
+
+        match self.parser.parse(message.body) {
+            Some(event) => Poll::Event(event),
+            None => Poll::Unparsed(message.body.to_string()),
+        }
+    }
+
+    fn shutdown(&mut self) {
+        if let Some(path) = &self.socket_path {
+            if let Err(e) = fs::remove_file(path) {
+                log::warn!("Failed to remove {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+impl Drop for SyslogIngestor {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::NginxCombinedParser;
+    use std::io::{Cursor, Write};
+
+    const LINE: &str = r#"1.2.3.4 - - [02/Oct/2024:00:48:26 +0900] "GET /cart HTTP/1.1" 404 0 "-" "curl/8.0""#;
+
+    fn ingestor(listen: String, tag: Option<&str>) -> SyslogIngestor {
+        let cfg = SyslogConfig {
+            listen,
+            tag: tag.map(str::to_string),
+        };
+        SyslogIngestor::bind(&cfg, 50, Box::new(NginxCombinedParser)).unwrap()
+    }
+
+    fn next_paths(ingestor: &mut SyslogIngestor, attempts: usize) -> Vec<String> {
+        (0..attempts)
+            .filter_map(|_| match ingestor.next_event() {
+                Poll::Event(event) => Some(event.path),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn strips_rfc3164_header_from_nginx() {
+        let raw = format!("<190>Oct  2 00:48:26 edge1 nginx: {}\n", LINE);
+        assert_eq!(
+            parse_message(&raw),
+            Some(SyslogMessage { tag: Some("nginx"), body: LINE })
+        );
+
+        // Local syslog(3) omits the hostname.
+        assert_eq!(
+            parse_message("<14>Oct 12 01:02:03 app[123]: hello world"),
+            Some(SyslogMessage { tag: Some("app"), body: "hello world" })
+        );
+
+        assert_eq!(parse_message("no priority"), None);
+    }
+
+    #[test]
+    fn strips_rfc5424_header_and_structured_data() {
+        let raw = format!(
+            "<165>1 2024-10-02T00:48:26.003Z edge1 nginx - - [meta x=\"a\\]b\" y=\"c\"][x@1 z=\"\"] \u{feff}{}",
+            LINE
+        );
+        assert_eq!(
+            parse_message(&raw),
+            Some(SyslogMessage { tag: Some("nginx"), body: LINE })
+        );
+
+        assert_eq!(
+            parse_message("<13>1 - - - - - - body"),
+            Some(SyslogMessage { tag: None, body: "body" })
+        );
+        assert_eq!(parse_message("<13>1 - host app - - [unterminated body"), None);
+    }
+
+    #[test]
+    fn reads_octet_counted_and_newline_frames() {
+        let first = "<13>1 - - app - - - one";
+        let stream = format!("{} {}<13>Oct  2 00:48:26 h app: two\n", first.len(), first);
+        let mut reader = Cursor::new(stream.into_bytes());
+
+        assert_eq!(read_frame(&mut reader).unwrap().as_deref(), Some(first));
+        assert_eq!(
+            read_frame(&mut reader).unwrap().as_deref(),
+            Some("<13>Oct  2 00:48:26 h app: two\n")
+        );
+        assert_eq!(read_frame(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_unknown_listen_addresses() {
+        assert!("udp://127.0.0.1:514".parse::<Listen>().is_ok());
+        assert!("tcp://[::1]:514".parse::<Listen>().is_ok());
+        assert!("unix:/run/aargal.sock".parse::<Listen>().is_ok());
+        assert!("127.0.0.1:514".parse::<Listen>().is_err());
+        assert!("udp://localhost:514".parse::<Listen>().is_err());
+        assert!("unix:".parse::<Listen>().is_err());
+    }
+
+    #[test]
+    fn receives_unix_datagrams_and_filters_by_tag() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("syslog.sock");
+        let mut ingestor = ingestor(format!("unix:{}", path.display()), Some("nginx"));
+
+        let client = UnixDatagram::unbound().unwrap();
+        let other = format!("<190>Oct  2 00:48:26 edge1 php-fpm: {}", LINE);
+        let nginx = format!("<190>Oct  2 00:48:26 edge1 nginx: {}", LINE);
+        client.send_to(other.as_bytes(), &path).unwrap();
+        client.send_to(nginx.as_bytes(), &path).unwrap();
+
+        assert_eq!(next_paths(&mut ingestor, 4), vec!["/cart".to_string()]);
+
+        ingestor.shutdown();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn receives_tcp_stream() {
+        let mut ingestor = ingestor("tcp://127.0.0.1:0".into(), None);
+
+        let mut client = TcpStream::connect(ingestor.local_addr().unwrap()).unwrap();
+        for _ in 0..2 {
+            writeln!(client, "<190>Oct  2 00:48:26 edge1 nginx: {}", LINE).unwrap();
+        }
+        drop(client);
+
+        assert_eq!(next_paths(&mut ingestor, 40).len(), 2);
+    }
+}
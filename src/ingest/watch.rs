@@ -0,0 +1,268 @@
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use crate::config::schema::WatchMode;
+
+#[cfg(target_os = "linux")]
+pub use self::inotify_waiter::InotifyWaiter;
+
+/// Puts idle file sources to sleep until a log may have new data.
+///
+/// With inotify the wait ends as soon as a watched file is written
+/// (`IN_MODIFY`), renamed away (`IN_MOVE_SELF`) or a new file appears in
+/// its directory (`IN_CREATE`). Polling simply sleeps for the poll
+/// interval, which is what network filesystems such as NFS need: inotify
+/// only sees writes made by the local kernel.
+pub enum Waiter {
+    Poll(Duration),
+    #[cfg(target_os = "linux")]
+    Inotify(InotifyWaiter),
+}
+
+impl Waiter {
+    pub fn new(mode: WatchMode, poll_interval: Duration) -> anyhow::Result<Self> {
+        match mode {
+            WatchMode::Poll => Ok(Waiter::Poll(poll_interval)),
+            #[cfg(target_os = "linux")]
+            WatchMode::Auto | WatchMode::Inotify => match InotifyWaiter::new(poll_interval) {
+                Ok(waiter) => Ok(Waiter::Inotify(waiter)),
+                Err(e) if mode == WatchMode::Auto => {
+                    log::warn!("inotify unavailable ({}); polling every {:?}", e, poll_interval);
+                    Ok(Waiter::Poll(poll_interval))
+                }
+                Err(e) => Err(anyhow::Error::new(e).context("Failed to initialise inotify")),
+            },
+            #[cfg(not(target_os = "linux"))]
+            WatchMode::Auto => Ok(Waiter::Poll(poll_interval)),
+            #[cfg(not(target_os = "linux"))]
+            WatchMode::Inotify => {
+                anyhow::bail!("ingest.watch = \"inotify\" is only supported on Linux")
+            }
+        }
+    }
+
+    pub fn is_inotify(&self) -> bool {
+        !matches!(self, Waiter::Poll(_))
+    }
+
+    /// Start watching `path` and its directory. No-op when polling.
+    pub fn watch(&mut self, path: &Path) {
+        match self {
+            Waiter::Poll(_) => {}
+            #[cfg(target_os = "linux")]
+            Waiter::Inotify(waiter) => waiter.watch(path),
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = path;
+    }
+
+    /// Stop watching `path`, and its directory once no other watched
+    /// file lives there. No-op when polling.
+    pub fn unwatch(&mut self, path: &Path) {
+        match self {
+            Waiter::Poll(_) => {}
+            #[cfg(target_os = "linux")]
+            Waiter::Inotify(waiter) => waiter.unwatch(path),
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = path;
+    }
+
+    /// Block until a watched file may have changed, for at most `limit`.
+    pub fn wait(&mut self, limit: Duration) {
+        match self {
+            Waiter::Poll(interval) => thread::sleep((*interval).min(limit)),
+            #[cfg(target_os = "linux")]
+            Waiter::Inotify(waiter) => waiter.wait(limit),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod inotify_waiter {
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::io;
+    use std::os::fd::AsRawFd;
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
+
+    use inotify::{Inotify, WatchDescriptor, WatchMask};
+
+    /// Longest a wait blocks without any event, so glob rescans,
+    /// checkpoint saves and shutdown stay responsive.
+    const MAX_WAIT: Duration = Duration::from_secs(1);
+
+    pub struct InotifyWaiter {
+        inotify: Inotify,
+        files: BTreeSet<PathBuf>,
+        /// Watched directories, with their watch once it could be added.
+        dirs: BTreeMap<PathBuf, Option<WatchDescriptor>>,
+        poll_interval: Duration,
+        /// Some path could not be watched (e.g. mid-rotation, or the
+        /// watch limit is reached); don't block longer than polling would.
+        incomplete: bool,
+        buffer: Vec<u8>,
+    }
+
+    impl InotifyWaiter {
+        pub fn new(poll_interval: Duration) -> io::Result<Self> {
+            Ok(Self {
+                inotify: Inotify::init()?,
+                files: BTreeSet::new(),
+                dirs: BTreeMap::new(),
+                poll_interval,
+                incomplete: false,
+                buffer: vec![0; 4096],
+            })
+        }
+
+        pub fn watch(&mut self, path: &Path) {
+            self.files.insert(path.to_path_buf());
+            self.dirs.entry(dir_of(path)).or_insert(None);
+            self.refresh();
+        }
+
+        pub fn unwatch(&mut self, path: &Path) {
+            if !self.files.remove(path) {
+                return;
+            }
+
+            // The kernel drops the watch of a deleted file by itself.
+            let dir = dir_of(path);
+            if !self.files.iter().any(|file| dir_of(file) == dir) {
+                if let Some(Some(wd)) = self.dirs.remove(&dir) {
+                    let _ = self.inotify.watches().remove(wd);
+                }
+            }
+            self.refresh();
+        }
+
+        /// (Re-)add every watch. A file watch follows the inode, so after
+        /// a rotation the path has to be watched again to see the new file.
+        fn refresh(&mut self) {
+            let mut watches = self.inotify.watches();
+            let mut incomplete = false;
+
+            for (dir, watch) in &mut self.dirs {
+                match watches.add(dir, WatchMask::CREATE | WatchMask::MOVED_TO) {
+                    Ok(wd) => *watch = Some(wd),
+                    Err(e) => {
+                        log::debug!("Cannot watch {}: {}", dir.display(), e);
+                        incomplete = true;
+                    }
+                }
+            }
+            for file in &self.files {
+                let mask = WatchMask::MODIFY | WatchMask::MOVE_SELF | WatchMask::DELETE_SELF;
+                if let Err(e) = watches.add(file, mask) {
+                    log::debug!("Cannot watch {}: {}", file.display(), e);
+                    incomplete = true;
+                }
+            }
+
+            self.incomplete = incomplete;
+        }
+
+        pub fn wait(&mut self, limit: Duration) {
+            let cap = if self.incomplete { self.poll_interval } else { MAX_WAIT };
+            let timeout = limit.min(cap).as_millis().min(i32::MAX as u128) as libc::c_int;
+
+            let mut pollfd = libc::pollfd {
+                fd: self.inotify.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            // SAFETY: a single valid pollfd; the inotify fd outlives the call.
+            let ready = unsafe { libc::poll(&mut pollfd, 1, timeout) };
+
+            if ready > 0 {
+                // Only the wakeup matters; whatever is left in the queue
+                // just makes the next wait return at once.
+                match self.inotify.read_events(&mut self.buffer) {
+                    Ok(_) => {}
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                    Err(e) => log::warn!("Failed to read inotify events: {}", e),
+                }
+                self.refresh();
+            } else if self.incomplete {
+                self.refresh();
+            }
+        }
+    }
+
+    fn dir_of(path: &Path) -> PathBuf {
+        match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        }
+    }
+}
+
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use std::time::Instant;
+
+    fn append(path: &Path, text: &str) {
+        let mut file = OpenOptions::new().create(true).append(true).open(path).unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+    }
+
+    /// Time a wait that would otherwise block for a full second.
+    fn timed_wait(waiter: &mut Waiter) -> Duration {
+        let start = Instant::now();
+        waiter.wait(Duration::from_secs(1));
+        start.elapsed()
+    }
+
+    #[test]
+    fn wakes_on_write_instead_of_polling() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("access.log");
+        append(&log, "");
+
+        let mut waiter = Waiter::new(WatchMode::Inotify, Duration::from_secs(60)).unwrap();
+        assert!(waiter.is_inotify());
+        waiter.watch(&log);
+
+        append(&log, "line\n");
+        assert!(timed_wait(&mut waiter) < Duration::from_millis(500));
+    }
+
+    #[test]
+    fn follows_rotation_to_the_new_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("access.log");
+        append(&log, "old\n");
+
+        let mut waiter = Waiter::new(WatchMode::Inotify, Duration::from_secs(60)).unwrap();
+        waiter.watch(&log);
+
+        fs::rename(&log, dir.path().join("access.log.1")).unwrap();
+        append(&log, "");
+        assert!(timed_wait(&mut waiter) < Duration::from_millis(500));
+
+        // Queue drained: nothing to wake us now.
+        let start = Instant::now();
+        waiter.wait(Duration::from_millis(200));
+        assert!(start.elapsed() >= Duration::from_millis(150));
+
+        // Writes to the new file are seen.
+        append(&log, "new\n");
+        assert!(timed_wait(&mut waiter) < Duration::from_millis(500));
+    }
+
+    #[test]
+    fn poll_mode_sleeps_for_the_interval() {
+        let mut waiter = Waiter::new(WatchMode::Poll, Duration::from_millis(50)).unwrap();
+        assert!(!waiter.is_inotify());
+
+        let start = Instant::now();
+        waiter.wait(Duration::from_secs(10));
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+}
@@ -5,14 +5,28 @@ pub mod engine;
 pub mod output;
 pub mod parser;
 pub mod doctor;
+pub mod net;
+pub mod replay;
 // pub mod util;
 
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Context;
+use signal_hook::consts::{SIGINT, SIGTERM};
 
 use crate::config::loader::load_config;
+use crate::engine::filter::RequestFilter;
 use crate::engine::pipeline::process_event;
-use crate::ingest::{Ingestor, file::FileIngestor, stdin::StdinIngestor};
+use crate::engine::signals::Signals;
+use crate::ingest::{IngestStats, Ingestor, Poll, multi::MultiIngestor, stdin::StdinIngestor, syslog::SyslogIngestor};
+use crate::ingest::archive::ArchiveIngestor;
+use crate::ingest::checkpoint::{CheckpointStore, Checkpointing};
+use crate::model::clock::Clock;
 use crate::model::state_store::StateStore;
+use crate::parser::build_parser;
 use crate::config::schema::IngestSource;
 
 pub fn run_daemon(config_path: &Path) -> anyhow::Result<()> {
@@ -21,26 +35,111 @@ pub fn run_daemon(config_path: &Path) -> anyhow::Result<()> {
     let config = load_config(config_path)?;
     println!("Loaded config: {:?}", config);
 
-    let mut state = StateStore::new(config.general.state_ttl_seconds);
+    // Live sources age state by the wall clock; a one-shot archive read
+    // by the time in its logs, like `aargal replay`.
+    let clock = match config.ingest.source {
+        IngestSource::Archive => Clock::event(),
+        _ => Clock::System,
+    };
+    let mut state = StateStore::with_clock(
+        config.general.state_ttl_seconds,
+        config.scoring.rates.windows_seconds.clone(),
+        clock,
+    );
+    state.remember_offences_for(config.scoring.offence_memory_seconds);
+
+    let mut filter = RequestFilter::new(&config.parser, &config.filter)?;
+    let mut signals = Signals::new(&config)?;
 
     let mut ingestor: Box<dyn Ingestor> = match config.ingest.source {
         IngestSource::File => {
-            let file_ingestor = FileIngestor::new(
-                config.ingest.path.clone(),
+            let checkpointing = match &config.ingest.checkpoint {
+                Some(cp) => Some(Checkpointing {
+                    store: Arc::new(Mutex::new(CheckpointStore::load(cp.path.clone())?)),
+                    interval: Duration::from_secs(cp.interval_seconds),
+                    max_backlog_bytes: cp.max_backlog_bytes,
+                }),
+                None => None,
+            };
+            let file_ingestor = MultiIngestor::new(
+                &config.ingest.file_sources(),
+                &config.parser,
                 config.ingest.poll_interval_ms,
+                Duration::from_secs(config.ingest.glob_rescan_seconds),
+                checkpointing,
+                config.ingest.watch,
             )?;
             Box::new(file_ingestor)
         }
         IngestSource::Stdin => {
-            Box::new(StdinIngestor::new())
+            Box::new(StdinIngestor::new(build_parser(&config.parser)?))
+        }
+        IngestSource::Archive => Box::new(ArchiveIngestor::open(
+            &config.ingest.file_sources(),
+            &config.parser,
+        )?),
+        IngestSource::Syslog => {
+            let syslog = config
+                .ingest
+                .syslog
+                .as_ref()
+                .context("ingest.source = \"syslog\" needs [ingest.syslog]")?;
+            Box::new(SyslogIngestor::bind(
+                syslog,
+                config.ingest.poll_interval_ms,
+                build_parser(&config.parser)?,
+            )?)
         }
     };
 
-    loop {
+    // First SIGINT/SIGTERM asks for a clean stop; a second one exits
+    // immediately in case the ingestor is blocked on input.
+    let shutdown = Arc::new(AtomicBool::new(false));
+    for signal in [SIGINT, SIGTERM] {
+        signal_hook::flag::register_conditional_shutdown(signal, 1, Arc::clone(&shutdown))?;
+        signal_hook::flag::register(signal, Arc::clone(&shutdown))?;
+    }
+
+    let poll_interval = Duration::from_millis(config.ingest.poll_interval_ms);
+    let mut stats = IngestStats::default();
+    while !shutdown.load(Ordering::Relaxed) {
         // println!("Inside run deamon loop");
-        if let Some(event) = ingestor.next_event() {
-            println!("INGESTED EVENT: {:?}", event);
-            let _ = process_event(event, &mut state, &config);
+        signals.reload_changed();
+        state.evict_if_due();
+        let poll = ingestor.next_event();
+        stats.record(&poll);
+
+        match poll {
+            Poll::Event(event) => {
+                println!("INGESTED EVENT: {:?}", event);
+                if !filter.accept(&event) {
+                    log::debug!("Filtered event ({})", filter.stats());
+                    continue;
+                }
+                let _ = process_event(event, &mut state, &config, &signals);
+            }
+            Poll::Idle => {}
+            Poll::Unparsed(line) => log::debug!("Unparseable line: {:?}", line.trim_end()),
+            Poll::Error(e) => {
+                log::warn!("Ingest error: {}", e);
+                // Don't spin on an error that persists.
+                std::thread::sleep(poll_interval);
+            }
+            Poll::Eof => {
+                log::info!("Input ended");
+                break;
+            }
         }
     }
+
+    log::info!("Shutting down");
+    ingestor.shutdown();
+    log::info!(
+        "Summary: {}; {} filtered ({}); {} IPs tracked",
+        stats,
+        filter.stats().total(),
+        filter.stats(),
+        state.len()
+    );
+    Ok(())
 }
@@ -2,6 +2,7 @@ use clap::{Parser, Subcommand};
 use std::path::PathBuf;
 
 use aargal::doctor::run_doctor;
+use aargal::replay::{run_compare, run_replay};
 
 #[derive(Parser)]
 #[command(name = "aargal")]
@@ -23,6 +24,29 @@ enum Command {
         #[arg(short, long)]
         config: PathBuf,
     },
+    /// Replay log files through a config without side effects
+    Replay {
+        #[arg(short, long)]
+        config: PathBuf,
+        /// Log files to replay (plain, .gz or .zst)
+        #[arg(required = true)]
+        logs: Vec<PathBuf>,
+        /// List every IP, not only those that would be acted on
+        #[arg(long)]
+        all: bool,
+    },
+    /// Replay log files through two configs and list the IPs that differ
+    Compare {
+        /// Current config
+        #[arg(short, long)]
+        config: PathBuf,
+        /// Proposed config
+        #[arg(short, long)]
+        proposed: PathBuf,
+        /// Log files to replay (plain, .gz or .zst)
+        #[arg(required = true)]
+        logs: Vec<PathBuf>,
+    },
 }
 
 fn main() -> anyhow::Result<()> {
@@ -43,6 +67,12 @@ fn main() -> anyhow::Result<()> {
         Command::Doctor { config } => {
             run_doctor(&config)
         }
+        Command::Replay { config, logs, all } => {
+            run_replay(&config, &logs, all)
+        }
+        Command::Compare { config, proposed, logs } => {
+            run_compare(&config, &proposed, &logs)
+        }
     }
 }
 
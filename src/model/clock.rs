@@ -0,0 +1,102 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Where state timing (first/last seen, TTL eviction) takes "now" from.
+///
+/// The live daemon follows the wall clock. Replays follow the logs, so
+/// the same input always ages and evicts state the same way, and tests
+/// move time by hand instead of sleeping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Clock {
+    /// Wall-clock time.
+    System,
+    /// The newest event timestamp observed so far.
+    Event { now: SystemTime },
+    /// Moves only through [`Clock::set`] and [`Clock::advance`].
+    Manual { now: SystemTime },
+}
+
+impl Clock {
+    /// An event clock that has not observed anything yet.
+    pub fn event() -> Self {
+        Clock::Event { now: UNIX_EPOCH }
+    }
+
+    pub fn manual(start: SystemTime) -> Self {
+        Clock::Manual { now: start }
+    }
+
+    pub fn now(&self) -> SystemTime {
+        match self {
+            Clock::System => SystemTime::now(),
+            Clock::Event { now } | Clock::Manual { now } => *now,
+        }
+    }
+
+    /// Feed an event timestamp. Only an event clock moves, and only
+    /// forwards: late lines do not turn it back.
+    pub fn observe(&mut self, at: SystemTime) {
+        if let Clock::Event { now } = self {
+            *now = (*now).max(at);
+        }
+    }
+
+    /// Set a manual clock; no effect on other clocks.
+    pub fn set(&mut self, to: SystemTime) {
+        if let Clock::Manual { now } = self {
+            *now = to;
+        }
+    }
+
+    /// Move a manual clock forwards; no effect on other clocks.
+    pub fn advance(&mut self, by: Duration) {
+        if let Clock::Manual { now } = self {
+            *now += by;
+        }
+    }
+
+    /// Time elapsed since `earlier`; zero if `earlier` is in the future
+    /// (a late event, or the wall clock stepping back).
+    pub fn since(&self, earlier: SystemTime) -> Duration {
+        self.now().duration_since(earlier).unwrap_or_default()
+    }
+}
+
+/// `secs` seconds after a fixed instant (2023-11-14T22:10:00Z), for tests
+/// that move time by hand.
+#[cfg(test)]
+pub fn at(secs: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(1_699_999_800 + secs)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn event_clock_follows_newest_event() {
+        let mut clock = Clock::event();
+
+        clock.observe(at(10));
+        clock.observe(at(5));
+        assert_eq!(clock.now(), at(10));
+
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(clock.now(), at(10));
+        assert_eq!(clock.since(at(4)), Duration::from_secs(6));
+        assert_eq!(clock.since(at(20)), Duration::ZERO);
+    }
+
+    #[test]
+    fn manual_clock_moves_only_when_told() {
+        let mut clock = Clock::manual(at(0));
+
+        clock.observe(at(100));
+        assert_eq!(clock.now(), at(0));
+
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(clock.now(), at(2));
+        clock.set(at(50));
+        assert_eq!(clock.now(), at(50));
+    }
+}
@@ -1,16 +1,132 @@
-use std::time::{Duration, Instant};
+use std::collections::{HashMap, HashSet};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::model::clock::Clock;
+use crate::model::paths::PathTracker;
+use crate::model::window::WindowCounter;
 use crate::parser::ParsedEvent;
+
+/// Windows used when none are configured: bursts, sustained load and
+/// slow crawls.
+pub const DEFAULT_WINDOWS: [u64; 3] = [10, 60, 600];
+
+/// Distinct user agents remembered per IP; enough to tell rotation from
+/// a household behind one address, small enough to bound memory.
+pub const MAX_TRACKED_USER_AGENTS: usize = 64;
+
+/// Sites remembered per IP for robots.txt fetches and crawl-delay checks.
+pub const MAX_TRACKED_SITES: usize = 16;
+
+/// Requests and errors over one sliding window.
+#[derive(Debug, Clone)]
+pub struct RateWindow {
+    pub requests: WindowCounter,
+    pub errors: WindowCounter,
+}
+
+impl RateWindow {
+    pub fn new(window_secs: u64) -> Self {
+        Self {
+            requests: WindowCounter::new(window_secs),
+            errors: WindowCounter::new(window_secs),
+        }
+    }
+}
+
+/// What the robots.txt signal has learned about a client.
+#[derive(Debug, Clone, Default)]
+pub struct RobotsTrack {
+    /// Sites whose robots.txt it requested: it knows their rules.
+    fetched: HashSet<String>,
+    /// First disallowed path it requested.
+    pub disallowed: Option<String>,
+    /// Requests that came sooner than the site's crawl delay.
+    pub delay_violations: u32,
+    /// Crawl delay of the site it most recently went too fast for.
+    pub crawl_delay: Option<Duration>,
+    /// Log time of the latest request per site.
+    last_request: HashMap<String, SystemTime>,
+}
+
+impl RobotsTrack {
+    /// Note a request for `site`'s robots.txt. Sites past
+    /// [`MAX_TRACKED_SITES`] are not remembered.
+    pub fn mark_fetched(&mut self, site: &str) {
+        if self.fetched.len() < MAX_TRACKED_SITES {
+            self.fetched.insert(site.to_string());
+        }
+    }
+
+    /// Whether it requested `site`'s robots.txt.
+    pub fn fetched(&self, site: &str) -> bool {
+        self.fetched.contains(site)
+    }
+
+    /// Whether it requested any robots.txt.
+    pub fn fetched_any(&self) -> bool {
+        !self.fetched.is_empty()
+    }
+
+    /// Note a request to `site` at `at`, returning the time since the
+    /// previous one. Sites past [`MAX_TRACKED_SITES`] are not followed.
+    pub fn gap_since_last(&mut self, site: &str, at: SystemTime) -> Option<Duration> {
+        if let Some(last) = self.last_request.get_mut(site) {
+            let gap = at.duration_since(*last).unwrap_or_default();
+            *last = (*last).max(at);
+            return Some(gap);
+        }
+        if self.last_request.len() < MAX_TRACKED_SITES {
+            self.last_request.insert(site.to_string(), at);
+        }
+        None
+    }
+}
+
+/// What an `IpState` tracks per scoring profile.
+#[derive(Debug, Clone)]
+pub struct ProfileState {
+    pub windows: Vec<RateWindow>,
+    pub paths: PathTracker,
+    pub tier: Option<usize>,
+}
+
 #[derive(Debug, Clone)]
 pub struct IpState {
-    pub ip: String,
+    pub ip: IpAddr,
 
     /* Counters */
+    /// Lifetime totals, for reporting only; scoring uses `windows`.
     pub request_count: u64,
     pub error_count: u64,
+    /// Sliding windows, one per configured length, for requests outside
+    /// every scoring profile.
+    pub windows: Vec<RateWindow>,
+    /// Decision tier reached outside every scoring profile (an index
+    /// into the tiers that apply there).
+    pub tier: Option<usize>,
+    /// The same windows, paths and tier per scoring profile, created on
+    /// first use, so traffic to one part of a site does not score against
+    /// another.
+    pub profiles: HashMap<String, ProfileState>,
+    /// Bans so far, across profiles; later bans last longer.
+    pub bans: u32,
+    /// Log time the latest ban runs out; `None` if its length is unknown.
+    pub banned_until: Option<SystemTime>,
+    /// Hashes of the distinct non-empty user agents seen.
+    user_agents: HashSet<u64>,
+    /// Paths requested outside every scoring profile.
+    pub paths: PathTracker,
+    /// First trap path this client requested.
+    pub honeypot: Option<String>,
+    pub robots: RobotsTrack,
 
     /* Derived signals */
-    pub last_seen: Instant,
-    pub first_seen: Instant,
+    /// Log time of the newest request; windows are read as of this time.
+    pub last_event: SystemTime,
+    /// Clock time (see [`Clock`]) of the first and latest request.
+    pub last_seen: SystemTime,
+    pub first_seen: SystemTime,
 
     /* Scoring */
     pub score: i32,
@@ -20,12 +136,25 @@ pub struct IpState {
 }
 
 impl IpState {
-    pub fn new(ip: String) -> Self {
-        let now = Instant::now();
+    pub fn new(ip: IpAddr, now: SystemTime) -> Self {
+        Self::with_windows(ip, &DEFAULT_WINDOWS, now)
+    }
+
+    pub fn with_windows(ip: IpAddr, windows: &[u64], now: SystemTime) -> Self {
         Self {
             ip,
             request_count: 0,
             error_count: 0,
+            windows: windows.iter().map(|secs| RateWindow::new(*secs)).collect(),
+            tier: None,
+            profiles: HashMap::new(),
+            bans: 0,
+            banned_until: None,
+            user_agents: HashSet::new(),
+            paths: PathTracker::default(),
+            honeypot: None,
+            robots: RobotsTrack::default(),
+            last_event: UNIX_EPOCH,
             first_seen: now,
             last_seen: now,
             score: 0,
@@ -34,25 +163,134 @@ impl IpState {
     }
 
     pub fn record(&mut self, event: &ParsedEvent) {
-        self.request_count += 1;
+        self.record_in(event, None);
+    }
 
-        self.last_seen = Instant::now();
+    /// Record `event`, counting it in the windows of scoring profile
+    /// `profile` (`None`: the top-level `[scoring]`).
+    pub fn record_in(&mut self, event: &ParsedEvent, profile: Option<&str>) {
+        let at = event.timestamp;
+        let error = event.status >= 400;
 
-        if event.status >= 400 {
+        for window in self.windows_mut(profile) {
+            window.requests.add(at);
+            if error {
+                window.errors.add(at);
+            }
+        }
+        self.request_count += 1;
+        if error {
             self.error_count += 1;
         }
+        self.touch(at);
+
+        if let Some(ua) = event.user_agent.as_deref() {
+            self.record_user_agent(ua);
+        }
+
+        match profile {
+            None => self.paths.record(&event.path),
+            Some(name) => self.profile_mut(name).paths.record(&event.path),
+        }
+    }
+
+    pub fn record_user_agent(&mut self, ua: &str) {
+        if ua.is_empty() || ua == "-" || self.user_agents.len() >= MAX_TRACKED_USER_AGENTS {
+            return;
+        }
+        let mut hasher = DefaultHasher::new();
+        ua.hash(&mut hasher);
+        self.user_agents.insert(hasher.finish());
+    }
+
+    /// Windows of scoring profile `profile`; empty for a profile this
+    /// client has not used.
+    pub fn windows_for(&self, profile: Option<&str>) -> &[RateWindow] {
+        match profile {
+            None => &self.windows,
+            Some(name) => self.profiles.get(name).map_or(&[], |p| p.windows.as_slice()),
+        }
+    }
+
+    /// Paths requested under scoring profile `profile`; `None` for a
+    /// profile this client has not used.
+    pub fn paths_for(&self, profile: Option<&str>) -> Option<&PathTracker> {
+        match profile {
+            None => Some(&self.paths),
+            Some(name) => self.profiles.get(name).map(|p| &p.paths),
+        }
+    }
+
+    /// Decision tier the client is in under scoring profile `profile`.
+    pub fn tier(&self, profile: Option<&str>) -> Option<usize> {
+        match profile {
+            None => self.tier,
+            Some(name) => self.profiles.get(name).and_then(|p| p.tier),
+        }
+    }
+
+    pub fn set_tier(&mut self, profile: Option<&str>, tier: Option<usize>) {
+        match profile {
+            None => self.tier = tier,
+            Some(name) => self.profile_mut(name).tier = tier,
+        }
+    }
+
+    fn windows_mut(&mut self, profile: Option<&str>) -> &mut Vec<RateWindow> {
+        match profile {
+            None => &mut self.windows,
+            Some(name) => &mut self.profile_mut(name).windows,
+        }
+    }
+
+    fn profile_mut(&mut self, name: &str) -> &mut ProfileState {
+        if !self.profiles.contains_key(name) {
+            let windows = self
+                .windows
+                .iter()
+                .map(|w| RateWindow::new(w.requests.window_secs()))
+                .collect();
+            self.profiles
+                .insert(name.to_string(), ProfileState {
+                    windows,
+                    paths: PathTracker::default(),
+                    tier: None,
+                });
+        }
+        self.profiles.get_mut(name).expect("inserted above")
+    }
+
+    /// Distinct user agents seen, up to [`MAX_TRACKED_USER_AGENTS`].
+    pub fn distinct_user_agents(&self) -> usize {
+        self.user_agents.len()
     }
 
     #[inline]
-    pub fn record_request(&mut self) {
+    pub fn record_request(&mut self, at: SystemTime) {
         self.request_count += 1;
-        self.last_seen = Instant::now();
+        for window in &mut self.windows {
+            window.requests.add(at);
+        }
+        self.touch(at);
     }
 
     #[inline]
-    pub fn record_error(&mut self) {
+    pub fn record_error(&mut self, at: SystemTime) {
         self.error_count += 1;
-        self.last_seen = Instant::now();
+        for window in &mut self.windows {
+            window.errors.add(at);
+        }
+        self.touch(at);
+    }
+
+    fn touch(&mut self, at: SystemTime) {
+        self.last_event = self.last_event.max(at);
+    }
+
+    /// Note activity at clock time `now`, for TTL eviction.
+    #[inline]
+    pub fn seen(&mut self, now: SystemTime) {
+        self.last_seen = self.last_seen.max(now);
     }
 
     #[inline]
@@ -66,8 +304,8 @@ impl IpState {
     }
 
     #[inline]
-    pub fn age(&self) -> Duration {
-        Instant::now().duration_since(self.last_seen)
+    pub fn age(&self, clock: &Clock) -> Duration {
+        clock.since(self.last_seen)
     }
 }
 
@@ -75,41 +313,115 @@ impl IpState {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::model::clock::at;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
 
     #[test]
     fn ip_state_initializes_correctly() {
-        let state = IpState::new("1.2.3.4".to_string());
+        let state = IpState::new(ip("1.2.3.4"), at(0));
 
-        assert_eq!(state.ip, "1.2.3.4");
+        assert_eq!(state.ip, ip("1.2.3.4"));
         assert_eq!(state.request_count, 0);
         assert_eq!(state.error_count, 0);
+        assert_eq!(state.windows.len(), DEFAULT_WINDOWS.len());
         assert_eq!(state.score, 0);
         assert!(!state.blocked);
     }
 
     #[test]
     fn recording_requests_updates_counters() {
-        let mut state = IpState::new("1.2.3.4".to_string());
+        let mut state = IpState::new(ip("1.2.3.4"), at(0));
 
-        state.record_request();
-        state.record_request();
+        state.record_request(at(0));
+        state.record_request(at(1));
 
         assert_eq!(state.request_count, 2);
         assert_eq!(state.error_count, 0);
+        assert_eq!(state.last_event, at(1));
     }
 
     #[test]
     fn recording_errors_updates_counters() {
-        let mut state = IpState::new("1.2.3.4".to_string());
+        let mut state = IpState::new(ip("1.2.3.4"), at(0));
 
-        state.record_error();
+        state.record_error(at(0));
 
         assert_eq!(state.error_count, 1);
     }
 
+    #[test]
+    fn windows_forget_old_requests_but_totals_do_not() {
+        let mut state = IpState::with_windows(ip("1.2.3.4"), &[10, 60], at(0));
+
+        for s in 0..5 {
+            state.record_request(at(s));
+        }
+        state.record_request(at(30));
+
+        let now = state.last_event;
+        assert_eq!(state.request_count, 6);
+        assert_eq!(state.windows[0].requests.count(now), 1);
+        assert_eq!(state.windows[1].requests.count(now), 6);
+    }
+
+    #[test]
+    fn profiles_count_in_their_own_windows() {
+        let mut state = IpState::with_windows(ip("1.2.3.4"), &[10, 60], at(0));
+        let event = |path: &str, status: u16, secs: u64| ParsedEvent {
+            status,
+            ..ParsedEvent::test("1.2.3.4", path, at(secs))
+        };
+
+        for s in 0..8 {
+            state.record_in(&event("/api/items", 429, s), Some("api"));
+        }
+        state.record(&event("/", 200, 9));
+
+        let now = state.last_event;
+        let api = state.windows_for(Some("api"));
+        assert_eq!(api.len(), 2);
+        assert_eq!((api[0].requests.count(now), api[0].errors.count(now)), (8, 8));
+        assert_eq!(state.windows_for(None)[0].requests.count(now), 1);
+        assert_eq!(state.windows_for(None)[0].errors.count(now), 0);
+        assert!(state.windows_for(Some("login")).is_empty());
+        assert_eq!((state.request_count, state.error_count), (9, 8));
+    }
+
+    #[test]
+    fn counts_distinct_user_agents_up_to_a_bound() {
+        let mut state = IpState::new(ip("1.2.3.4"), at(0));
+
+        state.record_user_agent("Mozilla/5.0 (X11)");
+        state.record_user_agent("Mozilla/5.0 (X11)");
+        state.record_user_agent("-");
+        state.record_user_agent("curl/8.5.0");
+        assert_eq!(state.distinct_user_agents(), 2);
+
+        for n in 0..100 {
+            state.record_user_agent(&format!("agent/{}", n));
+        }
+        assert_eq!(state.distinct_user_agents(), MAX_TRACKED_USER_AGENTS);
+    }
+
+    #[test]
+    fn age_follows_the_clock() {
+        let mut clock = Clock::manual(at(0));
+        let mut state = IpState::new(ip("1.2.3.4"), clock.now());
+
+        clock.advance(Duration::from_secs(30));
+        state.seen(clock.now());
+        clock.advance(Duration::from_secs(5));
+
+        assert_eq!(state.first_seen, at(0));
+        assert_eq!(state.age(&clock), Duration::from_secs(5));
+    }
+
     #[test]
     fn score_accumulates_correctly() {
-        let mut state = IpState::new("1.2.3.4".to_string());
+        let mut state = IpState::new(ip("1.2.3.4"), at(0));
 
         state.add_score(10);
         state.add_score(-3);
@@ -119,11 +431,10 @@ mod tests {
 
     #[test]
     fn blocking_flag_is_set() {
-        let mut state = IpState::new("1.2.3.4".to_string());
+        let mut state = IpState::new(ip("1.2.3.4"), at(0));
 
         assert!(!state.blocked);
         state.mark_blocked();
         assert!(state.blocked);
     }
 }
-
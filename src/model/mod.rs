@@ -1,2 +1,5 @@
+pub mod clock;
 pub mod ip_state;
+pub mod paths;
 pub mod state_store;
+pub mod window;
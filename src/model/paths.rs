@@ -0,0 +1,182 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+/// Distinct paths remembered per IP. Past this, every path not already
+/// remembered is counted as new, which can only overstate breadth for
+/// clients that have already made thousands of distinct requests.
+pub const MAX_TRACKED_PATHS: usize = 4096;
+
+/// Path templates (`/item/{}`) followed for ID enumeration per IP.
+pub const MAX_TRACKED_SEQUENCES: usize = 64;
+
+#[derive(Debug, Clone, Copy)]
+struct Sequence {
+    last: u64,
+    run: u32,
+}
+
+/// What an IP's request paths look like: how many are distinct, whether
+/// it walked a sitemap and how far it counted through numeric IDs.
+#[derive(Debug, Clone, Default)]
+pub struct PathTracker {
+    seen: HashSet<u64>,
+    distinct: u64,
+    total: u64,
+    sitemap: bool,
+    sequences: HashMap<u64, Sequence>,
+    longest_run: u32,
+}
+
+fn hash(value: impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Split `path` around its last number: `/item/42/reviews` becomes
+/// (`/item/{}/reviews`, 42), `/list?page=7` becomes (`/list?page={}`, 7).
+fn split_id(path: &str) -> Option<(u64, u64)> {
+    let bytes = path.as_bytes();
+    let end = bytes.iter().rposition(u8::is_ascii_digit)? + 1;
+    let start = bytes[..end]
+        .iter()
+        .rposition(|b| !b.is_ascii_digit())
+        .map_or(0, |i| i + 1);
+
+    let id = path[start..end].parse().ok()?;
+    Some((hash((&path[..start], &path[end..])), id))
+}
+
+fn is_sitemap(path: &str) -> bool {
+    let path = path.split('?').next().unwrap_or(path).to_ascii_lowercase();
+    path.contains("sitemap") && (path.ends_with(".xml") || path.ends_with(".xml.gz"))
+}
+
+impl PathTracker {
+    pub fn record(&mut self, path: &str) {
+        self.total += 1;
+
+        let key = hash(path);
+        if !self.seen.contains(&key) {
+            self.distinct += 1;
+            if self.seen.len() < MAX_TRACKED_PATHS {
+                self.seen.insert(key);
+            }
+        }
+
+        if is_sitemap(path) {
+            self.sitemap = true;
+        }
+
+        if let Some((template, id)) = split_id(path) {
+            self.record_id(template, id);
+        }
+    }
+
+    fn record_id(&mut self, template: u64, id: u64) {
+        if !self.sequences.contains_key(&template)
+            && self.sequences.len() >= MAX_TRACKED_SEQUENCES
+        {
+            return;
+        }
+
+        let seq = self
+            .sequences
+            .entry(template)
+            .or_insert(Sequence { last: id, run: 1 });
+
+        // Counting up or down by one extends the run; a repeat (reload)
+        // leaves it alone; anything else starts over.
+        if id.abs_diff(seq.last) == 1 {
+            seq.run += 1;
+        } else if id != seq.last {
+            seq.run = 1;
+        }
+        seq.last = id;
+        self.longest_run = self.longest_run.max(seq.run);
+    }
+
+    pub fn distinct(&self) -> u64 {
+        self.distinct
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Share of requests that went to a path not requested before.
+    pub fn unique_ratio(&self) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        self.distinct as f64 / self.total as f64
+    }
+
+    pub fn fetched_sitemap(&self) -> bool {
+        self.sitemap
+    }
+
+    /// Longest run of consecutive IDs requested under one path template.
+    pub fn longest_run(&self) -> u32 {
+        self.longest_run
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_distinct_paths_and_ratio() {
+        let mut paths = PathTracker::default();
+        for path in ["/", "/about", "/", "/contact", "/"] {
+            paths.record(path);
+        }
+
+        assert_eq!(paths.total(), 5);
+        assert_eq!(paths.distinct(), 3);
+        assert!((paths.unique_ratio() - 0.6).abs() < 1e-9);
+        assert!(!paths.fetched_sitemap());
+    }
+
+    #[test]
+    fn follows_sequential_ids_per_template() {
+        let mut paths = PathTracker::default();
+        for id in 100..110 {
+            paths.record(&format!("/item/{}", id));
+            // Interleaved browsing of another template does not break it.
+            paths.record("/search?page=1");
+        }
+        paths.record("/item/109");
+        assert_eq!(paths.longest_run(), 10);
+
+        paths.record("/item/500");
+        paths.record("/item/501");
+        assert_eq!(paths.longest_run(), 10);
+
+        for page in (1..=12).rev() {
+            paths.record(&format!("/list?page={}&sort=asc", page));
+        }
+        assert_eq!(paths.longest_run(), 12);
+    }
+
+    #[test]
+    fn splits_around_last_number() {
+        assert_eq!(split_id("/item/42/reviews").map(|(_, id)| id), Some(42));
+        assert_eq!(split_id("/item/42/reviews").map(|(t, _)| t), split_id("/item/7/reviews").map(|(t, _)| t));
+        assert_ne!(split_id("/item/42").map(|(t, _)| t), split_id("/user/42").map(|(t, _)| t));
+        assert_eq!(split_id("/about"), None);
+        assert_eq!(split_id("/x/99999999999999999999999"), None);
+    }
+
+    #[test]
+    fn detects_sitemaps() {
+        let mut paths = PathTracker::default();
+        paths.record("/sitemaps/products-3.xml.gz");
+
+        assert!(paths.fetched_sitemap());
+        assert!(!is_sitemap("/sitemap"));
+        assert!(is_sitemap("/Sitemap_Index.xml?v=2"));
+    }
+}
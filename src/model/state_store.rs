@@ -1,42 +1,89 @@
 use std::collections::HashMap;
-use std::time::Duration;
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime};
 use crate::parser::ParsedEvent;
-use super::ip_state::IpState;
+use super::clock::Clock;
+use super::ip_state::{IpState, DEFAULT_WINDOWS};
+
+/// Clock time between two sweeps of [`StateStore::evict_if_due`].
+pub const EVICTION_INTERVAL: Duration = Duration::from_secs(60);
 
 #[derive(Debug)]
 pub struct StateStore {
-    states: HashMap<String, IpState>,
+    states: HashMap<IpAddr, IpState>,
     ttl: Duration,
+    /// TTL of clients that were banned, so repeat bans escalate.
+    offence_ttl: Duration,
+    /// Rate window lengths given to every new `IpState`.
+    windows: Vec<u64>,
+    clock: Clock,
+    last_eviction: SystemTime,
 }
 
 impl StateStore {
+    /// Default windows on the system clock.
     pub fn new(ttl_seconds: u64) -> Self {
+        Self::with_clock(ttl_seconds, DEFAULT_WINDOWS.to_vec(), Clock::System)
+    }
+
+    pub fn with_clock(ttl_seconds: u64, windows: Vec<u64>, clock: Clock) -> Self {
         Self {
             states: HashMap::new(),
             ttl: Duration::from_secs(ttl_seconds),
+            offence_ttl: Duration::from_secs(ttl_seconds),
+            windows,
+            clock,
+            last_eviction: clock.now(),
         }
     }
 
+    /// Keep clients that were banned for `seconds` after their last
+    /// request (never less than the TTL).
+    pub fn remember_offences_for(&mut self, seconds: u64) {
+        self.offence_ttl = self.ttl.max(Duration::from_secs(seconds));
+    }
+
+    pub fn clock(&self) -> &Clock {
+        &self.clock
+    }
+
+    pub fn clock_mut(&mut self) -> &mut Clock {
+        &mut self.clock
+    }
+
     /// Get or create state for IP
-    pub fn get_or_create(&mut self, ip: &str) -> &mut IpState {
+    pub fn get_or_create(&mut self, ip: IpAddr) -> &mut IpState {
+        let (windows, now) = (&self.windows, self.clock.now());
         self.states
-            .entry(ip.to_string())
-            .or_insert_with(|| IpState::new(ip.to_string()))
+            .entry(ip)
+            .or_insert_with(|| IpState::with_windows(ip, windows, now))
     }
 
     /// Read-only access (used by scoring / output)
-    pub fn get(&self, ip: &str) -> Option<&IpState> {
+    pub fn get(&self, ip: &IpAddr) -> Option<&IpState> {
         self.states.get(ip)
     }
 
     /// Remove expired IP states
     pub fn evict_expired(&mut self) {
-        let ttl = self.ttl;
-        self.states.retain(|_, state| state.age() <= ttl);
+        let (ttl, offence_ttl, clock) = (self.ttl, self.offence_ttl, &self.clock);
+        self.states.retain(|_, state| {
+            state.age(clock) <= if state.bans > 0 { offence_ttl } else { ttl }
+        });
+    }
+
+    /// Evict expired states once every [`EVICTION_INTERVAL`] of clock
+    /// time. Called from the ingest loops, so a replay on the event clock
+    /// evicts exactly when the daemon would have.
+    pub fn evict_if_due(&mut self) {
+        if self.clock.since(self.last_eviction) >= EVICTION_INTERVAL {
+            self.evict_expired();
+            self.last_eviction = self.clock.now();
+        }
     }
 
     /// Mark an IP as blocked (decision already made upstream)
-    pub fn mark_blocked(&mut self, ip: &str) {
+    pub fn mark_blocked(&mut self, ip: &IpAddr) {
         if let Some(state) = self.states.get_mut(ip) {
             state.mark_blocked();
         }
@@ -51,31 +98,43 @@ impl StateStore {
         self.states.is_empty()
     }
 
-    pub fn update(&mut self, event: &ParsedEvent) -> &IpState {
-    let state = self.states
-        .entry(event.ip.clone())
-        .or_insert_with(|| IpState::new(event.ip.clone()));
+    pub fn update(&mut self, event: &ParsedEvent) -> &mut IpState {
+        self.update_in(event, None)
+    }
 
-    state.record(event);
-    state
-}
+    /// Like [`update`](Self::update), counting `event` in the windows of
+    /// scoring profile `profile`.
+    pub fn update_in(&mut self, event: &ParsedEvent, profile: Option<&str>) -> &mut IpState {
+        self.clock.observe(event.timestamp);
+        let (windows, now) = (&self.windows, self.clock.now());
+        let state = self.states
+            .entry(event.ip)
+            .or_insert_with(|| IpState::with_windows(event.ip, windows, now));
+
+        state.record_in(event, profile);
+        state.seen(now);
+        state
+    }
 }
 
-
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::thread::sleep;
-    use std::time::Duration;
+    use crate::model::clock::at;
+    use std::time::{Duration, SystemTime};
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
 
     #[test]
     fn creates_and_retrieves_ip_state() {
         let mut store = StateStore::new(60);
 
-        let state = store.get_or_create("1.2.3.4");
-        state.record_request();
+        let state = store.get_or_create(ip("1.2.3.4"));
+        state.record_request(SystemTime::now());
 
-        let retrieved = store.get("1.2.3.4").unwrap();
+        let retrieved = store.get(&ip("1.2.3.4")).unwrap();
         assert_eq!(retrieved.request_count, 1);
     }
 
@@ -83,10 +142,10 @@ mod tests {
     fn same_ip_returns_same_state() {
         let mut store = StateStore::new(60);
 
-        store.get_or_create("1.2.3.4").record_request();
-        store.get_or_create("1.2.3.4").record_request();
+        store.get_or_create(ip("1.2.3.4")).record_request(SystemTime::now());
+        store.get_or_create(ip("1.2.3.4")).record_request(SystemTime::now());
 
-        let state = store.get("1.2.3.4").unwrap();
+        let state = store.get(&ip("1.2.3.4")).unwrap();
         assert_eq!(state.request_count, 2);
     }
 
@@ -94,33 +153,92 @@ mod tests {
     fn state_store_tracks_multiple_ips() {
         let mut store = StateStore::new(60);
 
-        store.get_or_create("1.1.1.1");
-        store.get_or_create("2.2.2.2");
+        store.get_or_create(ip("1.1.1.1"));
+        store.get_or_create(ip("2.2.2.2"));
 
         assert_eq!(store.len(), 2);
     }
 
     #[test]
     fn expired_states_are_evicted() {
-        let mut store = StateStore::new(1); // 1 second TTL
+        // 1 second TTL
+        let mut store = StateStore::with_clock(1, DEFAULT_WINDOWS.to_vec(), Clock::manual(at(0)));
 
-        store.get_or_create("1.2.3.4");
+        store.get_or_create(ip("1.2.3.4"));
         assert_eq!(store.len(), 1);
 
-        sleep(Duration::from_secs(2));
+        store.clock_mut().advance(Duration::from_secs(2));
         store.evict_expired();
 
         assert_eq!(store.len(), 0);
     }
 
+    #[test]
+    fn event_clock_ages_state_by_log_time() {
+        let mut store = StateStore::with_clock(60, DEFAULT_WINDOWS.to_vec(), Clock::event());
+
+        store.update(&ParsedEvent::test("1.1.1.1", "/", at(0)));
+        store.update(&ParsedEvent::test("2.2.2.2", "/", at(30)));
+        store.evict_expired();
+        assert_eq!(store.len(), 2);
+
+        // An hour later in the logs, however fast the replay runs.
+        store.update(&ParsedEvent::test("2.2.2.2", "/", at(3600)));
+        store.evict_expired();
+
+        assert_eq!(store.len(), 1);
+        assert_eq!(store.get(&ip("2.2.2.2")).unwrap().first_seen, at(30));
+        assert_eq!(store.get(&ip("2.2.2.2")).unwrap().last_seen, at(3600));
+    }
+
+    #[test]
+    fn banned_clients_outlive_the_ttl() {
+        let mut store = StateStore::with_clock(60, DEFAULT_WINDOWS.to_vec(), Clock::manual(at(0)));
+        store.remember_offences_for(3600);
+
+        store.get_or_create(ip("1.1.1.1"));
+        store.get_or_create(ip("2.2.2.2")).bans = 1;
+
+        store.clock_mut().advance(Duration::from_secs(120));
+        store.evict_expired();
+        assert!(store.get(&ip("1.1.1.1")).is_none());
+        assert_eq!(store.get(&ip("2.2.2.2")).unwrap().bans, 1);
+
+        store.clock_mut().advance(Duration::from_secs(3600));
+        store.evict_expired();
+        assert!(store.is_empty());
+    }
+
+    #[test]
+    fn evicts_once_per_interval_of_clock_time() {
+        let mut store = StateStore::with_clock(60, DEFAULT_WINDOWS.to_vec(), Clock::manual(at(0)));
+        store.get_or_create(ip("1.1.1.1"));
+
+        store.clock_mut().set(at(61));
+        store.evict_if_due();
+        assert!(store.is_empty());
+
+        store.get_or_create(ip("2.2.2.2"));
+        store.clock_mut().set(at(200));
+        store.evict_if_due();
+        assert!(store.is_empty());
+
+        // Expired, but the last sweep was less than an interval ago.
+        store.clock_mut().set(at(130));
+        store.get_or_create(ip("3.3.3.3"));
+        store.clock_mut().set(at(255));
+        store.evict_if_due();
+        assert_eq!(store.len(), 1);
+    }
+
     #[test]
     fn mark_blocked_sets_flag() {
         let mut store = StateStore::new(60);
 
-        store.get_or_create("5.6.7.8");
-        store.mark_blocked("5.6.7.8");
+        store.get_or_create(ip("5.6.7.8"));
+        store.mark_blocked(&ip("5.6.7.8"));
 
-        let state = store.get("5.6.7.8").unwrap();
+        let state = store.get(&ip("5.6.7.8")).unwrap();
         assert!(state.blocked);
     }
 
@@ -129,7 +247,7 @@ mod tests {
         let mut store = StateStore::new(60);
 
         // Should not panic
-        store.mark_blocked("9.9.9.9");
+        store.mark_blocked(&ip("9.9.9.9"));
         assert!(store.is_empty());
     }
 }
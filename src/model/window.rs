@@ -0,0 +1,154 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Buckets per window: counts slide in steps of a tenth of the window.
+const BUCKETS: u64 = 10;
+
+/// Counts events over the last `window` seconds.
+///
+/// The window is split into (at most) ten buckets, so memory stays
+/// constant whatever the rate and the window slides with a precision of
+/// one bucket. Time comes from the caller (the event timestamp), which
+/// makes replays and tests independent of the wall clock.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WindowCounter {
+    /// Length asked for; the buckets may cover a little more.
+    window_secs: u64,
+    bucket_secs: u64,
+    buckets: Vec<u32>,
+    /// Bucket number (seconds since the epoch / `bucket_secs`) of the
+    /// newest bucket.
+    head: u64,
+}
+
+impl WindowCounter {
+    pub fn new(window_secs: u64) -> Self {
+        let window_secs = window_secs.max(1);
+        let bucket_secs = window_secs.div_ceil(BUCKETS);
+        let buckets = window_secs.div_ceil(bucket_secs);
+
+        Self {
+            window_secs,
+            bucket_secs,
+            buckets: vec![0; buckets as usize],
+            head: 0,
+        }
+    }
+
+    /// Configured length of the window, as named in rules and reasons.
+    pub fn window_secs(&self) -> u64 {
+        self.window_secs
+    }
+
+    /// Length actually covered (rounded up to whole buckets).
+    fn covered_secs(&self) -> u64 {
+        self.bucket_secs * self.buckets.len() as u64
+    }
+
+    fn bucket_of(&self, at: SystemTime) -> u64 {
+        let secs = at.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        secs / self.bucket_secs
+    }
+
+    fn slot(&self, bucket: u64) -> usize {
+        (bucket % self.buckets.len() as u64) as usize
+    }
+
+    /// Count one event at `at`. Slightly late events still land in their
+    /// bucket; events older than the window are dropped.
+    pub fn add(&mut self, at: SystemTime) {
+        let bucket = self.bucket_of(at);
+        let len = self.buckets.len() as u64;
+
+        if bucket > self.head {
+            let stale = (bucket - self.head).min(len);
+            for step in 0..stale {
+                let slot = self.slot(bucket - step);
+                self.buckets[slot] = 0;
+            }
+            self.head = bucket;
+        } else if self.head - bucket >= len {
+            return;
+        }
+
+        let slot = self.slot(bucket);
+        self.buckets[slot] = self.buckets[slot].saturating_add(1);
+    }
+
+    /// Events in the window ending at `now`.
+    pub fn count(&self, now: SystemTime) -> u64 {
+        let now = self.bucket_of(now);
+        let len = self.buckets.len() as u64;
+
+        let newest = now.min(self.head);
+        let oldest = (now + 1).saturating_sub(len).max((self.head + 1).saturating_sub(len));
+        if newest < oldest {
+            return 0;
+        }
+
+        (oldest..=newest)
+            .map(|bucket| self.buckets[self.slot(bucket)] as u64)
+            .sum()
+    }
+
+    /// Events per second over the window ending at `now`.
+    pub fn per_second(&self, now: SystemTime) -> f64 {
+        self.count(now) as f64 / self.covered_secs() as f64
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::clock::at;
+
+    #[test]
+    fn counts_within_window_and_forgets_older_events() {
+        let mut counter = WindowCounter::new(10);
+        for s in 0..5 {
+            counter.add(at(s));
+        }
+        assert_eq!(counter.count(at(4)), 5);
+        assert_eq!(counter.count(at(9)), 5);
+
+        // Seconds 0 and 1 have slid out.
+        counter.add(at(11));
+        assert_eq!(counter.count(at(11)), 4);
+
+        assert_eq!(counter.count(at(100)), 0);
+        counter.add(at(100));
+        assert_eq!(counter.count(at(100)), 1);
+    }
+
+    #[test]
+    fn uses_coarser_buckets_for_long_windows() {
+        let mut counter = WindowCounter::new(600);
+        assert_eq!(counter.window_secs(), 600);
+
+        for s in 0..120 {
+            counter.add(at(s));
+        }
+        assert_eq!(counter.count(at(119)), 120);
+        assert!((counter.per_second(at(119)) - 0.2).abs() < 1e-9);
+
+        // Ten minutes on, the first minute of the burst has slid out.
+        assert_eq!(counter.count(at(659)), 60);
+    }
+
+    #[test]
+    fn reports_the_configured_length() {
+        let counter = WindowCounter::new(15);
+        assert_eq!(counter.window_secs(), 15);
+        assert_eq!(counter.covered_secs(), 16);
+    }
+
+    #[test]
+    fn late_events_land_in_their_bucket() {
+        let mut counter = WindowCounter::new(10);
+        counter.add(at(20));
+        counter.add(at(15));
+        counter.add(at(5));
+
+        assert_eq!(counter.count(at(20)), 2);
+    }
+}
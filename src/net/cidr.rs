@@ -0,0 +1,146 @@
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+/// An IPv4 or IPv6 network in CIDR notation (`10.0.0.0/8`, `2001:db8::/32`).
+///
+/// A bare address is a single-host network. Host bits are masked off on
+/// parse, so `10.1.2.3/8` and `10.0.0.0/8` compare equal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum CidrError {
+    #[error("invalid address in {0:?}")]
+    Address(String),
+    #[error("invalid prefix length in {0:?}")]
+    Prefix(String),
+}
+
+impl Cidr {
+    pub fn new(addr: IpAddr, prefix: u8) -> Option<Self> {
+        let addr = addr.to_canonical();
+        let max = max_prefix(&addr);
+        if prefix > max {
+            return None;
+        }
+
+        let network = match addr {
+            IpAddr::V4(v4) => IpAddr::V4(Ipv4Addr::from(u32::from(v4) & mask_v4(prefix))),
+            IpAddr::V6(v6) => IpAddr::V6(Ipv6Addr::from(u128::from(v6) & mask_v6(prefix))),
+        };
+
+        Some(Self { network, prefix })
+    }
+
+    pub fn network(&self) -> IpAddr {
+        self.network
+    }
+
+    pub fn prefix(&self) -> u8 {
+        self.prefix
+    }
+
+    /// IPv4-mapped IPv6 addresses (`::ffff:1.2.3.4`) match IPv4 networks.
+    pub fn contains(&self, addr: &IpAddr) -> bool {
+        match (self.network, addr.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                u32::from(ip) & mask_v4(self.prefix) == u32::from(net)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                u128::from(ip) & mask_v6(self.prefix) == u128::from(net)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = CidrError;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        let raw = raw.trim();
+        let (addr, prefix) = match raw.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (raw, None),
+        };
+
+        let addr: IpAddr = addr
+            .parse()
+            .map_err(|_| CidrError::Address(raw.to_string()))?;
+        let prefix = match prefix {
+            Some(p) => p.parse().map_err(|_| CidrError::Prefix(raw.to_string()))?,
+            None => max_prefix(&addr.to_canonical()),
+        };
+
+        Cidr::new(addr, prefix).ok_or_else(|| CidrError::Prefix(raw.to_string()))
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+fn max_prefix(addr: &IpAddr) -> u8 {
+    match addr {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    }
+}
+
+fn mask_v4(prefix: u8) -> u32 {
+    u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0)
+}
+
+fn mask_v6(prefix: u8) -> u128 {
+    u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_and_masks_networks() {
+        let cidr: Cidr = "10.1.2.3/8".parse().unwrap();
+        assert_eq!(cidr.to_string(), "10.0.0.0/8");
+
+        let host: Cidr = "192.0.2.1".parse().unwrap();
+        assert_eq!(host.prefix(), 32);
+
+        let v6: Cidr = "2001:db8::1/32".parse().unwrap();
+        assert_eq!(v6.to_string(), "2001:db8::/32");
+    }
+
+    #[test]
+    fn matches_addresses() {
+        let v4: Cidr = "173.245.48.0/20".parse().unwrap();
+        assert!(v4.contains(&ip("173.245.63.255")));
+        assert!(!v4.contains(&ip("173.245.64.0")));
+        assert!(v4.contains(&ip("::ffff:173.245.48.1")));
+
+        let v6: Cidr = "2400:cb00::/32".parse().unwrap();
+        assert!(v6.contains(&ip("2400:cb00:1::5")));
+        assert!(!v6.contains(&ip("173.245.48.1")));
+
+        let all: Cidr = "0.0.0.0/0".parse().unwrap();
+        assert!(all.contains(&ip("8.8.8.8")));
+    }
+
+    #[test]
+    fn rejects_invalid_networks() {
+        assert!(matches!("10.0.0.0/33".parse::<Cidr>(), Err(CidrError::Prefix(_))));
+        assert!(matches!("10.0.0/8".parse::<Cidr>(), Err(CidrError::Address(_))));
+        assert!(matches!("10.0.0.0/x".parse::<Cidr>(), Err(CidrError::Prefix(_))));
+    }
+}
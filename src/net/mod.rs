@@ -0,0 +1,59 @@
+pub mod cidr;
+
+pub use cidr::Cidr;
+
+use std::net::IpAddr;
+
+/// Parse a client address as it appears in logs and forwarding headers:
+/// `1.2.3.4`, `1.2.3.4:5678`, `2001:db8::1` or `[2001:db8::1]:443`.
+///
+/// IPv4-mapped IPv6 addresses (`::ffff:1.2.3.4`) are folded to plain
+/// IPv4 so a dual-stack listener does not split one client in two.
+/// Placeholders such as `-` or `unix:` are rejected.
+pub fn parse_addr(raw: &str) -> Option<IpAddr> {
+    let raw = raw.trim();
+
+    let addr: IpAddr = if let Ok(addr) = raw.parse() {
+        addr
+    } else if let Some(rest) = raw.strip_prefix('[') {
+        rest.split_once(']')?.0.parse().ok()?
+    } else {
+        let (host, port) = raw.rsplit_once(':')?;
+        port.parse::<u16>().ok()?;
+        host.parse().ok()?
+    };
+
+    Some(addr.to_canonical())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> Option<IpAddr> {
+        s.parse().ok()
+    }
+
+    #[test]
+    fn parses_addresses_with_ports() {
+        assert_eq!(parse_addr(" 1.2.3.4:5678"), ip("1.2.3.4"));
+        assert_eq!(parse_addr("[2001:db8::1]:443"), ip("2001:db8::1"));
+        assert_eq!(parse_addr("2001:db8::1"), ip("2001:db8::1"));
+    }
+
+    #[test]
+    fn normalises_ipv6_spellings() {
+        assert_eq!(parse_addr("2001:0db8:0:0::1"), parse_addr("2001:db8::1"));
+        assert_eq!(parse_addr("::ffff:192.0.2.1"), ip("192.0.2.1"));
+        assert_eq!(parse_addr("[::ffff:192.0.2.1]:80"), ip("192.0.2.1"));
+    }
+
+    #[test]
+    fn rejects_placeholders() {
+        assert_eq!(parse_addr("-"), None);
+        assert_eq!(parse_addr("unix:"), None);
+        assert_eq!(parse_addr(""), None);
+        assert_eq!(parse_addr("999.1.1.1"), None);
+    }
+}
@@ -1,3 +1,5 @@
+use std::net::IpAddr;
+
 use crate::engine::action::ActionResult;
 use crate::config::schema::{BlockAction};
 use crate::engine::scoring::ScoreResult;
@@ -16,7 +18,7 @@ impl From<std::io::Error> for ExecutorError {
 
 pub fn execute_action(
     action: ActionResult,
-    ip: &str,
+    ip: IpAddr,
     score: &ScoreResult,
     fail2ban: Option<&Fail2BanConfig>,
 ) -> Result<(), ExecutorError> {
@@ -24,26 +26,31 @@ pub fn execute_action(
     match action {
         ActionResult::None => Ok(()),
 
-        ActionResult::DetectOnly => {
+        ActionResult::DetectOnly(tier) => {
             println!("Inside execute_action() ActionResult, DetectOnly");
-            crate::output::log::log_detect(ip, score);
+            crate::output::log::log_detect(ip, score, &tier);
             Ok(())
         }
 
-        ActionResult::Block(block_action) => match block_action {
+        ActionResult::Throttle(tier) => {
+            crate::output::log::log_throttle(ip, score, &tier);
+            Ok(())
+        }
+
+        ActionResult::Block(block_action, tier) => match block_action {
             BlockAction::Log => {
-                crate::output::log::log_block(ip, score);
+                crate::output::log::log_block(ip, score, &tier);
                 Ok(())
             }
             BlockAction::Stdout => {
-                crate::output::stdout::print_block(ip, score);
+                crate::output::stdout::print_block(ip, score, &tier);
                 Ok(())
             }
             BlockAction::Fail2ban => {
                 let cfg = fail2ban.ok_or_else(|| {
                     ExecutorError::Fail2Ban("Fail2Ban config missing".into())
                 })?;
-                crate::output::fail2ban::ban_ip(ip, cfg)
+                crate::output::fail2ban::ban_ip(ip, tier.duration, cfg)
             }
         },
     }
@@ -56,11 +63,24 @@ mod tests {
     use crate::engine::scoring::{ScoreResult};
     use crate::engine::scoring::ScoreReason;
     use crate::config::schema::{BlockAction};
+    use crate::engine::decision::Tier;
+
+    fn ip() -> IpAddr {
+        "1.2.3.4".parse().unwrap()
+    }
+
+    fn tier() -> Tier {
+        Tier {
+            name: "threshold".into(),
+            duration: None,
+        }
+    }
 
     fn score() -> ScoreResult {
         ScoreResult {
             score: 120,
-            reasons: vec![ScoreReason::HighRate { count: 50 }],
+            reasons: vec![ScoreReason::HighRate { requests: 50, window_secs: 10 }],
+            forced: None,
         }
     }
 
@@ -68,7 +88,7 @@ mod tests {
     fn allows_none_action() {
         let result = execute_action(
             ActionResult::None,
-            "1.2.3.4",
+            ip(),
             &score(),
             None,
         );
@@ -78,8 +98,8 @@ mod tests {
     #[test]
     fn allows_detect_only() {
         let result = execute_action(
-            ActionResult::DetectOnly,
-            "1.2.3.4",
+            ActionResult::DetectOnly(tier()),
+            ip(),
             &score(),
             None,
         );
@@ -89,8 +109,8 @@ mod tests {
     #[test]
     fn fails_fail2ban_when_config_missing() {
         let result = execute_action(
-            ActionResult::Block(BlockAction::Fail2ban),
-            "1.2.3.4",
+            ActionResult::Block(BlockAction::Fail2ban, tier()),
+            ip(),
             &score(),
             None,
         );
@@ -100,8 +120,8 @@ mod tests {
     #[test]
     fn allows_log_block() {
         let result = execute_action(
-            ActionResult::Block(BlockAction::Log),
-            "1.2.3.4",
+            ActionResult::Block(BlockAction::Log, tier()),
+            ip(),
             &score(),
             None,
         );
@@ -1,17 +1,55 @@
 use std::io::Write;
+use std::net::IpAddr;
 use std::os::unix::net::UnixStream;
+use std::time::Duration;
 
 use crate::config::schema::Fail2BanConfig;
 use crate::output::executor::ExecutorError;
 
-pub fn format_command(jail: &str, ip: &str) -> String {
+pub fn format_command(jail: &str, ip: IpAddr) -> String {
     format!("set {} banip {}", jail, ip)
 }
 
+/// Sets the ban length of the jail's next bans.
+pub fn format_bantime_command(jail: &str, duration: Duration) -> String {
+    format!("set {} bantime {}", jail, duration.as_secs())
+}
+
+/// The commands banning `ip`. fail2ban has no per-ban length, so a ban
+/// with a `duration` sets the jail's `bantime` around the `banip` and
+/// puts `restore` (the jail's own `bantime`) back afterwards.
+pub fn ban_commands(
+    jail: &str,
+    ip: IpAddr,
+    duration: Option<Duration>,
+    restore: Option<Duration>,
+) -> Vec<String> {
+    let Some(duration) = duration.filter(|d| Some(*d) != restore) else {
+        return vec![format_command(jail, ip)];
+    };
 
-pub fn ban_ip(ip: &str, cfg: &Fail2BanConfig) -> Result<(), ExecutorError> {
+    let mut commands = vec![format_bantime_command(jail, duration), format_command(jail, ip)];
+    if let Some(restore) = restore {
+        commands.push(format_bantime_command(jail, restore));
+    }
+    commands
+}
+
+
+/// Ban `ip`, for `duration` when given; otherwise for the jail's
+/// configured `bantime`.
+pub fn ban_ip(
+    ip: IpAddr,
+    duration: Option<Duration>,
+    cfg: &Fail2BanConfig,
+) -> Result<(), ExecutorError> {
+    let restore = cfg.bantime.map(Duration::from_secs);
     let mut stream = UnixStream::connect(&cfg.socket)?;
-    let cmd = format!("set {} banip {}\n", cfg.jail, ip);
+    let mut cmd = String::new();
+    for line in ban_commands(&cfg.jail, ip, duration, restore) {
+        cmd += &line;
+        cmd.push('\n');
+    }
     stream.write_all(cmd.as_bytes())?;
     Ok(())
 }
@@ -23,8 +61,32 @@ mod tests {
 
     #[test]
     fn formats_fail2ban_command() {
-        let cmd = format_command("aargal-auto", "1.2.3.4");
+        let cmd = format_command("aargal-auto", "1.2.3.4".parse().unwrap());
         assert_eq!(cmd, "set aargal-auto banip 1.2.3.4");
     }
+
+    #[test]
+    fn formats_bantime_command() {
+        let cmd = format_bantime_command("aargal-auto", Duration::from_secs(3600));
+        assert_eq!(cmd, "set aargal-auto bantime 3600");
+    }
+
+    #[test]
+    fn restores_the_jail_bantime_after_a_timed_ban() {
+        let ip = "1.2.3.4".parse().unwrap();
+        let hour = Some(Duration::from_secs(3600));
+        let jail = Some(Duration::from_secs(600));
+
+        assert_eq!(
+            ban_commands("aargal-auto", ip, hour, jail),
+            vec![
+                "set aargal-auto bantime 3600",
+                "set aargal-auto banip 1.2.3.4",
+                "set aargal-auto bantime 600",
+            ]
+        );
+        assert_eq!(ban_commands("aargal-auto", ip, None, jail), vec!["set aargal-auto banip 1.2.3.4"]);
+        assert_eq!(ban_commands("aargal-auto", ip, jail, jail), vec!["set aargal-auto banip 1.2.3.4"]);
+    }
 }
 
@@ -1,19 +1,34 @@
+use std::net::IpAddr;
+
+use crate::engine::decision::Tier;
 use crate::engine::scoring::ScoreResult;
 
-pub fn log_detect(ip: &str, score: &ScoreResult) {
+pub fn log_detect(ip: IpAddr, score: &ScoreResult, tier: &Tier) {
     log::info!(
-        "AARGAL DETECT ip={} score={} reasons={:?}",
+        "AARGAL DETECT ip={} score={} tier={} reasons={:?}",
+        ip,
+        score.score,
+        tier,
+        score.reasons
+    );
+}
+
+pub fn log_throttle(ip: IpAddr, score: &ScoreResult, tier: &Tier) {
+    log::warn!(
+        "AARGAL THROTTLE ip={} score={} tier={} reasons={:?}",
         ip,
         score.score,
+        tier,
         score.reasons
     );
 }
 
-pub fn log_block(ip: &str, score: &ScoreResult) {
+pub fn log_block(ip: IpAddr, score: &ScoreResult, tier: &Tier) {
     log::warn!(
-        "AARGAL BLOCK ip={} score={} reasons={:?}",
+        "AARGAL BLOCK ip={} score={} tier={} reasons={:?}",
         ip,
         score.score,
+        tier,
         score.reasons
     );
 }
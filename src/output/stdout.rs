@@ -1,10 +1,14 @@
+use std::net::IpAddr;
+
+use crate::engine::decision::Tier;
 use crate::engine::scoring::ScoreResult;
 
-pub fn print_block(ip: &str, score: &ScoreResult) {
+pub fn print_block(ip: IpAddr, score: &ScoreResult, tier: &Tier) {
     println!(
-        "AARGAL BLOCK ip={} score={} reasons={:?}",
+        "AARGAL BLOCK ip={} score={} tier={} reasons={:?}",
         ip,
         score.score,
+        tier,
         score.reasons
     );
 }
@@ -0,0 +1,169 @@
+use std::net::IpAddr;
+
+use anyhow::Context;
+
+use crate::config::schema::ClientIpConfig;
+use crate::net::{parse_addr, Cidr};
+use crate::parser::{LogParser, ParsedEvent};
+
+/// Replaces the proxy address in [`ParsedEvent::ip`] with the real client.
+///
+/// Mirrors nginx `set_real_ip_from` + `real_ip_header` +
+/// `real_ip_recursive`: only when the connecting address is a trusted
+/// proxy is the forwarding header consulted, walking it right-to-left
+/// and skipping trusted hops. The original address is kept in
+/// `extensions["realip_remote_addr"]`, as nginx names it.
+pub struct ClientIpResolver {
+    inner: Box<dyn LogParser>,
+    trusted: Vec<Cidr>,
+    header: String,
+    recursive: bool,
+}
+
+impl ClientIpResolver {
+    pub fn new(inner: Box<dyn LogParser>, cfg: &ClientIpConfig) -> anyhow::Result<Self> {
+        let trusted = cfg
+            .trusted_proxies
+            .iter()
+            .map(|raw| {
+                raw.parse::<Cidr>()
+                    .with_context(|| format!("Invalid parser.client_ip.trusted_proxies entry {:?}", raw))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Ok(Self {
+            inner,
+            trusted,
+            header: cfg.header.clone(),
+            recursive: cfg.recursive,
+        })
+    }
+
+    fn is_trusted(&self, addr: &IpAddr) -> bool {
+        self.trusted.iter().any(|net| net.contains(addr))
+    }
+
+    /// The client address for a request from `remote` carrying `header`.
+    fn resolve(&self, remote: IpAddr, header: Option<&str>) -> IpAddr {
+        if !self.is_trusted(&remote) {
+            return remote;
+        }
+
+        let Some(header) = header else {
+            return remote;
+        };
+
+        let mut client = remote;
+        for hop in header.rsplit(',') {
+            let Some(addr) = parse_addr(hop) else {
+                // Garbage in the chain before any untrusted hop: nothing
+                // in the header can be believed, so keep the proxy itself
+                // as nginx does.
+                return remote;
+            };
+
+            client = addr;
+            if !self.recursive || !self.is_trusted(&addr) {
+                break;
+            }
+        }
+
+        client
+    }
+}
+
+impl LogParser for ClientIpResolver {
+    fn parse(&self, line: &str) -> Option<ParsedEvent> {
+        let mut event = self.inner.parse(line)?;
+
+        let remote = event.ip;
+        let client = self.resolve(remote, event.extensions.get(&self.header).map(String::as_str));
+        if client != remote {
+            event.ip = client;
+            event.extensions.insert("realip_remote_addr".to_string(), remote.to_string());
+        }
+
+        Some(event)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::template::LogFormat;
+
+    const FORMAT: &str = r#"$remote_addr [$time_local] "$request" $status "$http_x_forwarded_for""#;
+
+    fn resolver(trusted: &[&str], recursive: bool) -> ClientIpResolver {
+        let cfg = ClientIpConfig {
+            trusted_proxies: trusted.iter().map(|s| s.to_string()).collect(),
+            header: "http_x_forwarded_for".into(),
+            recursive,
+        };
+        ClientIpResolver::new(Box::new(LogFormat::compile(FORMAT).unwrap()), &cfg).unwrap()
+    }
+
+    fn line(remote: &str, xff: &str) -> String {
+        format!(
+            r#"{} [02/Oct/2024:00:48:26 +0900] "GET / HTTP/1.1" 200 "{}""#,
+            remote, xff
+        )
+    }
+
+    #[test]
+    fn untrusted_remote_is_the_client() {
+        let r = resolver(&["10.0.0.0/8"], true);
+        let event = r.parse(&line("203.0.113.5", "1.1.1.1")).unwrap();
+
+        assert_eq!(event.ip.to_string(), "203.0.113.5");
+        assert!(!event.extensions.contains_key("realip_remote_addr"));
+    }
+
+    #[test]
+    fn walks_header_right_to_left_skipping_trusted_hops() {
+        let r = resolver(&["10.0.0.0/8", "173.245.48.0/20"], true);
+        let event = r
+            .parse(&line("10.0.0.2", "198.51.100.7, 203.0.113.9, 173.245.48.10"))
+            .unwrap();
+
+        assert_eq!(event.ip.to_string(), "203.0.113.9");
+        assert_eq!(event.extensions["realip_remote_addr"], "10.0.0.2");
+    }
+
+    #[test]
+    fn non_recursive_takes_last_hop() {
+        let r = resolver(&["10.0.0.0/8", "173.245.48.0/20"], false);
+        let event = r
+            .parse(&line("10.0.0.2", "198.51.100.7, 173.245.48.10"))
+            .unwrap();
+
+        assert_eq!(event.ip.to_string(), "173.245.48.10");
+    }
+
+    #[test]
+    fn stops_at_garbage_and_missing_header() {
+        let r = resolver(&["10.0.0.0/8"], true);
+
+        let event = r.parse(&line("10.0.0.2", "evil, 10.0.0.3")).unwrap();
+        assert_eq!(event.ip.to_string(), "10.0.0.2");
+        assert!(!event.extensions.contains_key("realip_remote_addr"));
+
+        // Untrusted hops right of the garbage are still the client.
+        let event = r.parse(&line("10.0.0.2", "evil, 198.51.100.7, 10.0.0.3")).unwrap();
+        assert_eq!(event.ip.to_string(), "198.51.100.7");
+
+        let event = r.parse(&line("10.0.0.2", "-")).unwrap();
+        assert_eq!(event.ip.to_string(), "10.0.0.2");
+    }
+
+    #[test]
+    fn rejects_invalid_trusted_proxies() {
+        let cfg = ClientIpConfig {
+            trusted_proxies: vec!["10.0.0.0/99".into()],
+            header: "http_x_forwarded_for".into(),
+            recursive: true,
+        };
+        assert!(ClientIpResolver::new(Box::new(LogFormat::compile(FORMAT).unwrap()), &cfg).is_err());
+    }
+}
@@ -0,0 +1,44 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::SystemTime;
+
+/// A single request, normalised from whatever log format it came from.
+#[derive(Debug, Clone)]
+pub struct ParsedEvent {
+    pub ip: IpAddr,
+    pub method: Option<String>,
+    pub path: String,
+    pub protocol: Option<String>,
+    pub status: u16,
+    pub bytes_sent: u64,
+    pub referer: Option<String>,
+    pub user_agent: Option<String>,
+    pub host: Option<String>,
+    /// Label of the ingest source the line came from (e.g. a vhost).
+    pub source: Option<String>,
+    pub timestamp: SystemTime,
+    /// Fields a custom `log_format` captured that have no dedicated slot
+    /// above, keyed by nginx variable name without the `$`.
+    pub extensions: HashMap<String, String>,
+}
+
+#[cfg(test)]
+impl ParsedEvent {
+    /// A `GET` of `path` from `ip` answered with 200; the rest is empty.
+    pub fn test(ip: &str, path: &str, timestamp: SystemTime) -> Self {
+        Self {
+            ip: ip.parse().expect("test IP address"),
+            method: Some("GET".into()),
+            path: path.into(),
+            protocol: Some("HTTP/1.1".into()),
+            status: 200,
+            bytes_sent: 0,
+            referer: None,
+            user_agent: None,
+            host: None,
+            source: None,
+            timestamp,
+            extensions: HashMap::new(),
+        }
+    }
+}
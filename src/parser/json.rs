@@ -0,0 +1,250 @@
+use serde_json::Value;
+
+use crate::config::schema::JsonFieldMap;
+use crate::net::parse_addr;
+use crate::parser::nginx::split_request;
+use crate::parser::time::{parse_rfc3339, parse_time_local, parse_unix};
+use crate::parser::{LogParser, ParsedEvent};
+
+/// JSON access-log parser (nginx `escape=json`, Caddy, Traefik, ...).
+///
+/// Every field is looked up through [`JsonFieldMap`]. Keys may be dotted
+/// paths into nested objects (`request.remote_ip`); arrays resolve to
+/// their first element, which is how Caddy logs request headers.
+///
+/// `ip`, `status`, a path (directly or via `request`) and a timestamp are
+/// required. Everything else is optional.
+#[derive(Debug, Clone)]
+pub struct JsonParser {
+    fields: JsonFieldMap,
+}
+
+impl JsonParser {
+    pub fn new(fields: JsonFieldMap) -> Self {
+        Self { fields }
+    }
+}
+
+impl LogParser for JsonParser {
+    fn parse(&self, line: &str) -> Option<ParsedEvent> {
+        let record: Value = serde_json::from_str(line.trim()).ok()?;
+        let f = &self.fields;
+
+        let ip = parse_addr(&lookup_string(&record, &f.ip)?)?;
+        let status: u16 = lookup_number(&record, &f.status)?.try_into().ok()?;
+        let timestamp = lookup(&record, &f.timestamp).and_then(parse_timestamp)?;
+
+        let (mut method, mut path, mut protocol) = match lookup_string(&record, &f.request) {
+            Some(request) => {
+                let (m, p, proto) = split_request(&request);
+                (m, Some(p), proto)
+            }
+            None => (None, None, None),
+        };
+
+        if let Some(value) = lookup_string(&record, &f.method) {
+            method = Some(value);
+        }
+        if let Some(value) = lookup_string(&record, &f.path) {
+            path = Some(value);
+        }
+        if let Some(value) = lookup_string(&record, &f.protocol) {
+            protocol = Some(value);
+        }
+
+        let extensions = f
+            .extensions
+            .iter()
+            .filter_map(|key| Some((key.clone(), lookup_string(&record, key)?)))
+            .collect();
+
+        Some(ParsedEvent {
+            ip,
+            method,
+            path: path?,
+            protocol,
+            status,
+            bytes_sent: lookup_number(&record, &f.bytes_sent).unwrap_or(0),
+            referer: lookup_string(&record, &f.referer),
+            user_agent: lookup_string(&record, &f.user_agent),
+            host: lookup_string(&record, &f.host),
+            source: None,
+            timestamp,
+            extensions,
+        })
+    }
+}
+
+/// Resolve `key` in `record`, treating dots as nesting.
+///
+/// An exact key match wins, so flat records with dotted names
+/// (`"request.uri": ...`) still work.
+fn lookup<'a>(record: &'a Value, key: &str) -> Option<&'a Value> {
+    if key.is_empty() {
+        return None;
+    }
+
+    let object = match record {
+        Value::Object(map) => map,
+        Value::Array(items) => return lookup(items.first()?, key),
+        _ => return None,
+    };
+
+    if let Some(value) = object.get(key) {
+        return Some(first_element(value));
+    }
+
+    for (idx, _) in key.match_indices('.') {
+        if let Some(inner) = object.get(&key[..idx]) {
+            if let Some(value) = lookup(first_element(inner), &key[idx + 1..]) {
+                return Some(value);
+            }
+        }
+    }
+
+    None
+}
+
+fn first_element(value: &Value) -> &Value {
+    match value {
+        Value::Array(items) => items.first().unwrap_or(value),
+        _ => value,
+    }
+}
+
+/// Strings are taken verbatim, numbers are stringified; empty and `-`
+/// (nginx's placeholder) count as missing.
+fn lookup_string(record: &Value, key: &str) -> Option<String> {
+    let value = match lookup(record, key)? {
+        Value::String(s) => s.clone(),
+        Value::Number(n) => n.to_string(),
+        _ => return None,
+    };
+
+    if value.is_empty() || value == "-" {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+/// nginx `escape=json` writes every variable as a string, so accept
+/// numeric strings as well as JSON numbers.
+fn lookup_number(record: &Value, key: &str) -> Option<u64> {
+    match lookup(record, key)? {
+        Value::Number(n) => n.as_u64(),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+/// Accepts Unix seconds (number or numeric string), RFC 3339 and
+/// nginx `$time_local`.
+fn parse_timestamp(value: &Value) -> Option<std::time::SystemTime> {
+    match value {
+        Value::Number(n) => parse_unix(n.as_f64()?),
+        Value::String(s) => parse_rfc3339(s)
+            .or_else(|| parse_time_local(s))
+            .or_else(|| parse_unix(s.parse().ok()?)),
+        _ => None,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn nginx() -> JsonParser {
+        JsonParser::new(JsonFieldMap::default())
+    }
+
+    #[test]
+    fn parses_nginx_escape_json() {
+        let line = r#"{"time_iso8601":"2024-10-02T00:48:26+09:00","remote_addr":"203.0.113.7","request":"GET /a?b=1 HTTP/2.0","status":"404","body_bytes_sent":"512","http_referer":"","http_user_agent":"curl/8.0","host":"shop.example.com"}"#;
+        let event = nginx().parse(line).unwrap();
+
+        assert_eq!(event.ip.to_string(), "203.0.113.7");
+        assert_eq!(event.method.as_deref(), Some("GET"));
+        assert_eq!(event.path, "/a?b=1");
+        assert_eq!(event.protocol.as_deref(), Some("HTTP/2.0"));
+        assert_eq!(event.status, 404);
+        assert_eq!(event.bytes_sent, 512);
+        assert!(event.referer.is_none());
+        assert_eq!(event.user_agent.as_deref(), Some("curl/8.0"));
+        assert_eq!(event.host.as_deref(), Some("shop.example.com"));
+        assert!(event.extensions.is_empty());
+        assert_eq!(
+            event.timestamp,
+            UNIX_EPOCH + Duration::from_secs(1_727_797_706)
+        );
+    }
+
+    #[test]
+    fn parses_caddy_nested_fields() {
+        let fields = JsonFieldMap {
+            ip: "request.remote_ip".into(),
+            status: "status".into(),
+            path: "request.uri".into(),
+            method: "request.method".into(),
+            protocol: "request.proto".into(),
+            user_agent: "request.headers.User-Agent".into(),
+            host: "request.host".into(),
+            timestamp: "ts".into(),
+            bytes_sent: "size".into(),
+            extensions: vec!["request.headers.X-Forwarded-For".into()],
+            ..JsonFieldMap::default()
+        };
+        let line = r#"{"level":"info","ts":1727797706.5,"request":{"remote_ip":"198.51.100.2","proto":"HTTP/1.1","method":"POST","host":"api.example.com","uri":"/login","headers":{"User-Agent":["python-requests/2.31"],"X-Forwarded-For":["192.0.2.44"]}},"size":42,"status":401}"#;
+        let event = JsonParser::new(fields).parse(line).unwrap();
+
+        assert_eq!(event.ip.to_string(), "198.51.100.2");
+        assert_eq!(event.method.as_deref(), Some("POST"));
+        assert_eq!(event.path, "/login");
+        assert_eq!(event.status, 401);
+        assert_eq!(event.bytes_sent, 42);
+        assert_eq!(event.user_agent.as_deref(), Some("python-requests/2.31"));
+        assert_eq!(event.host.as_deref(), Some("api.example.com"));
+        assert_eq!(event.extensions["request.headers.X-Forwarded-For"], "192.0.2.44");
+        assert_eq!(
+            event.timestamp,
+            UNIX_EPOCH + Duration::from_millis(1_727_797_706_500)
+        );
+    }
+
+    #[test]
+    fn parses_traefik_flat_fields() {
+        let fields = JsonFieldMap {
+            ip: "ClientHost".into(),
+            status: "DownstreamStatus".into(),
+            path: "RequestPath".into(),
+            method: "RequestMethod".into(),
+            user_agent: "request_User-Agent".into(),
+            host: "RequestHost".into(),
+            timestamp: "StartUTC".into(),
+            ..JsonFieldMap::default()
+        };
+        let line = r#"{"ClientHost":"192.0.2.9","DownstreamStatus":200,"RequestPath":"/","RequestMethod":"GET","RequestHost":"blog.example.com","StartUTC":"2024-10-01T15:48:26.123456789Z","request_User-Agent":"Mozilla/5.0"}"#;
+        let event = JsonParser::new(fields).parse(line).unwrap();
+
+        assert_eq!(event.ip.to_string(), "192.0.2.9");
+        assert_eq!(event.status, 200);
+        assert_eq!(event.host.as_deref(), Some("blog.example.com"));
+        assert_eq!(event.user_agent.as_deref(), Some("Mozilla/5.0"));
+    }
+
+    #[test]
+    fn rejects_records_missing_required_fields() {
+        let parser = nginx();
+
+        assert!(parser.parse("not json").is_none());
+        assert!(parser
+            .parse(r#"{"remote_addr":"-","request":"GET / HTTP/1.1","status":"200","time_iso8601":"2024-10-02T00:48:26+09:00"}"#)
+            .is_none());
+        assert!(parser.parse(r#"{"remote_addr":"1.2.3.4","status":"200"}"#).is_none());
+        assert!(parser
+            .parse(r#"{"remote_addr":"1.2.3.4","request":"GET / HTTP/1.1","time_iso8601":"2024-10-02T00:48:26+09:00"}"#)
+            .is_none());
+    }
+}
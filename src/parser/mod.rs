@@ -1,3 +1,58 @@
+pub mod client_ip;
+pub mod event;
+pub mod json;
 pub mod nginx;
+pub mod template;
+pub mod time;
 
-pub use nginx::{parse_line, ParsedEvent};
+pub use event::ParsedEvent;
+pub use nginx::parse_line;
+
+use anyhow::Context;
+
+use crate::config::schema::{ParserConfig, ParserFormat};
+use crate::parser::client_ip::ClientIpResolver;
+use crate::parser::template::LogFormat;
+
+/// Turns one raw log line into a [`ParsedEvent`].
+///
+/// Returns `None` for lines the format cannot make sense of.
+pub trait LogParser: Send {
+    fn parse(&self, line: &str) -> Option<ParsedEvent>;
+}
+
+/// nginx `combined` format, see [`nginx::parse_line`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NginxCombinedParser;
+
+impl LogParser for NginxCombinedParser {
+    fn parse(&self, line: &str) -> Option<ParsedEvent> {
+        parse_line(line)
+    }
+}
+
+/// Build the parser selected by `parser.format`, wrapped in client IP
+/// resolution when `[parser.client_ip]` is configured.
+pub fn build_parser(cfg: &ParserConfig) -> anyhow::Result<Box<dyn LogParser>> {
+    let parser = build_format_parser(cfg)?;
+
+    Ok(match &cfg.client_ip {
+        Some(client_ip) => Box::new(ClientIpResolver::new(parser, client_ip)?),
+        None => parser,
+    })
+}
+
+fn build_format_parser(cfg: &ParserConfig) -> anyhow::Result<Box<dyn LogParser>> {
+    Ok(match cfg.format {
+        ParserFormat::NginxCombined => Box::new(NginxCombinedParser),
+        ParserFormat::NginxLogFormat => {
+            let template = cfg.log_format.as_deref().context(
+                "parser.log_format is required when parser.format = \"nginx_log_format\"",
+            )?;
+            let format = LogFormat::compile(template)
+                .context("Invalid parser.log_format")?;
+            Box::new(format)
+        }
+        ParserFormat::Json => Box::new(json::JsonParser::new(cfg.json.clone())),
+    })
+}
@@ -1,38 +1,126 @@
-use std::time::SystemTime;
+use std::sync::OnceLock;
 
-#[derive(Debug, Clone)]
-pub struct ParsedEvent {
-    pub ip: String,
-    pub status: u16,
-    pub path: String,
-    pub user_agent: Option<String>,
-    pub timestamp: SystemTime,
+use crate::parser::template::LogFormat;
+use crate::parser::{LogParser, ParsedEvent};
 
+/// nginx's predefined `combined` log format.
+pub const COMBINED: &str = r#"$remote_addr - $remote_user [$time_local] "$request" $status $body_bytes_sent "$http_referer" "$http_user_agent""#;
+
+fn combined() -> &'static LogFormat {
+    static FORMAT: OnceLock<LogFormat> = OnceLock::new();
+    FORMAT.get_or_init(|| {
+        LogFormat::compile(COMBINED).expect("built-in combined format compiles")
+    })
 }
 
-/// Minimal nginx combined log parser (Phase 1)
+/// nginx `combined` log format parser.
+///
+/// Anything after the user agent (e.g. extra fields appended to
+/// `combined`) is ignored.
 pub fn parse_line(line: &str) -> Option<ParsedEvent> {
-    // Very conservative parsing — no regex yet
-    let parts: Vec<&str> = line.split('"').collect();
-    if parts.len() < 2 {
-        return None;
+    combined().parse(line)
+}
+
+/// Split `$request` into method, path and protocol.
+///
+/// Malformed request lines (TLS handshakes on port 80, `-`, junk from
+/// scanners) keep the raw text as the path so they are still counted.
+pub(crate) fn split_request(
+    request: &str,
+) -> (Option<String>, String, Option<String>) {
+    let parts: Vec<&str> = request.split(' ').filter(|p| !p.is_empty()).collect();
+
+    match parts.as_slice() {
+        [method, path, protocol] => (
+            Some(method.to_string()),
+            path.to_string(),
+            Some(protocol.to_string()),
+        ),
+        [method, path] => (Some(method.to_string()), path.to_string(), None),
+        _ => (None, request.to_string(), None),
     }
+}
 
-    let ip = parts[0].split_whitespace().next()?.to_string();
 
-    let request = parts[1];
-    let path = request.split_whitespace().nth(1)?.to_string();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
 
-    let tail = parts.get(2)?;
-    let status: u16 = tail.split_whitespace().next()?.parse().ok()?;
+    const LINE: &str = r#"203.0.113.7 - alice [02/Oct/2024:00:48:26 +0900] "GET /products?id=3 HTTP/1.1" 200 5123 "https://example.com/" "Mozilla/5.0 (X11; Linux x86_64)""#;
 
-    let user_agent = parts.get(5).map(|s| s.to_string());
+    #[test]
+    fn parses_combined_line() {
+        let event = parse_line(LINE).unwrap();
 
-    Some(ParsedEvent {
-        ip,
-        status,
-        path,
-        user_agent,
-        timestamp: SystemTime::now(),
-    })
+        assert_eq!(event.ip.to_string(), "203.0.113.7");
+        assert_eq!(event.method.as_deref(), Some("GET"));
+        assert_eq!(event.path, "/products?id=3");
+        assert_eq!(event.protocol.as_deref(), Some("HTTP/1.1"));
+        assert_eq!(event.status, 200);
+        assert_eq!(event.bytes_sent, 5123);
+        assert_eq!(event.referer.as_deref(), Some("https://example.com/"));
+        assert_eq!(
+            event.user_agent.as_deref(),
+            Some("Mozilla/5.0 (X11; Linux x86_64)")
+        );
+        assert_eq!(
+            event.timestamp,
+            UNIX_EPOCH + Duration::from_secs(1_727_797_706)
+        );
+    }
+
+    #[test]
+    fn dash_referer_and_agent_become_none() {
+        let line = r#"1.2.3.4 - - [02/Oct/2024:00:48:26 +0900] "GET / HTTP/1.1" 404 0 "-" "-""#;
+        let event = parse_line(line).unwrap();
+
+        assert!(event.referer.is_none());
+        assert!(event.user_agent.is_none());
+    }
+
+    #[test]
+    fn handles_escaped_quotes() {
+        let line = r#"1.2.3.4 - - [02/Oct/2024:00:48:26 +0900] "GET /a\"b HTTP/1.1" 200 10 "-" "evil \"agent\" \x22x\x22""#;
+        let event = parse_line(line).unwrap();
+
+        assert_eq!(event.path, "/a\"b");
+        assert_eq!(event.user_agent.as_deref(), Some(r#"evil "agent" "x""#));
+    }
+
+    #[test]
+    fn keeps_malformed_request_as_path() {
+        let line = r#"1.2.3.4 - - [02/Oct/2024:00:48:26 +0900] "\x16\x03\x01" 400 157 "-" "-""#;
+        let event = parse_line(line).unwrap();
+
+        assert!(event.method.is_none());
+        assert!(event.protocol.is_none());
+        assert_eq!(event.status, 400);
+    }
+
+    #[test]
+    fn normalises_ipv6_clients() {
+        let a = parse_line(r#"2001:0db8:0:0::1 - - [02/Oct/2024:00:48:26 +0900] "GET / HTTP/1.1" 200 1 "-" "-""#).unwrap();
+        let b = parse_line(r#"2001:db8::1 - - [02/Oct/2024:00:48:26 +0900] "GET / HTTP/1.1" 200 1 "-" "-""#).unwrap();
+        let mapped = parse_line(r#"::ffff:192.0.2.1 - - [02/Oct/2024:00:48:26 +0900] "GET / HTTP/1.1" 200 1 "-" "-""#).unwrap();
+
+        assert_eq!(a.ip, b.ip);
+        assert_eq!(mapped.ip.to_string(), "192.0.2.1");
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        assert!(parse_line("").is_none());
+        assert!(parse_line("not a log line").is_none());
+        assert!(parse_line(r#"1.2.3.4 - - [bad time] "GET / HTTP/1.1" 200 1 "-" "-""#).is_none());
+    }
+
+    #[test]
+    fn parses_fixture_log() {
+        let raw = include_str!("../../tests/fixtures/access.log");
+
+        for line in raw.lines().filter(|l| !l.trim().is_empty()) {
+            assert!(parse_line(line).is_some(), "failed to parse: {}", line);
+        }
+    }
 }
@@ -0,0 +1,411 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::SystemTime;
+
+use crate::net::parse_addr;
+use crate::parser::nginx::split_request;
+use crate::parser::time::{parse_rfc3339, parse_time_local, parse_unix};
+use crate::parser::{LogParser, ParsedEvent};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Var(String),
+}
+
+/// A compiled nginx `log_format` string.
+///
+/// The template is split into literal text and `$variable` references.
+/// Each variable captures everything up to the next literal, skipping
+/// backslash escapes so `\"` inside a quoted value does not end it.
+/// Variables Aargal understands are mapped onto [`ParsedEvent`]; all
+/// others are kept in [`ParsedEvent::extensions`] under their name
+/// (without the `$`).
+#[derive(Debug, Clone)]
+pub struct LogFormat {
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum LogFormatError {
+    #[error("log_format is empty")]
+    Empty,
+    #[error("empty variable name at byte {0}")]
+    EmptyVariable(usize),
+    #[error("unterminated ${{...}} at byte {0}")]
+    UnterminatedBrace(usize),
+    #[error("${0} and ${1} are adjacent; add a separator so they can be told apart")]
+    AdjacentVariables(String, String),
+    #[error("log_format must contain {0}")]
+    MissingField(&'static str),
+}
+
+impl LogFormat {
+    pub fn compile(template: &str) -> Result<Self, LogFormatError> {
+        let template = template.trim();
+        if template.is_empty() {
+            return Err(LogFormatError::Empty);
+        }
+
+        let mut segments: Vec<Segment> = Vec::new();
+        let mut literal = String::new();
+        let mut rest = template;
+        let mut offset = 0;
+
+        while let Some(idx) = rest.find('$') {
+            literal.push_str(&rest[..idx]);
+            let after = &rest[idx + 1..];
+
+            let (name, consumed) = if let Some(braced) = after.strip_prefix('{') {
+                let end = braced
+                    .find('}')
+                    .ok_or(LogFormatError::UnterminatedBrace(offset + idx))?;
+                (&braced[..end], end + 2)
+            } else {
+                let end = after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                (&after[..end], end)
+            };
+
+            if name.is_empty() {
+                return Err(LogFormatError::EmptyVariable(offset + idx));
+            }
+
+            if !literal.is_empty() {
+                segments.push(Segment::Literal(std::mem::take(&mut literal)));
+            } else if let Some(Segment::Var(prev)) = segments.last() {
+                return Err(LogFormatError::AdjacentVariables(
+                    prev.clone(),
+                    name.to_string(),
+                ));
+            }
+            segments.push(Segment::Var(name.to_string()));
+
+            let advance = idx + 1 + consumed;
+            rest = &rest[advance..];
+            offset += advance;
+        }
+
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        let format = Self { segments };
+        format.check_required()?;
+        Ok(format)
+    }
+
+    fn has_var(&self, names: &[&str]) -> bool {
+        self.segments
+            .iter()
+            .any(|s| matches!(s, Segment::Var(v) if names.contains(&v.as_str())))
+    }
+
+    fn check_required(&self) -> Result<(), LogFormatError> {
+        if !self.has_var(&["remote_addr"]) {
+            return Err(LogFormatError::MissingField("$remote_addr"));
+        }
+        if !self.has_var(&["status"]) {
+            return Err(LogFormatError::MissingField("$status"));
+        }
+        if !self.has_var(&["request", "request_uri", "uri"]) {
+            return Err(LogFormatError::MissingField(
+                "$request, $request_uri or $uri",
+            ));
+        }
+        if !self.has_var(&["time_local", "time_iso8601", "msec"]) {
+            return Err(LogFormatError::MissingField(
+                "$time_local, $time_iso8601 or $msec",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Split `line` into `(variable, value)` pairs.
+    ///
+    /// Text after the last segment is ignored so formats that were
+    /// extended at the end still match.
+    pub fn extract<'a>(&'a self, line: &str) -> Option<Vec<(&'a str, String)>> {
+        // A file truncated under an open writer (logrotate `copytruncate`)
+        // can leave a run of NUL bytes in front of the next line.
+        let line = line.trim_start_matches('\0').trim_end_matches(['\r', '\n']);
+
+        let mut values = Vec::new();
+        let mut pos = 0;
+
+        for (i, segment) in self.segments.iter().enumerate() {
+            match segment {
+                Segment::Literal(text) => {
+                    if !line[pos..].starts_with(text.as_str()) {
+                        return None;
+                    }
+                    pos += text.len();
+                }
+                Segment::Var(name) => {
+                    let end = match self.segments.get(i + 1) {
+                        Some(Segment::Literal(next)) => {
+                            pos + find_unescaped(&line[pos..], next)?
+                        }
+                        _ => line.len(),
+                    };
+                    values.push((name.as_str(), unescape(&line[pos..end])));
+                    pos = end;
+                }
+            }
+        }
+
+        Some(values)
+    }
+}
+
+impl LogParser for LogFormat {
+    fn parse(&self, line: &str) -> Option<ParsedEvent> {
+        let mut builder = EventBuilder::default();
+
+        for (name, value) in self.extract(line)? {
+            builder.set(name, value)?;
+        }
+
+        builder.build()
+    }
+}
+
+/// Byte offset of the first `needle` in `haystack` that is not part of
+/// a backslash escape.
+fn find_unescaped(haystack: &str, needle: &str) -> Option<usize> {
+    let bytes = haystack.as_bytes();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'\\' {
+            i += 2;
+            continue;
+        }
+        if haystack.is_char_boundary(i) && haystack[i..].starts_with(needle) {
+            return Some(i);
+        }
+        i += 1;
+    }
+
+    None
+}
+
+/// Decode the escapes nginx writes (`\"`, `\\`, `\xHH`).
+fn unescape(raw: &str) -> String {
+    if !raw.contains('\\') {
+        return raw.to_string();
+    }
+
+    let mut out = String::with_capacity(raw.len());
+    let mut chars = raw.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                match u8::from_str_radix(&hex, 16) {
+                    Ok(b) if b.is_ascii() => out.push(b as char),
+                    _ => {
+                        out.push_str("\\x");
+                        out.push_str(&hex);
+                    }
+                }
+            }
+            Some(escaped) => out.push(escaped),
+            None => out.push('\\'),
+        }
+    }
+
+    out
+}
+
+fn dash_to_none(value: String) -> Option<String> {
+    if value.is_empty() || value == "-" {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+#[derive(Default)]
+struct EventBuilder {
+    ip: Option<IpAddr>,
+    method: Option<String>,
+    path: Option<String>,
+    protocol: Option<String>,
+    status: Option<u16>,
+    body_bytes_sent: Option<u64>,
+    bytes_sent: Option<u64>,
+    referer: Option<String>,
+    user_agent: Option<String>,
+    host: Option<String>,
+    timestamp: Option<SystemTime>,
+    extensions: HashMap<String, String>,
+}
+
+impl EventBuilder {
+    /// Returns `None` when a known variable holds a value of the wrong
+    /// shape, which means the line does not match the format.
+    fn set(&mut self, name: &str, value: String) -> Option<()> {
+        match name {
+            "remote_addr" => self.ip = Some(parse_addr(&value)?),
+            "request" => {
+                let (method, path, protocol) = split_request(&value);
+                self.method = self.method.take().or(method);
+                self.path = self.path.take().or(Some(path));
+                self.protocol = self.protocol.take().or(protocol);
+            }
+            "request_method" => self.method = dash_to_none(value),
+            "request_uri" => self.path = Some(value),
+            "uri" => self.path = self.path.take().or(Some(value)),
+            "server_protocol" => self.protocol = dash_to_none(value),
+            "status" => self.status = Some(value.parse().ok()?),
+            "body_bytes_sent" => self.body_bytes_sent = Some(parse_bytes(&value)?),
+            "bytes_sent" => self.bytes_sent = Some(parse_bytes(&value)?),
+            "http_referer" => self.referer = dash_to_none(value),
+            "http_user_agent" => self.user_agent = dash_to_none(value),
+            "host" => self.host = dash_to_none(value),
+            "http_host" | "server_name" => {
+                self.host = self.host.take().or_else(|| dash_to_none(value))
+            }
+            "time_local" => self.timestamp = Some(parse_time_local(&value)?),
+            "time_iso8601" => self.timestamp = Some(parse_rfc3339(&value)?),
+            "msec" => self.timestamp = Some(parse_unix(value.parse().ok()?)?),
+            _ => {
+                self.extensions.insert(name.to_string(), value);
+            }
+        }
+        Some(())
+    }
+
+    fn build(self) -> Option<ParsedEvent> {
+        Some(ParsedEvent {
+            ip: self.ip?,
+            method: self.method,
+            path: self.path?,
+            protocol: self.protocol,
+            status: self.status?,
+            bytes_sent: self.body_bytes_sent.or(self.bytes_sent).unwrap_or(0),
+            referer: self.referer,
+            user_agent: self.user_agent,
+            host: self.host,
+            source: None,
+            timestamp: self.timestamp?,
+            extensions: self.extensions,
+        })
+    }
+}
+
+/// `$body_bytes_sent` is `0` or a number; tolerate `-` from other servers.
+fn parse_bytes(raw: &str) -> Option<u64> {
+    if raw == "-" {
+        Some(0)
+    } else {
+        raw.parse().ok()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CUSTOM: &str = r#"$remote_addr - $remote_user [$time_local] "$request" $status $body_bytes_sent "$http_referer" "$http_user_agent" rt=$request_time host=$host xff="$http_x_forwarded_for" $ssl_protocol"#;
+
+    #[test]
+    fn compiles_segments() {
+        let format = LogFormat::compile("$remote_addr [$time_local] \"$request\" ${status}x").unwrap();
+
+        assert_eq!(
+            format.segments,
+            vec![
+                Segment::Var("remote_addr".into()),
+                Segment::Literal(" [".into()),
+                Segment::Var("time_local".into()),
+                Segment::Literal("] \"".into()),
+                Segment::Var("request".into()),
+                Segment::Literal("\" ".into()),
+                Segment::Var("status".into()),
+                Segment::Literal("x".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_templates() {
+        assert_eq!(LogFormat::compile("  ").unwrap_err(), LogFormatError::Empty);
+        assert_eq!(
+            LogFormat::compile("$remote_addr$status").unwrap_err(),
+            LogFormatError::AdjacentVariables("remote_addr".into(), "status".into())
+        );
+        assert!(matches!(
+            LogFormat::compile("$remote_addr ${status"),
+            Err(LogFormatError::UnterminatedBrace(_))
+        ));
+        assert!(matches!(
+            LogFormat::compile("$remote_addr $status $request"),
+            Err(LogFormatError::MissingField(_))
+        ));
+    }
+
+    #[test]
+    fn parses_custom_format_with_extensions() {
+        let format = LogFormat::compile(CUSTOM).unwrap();
+        let line = r#"10.0.0.1 - - [02/Oct/2024:00:48:26 +0900] "GET /x HTTP/2.0" 200 99 "-" "Mozilla/5.0" rt=0.012 host=shop.example.com xff="198.51.100.4, 10.0.0.1" TLSv1.3"#;
+        let event = format.parse(line).unwrap();
+
+        assert_eq!(event.ip.to_string(), "10.0.0.1");
+        assert_eq!(event.path, "/x");
+        assert_eq!(event.status, 200);
+        assert_eq!(event.bytes_sent, 99);
+        assert_eq!(event.host.as_deref(), Some("shop.example.com"));
+        assert_eq!(event.extensions["request_time"], "0.012");
+        assert_eq!(event.extensions["http_x_forwarded_for"], "198.51.100.4, 10.0.0.1");
+        assert_eq!(event.extensions["ssl_protocol"], "TLSv1.3");
+        assert_eq!(event.extensions["remote_user"], "-");
+    }
+
+    #[test]
+    fn handles_reordered_fields() {
+        let format = LogFormat::compile(
+            r#"$time_iso8601|$status|$request_method|$request_uri|$remote_addr|$http_user_agent"#,
+        )
+        .unwrap();
+        let event = format
+            .parse("2024-10-02T00:48:26+09:00|503|POST|/api/login|192.0.2.1|curl/8.4.0")
+            .unwrap();
+
+        assert_eq!(event.ip.to_string(), "192.0.2.1");
+        assert_eq!(event.method.as_deref(), Some("POST"));
+        assert_eq!(event.path, "/api/login");
+        assert_eq!(event.status, 503);
+        assert_eq!(event.user_agent.as_deref(), Some("curl/8.4.0"));
+        assert!(event.protocol.is_none());
+    }
+
+    #[test]
+    fn rejects_invalid_remote_addr() {
+        let format = LogFormat::compile(CUSTOM).unwrap();
+        let line = r#"- - - [02/Oct/2024:00:48:26 +0900] "GET /x HTTP/2.0" 200 99 "-" "-" rt=0 host=- xff="-" -"#;
+
+        assert!(format.parse(line).is_none());
+    }
+
+    #[test]
+    fn rejects_lines_that_do_not_match() {
+        let format = LogFormat::compile(CUSTOM).unwrap();
+
+        assert!(format.parse("").is_none());
+        assert!(format.parse("10.0.0.1 something else entirely").is_none());
+        assert!(format
+            .parse(r#"10.0.0.1 - - [02/Oct/2024:00:48:26 +0900] "GET / HTTP/1.1" abc 1 "-" "-" rt=0 host=- xff="-" -"#)
+            .is_none());
+    }
+}
@@ -0,0 +1,288 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun",
+    "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Parse nginx `$time_local`, e.g. `10/Oct/2024:13:55:36 +0200`.
+pub fn parse_time_local(raw: &str) -> Option<SystemTime> {
+    let (datetime, offset) = raw.trim().split_once(' ')?;
+
+    let mut parts = datetime.splitn(4, ':');
+    let date = parts.next()?;
+    let hour: u32 = parts.next()?.parse().ok()?;
+    let minute: u32 = parts.next()?.parse().ok()?;
+    let second: u32 = parts.next()?.parse().ok()?;
+
+    let mut date_parts = date.splitn(3, '/');
+    let day: u32 = date_parts.next()?.parse().ok()?;
+    let month_name = date_parts.next()?;
+    let year: i64 = date_parts.next()?.parse().ok()?;
+    let month = MONTHS.iter().position(|m| *m == month_name)? as u32 + 1;
+
+    let offset = parse_offset(offset)?;
+
+    to_system_time(year, month, day, hour, minute, second, 0, offset)
+}
+
+/// Parse an RFC 3339 / ISO 8601 timestamp as written by nginx
+/// `$time_iso8601`, Caddy and Traefik, e.g. `2024-10-02T00:48:26+09:00`
+/// or `2024-10-01T15:48:26.123456Z`.
+pub fn parse_rfc3339(raw: &str) -> Option<SystemTime> {
+    let raw = raw.trim();
+    if raw.len() < 20 || !raw.is_char_boundary(10) || !raw.is_char_boundary(19) {
+        return None;
+    }
+
+    let (date, rest) = raw.split_at(10);
+    let mut date_parts = date.splitn(3, '-');
+    let year: i64 = date_parts.next()?.parse().ok()?;
+    let month: u32 = date_parts.next()?.parse().ok()?;
+    let day: u32 = date_parts.next()?.parse().ok()?;
+
+    if !matches!(rest.as_bytes()[0], b'T' | b't' | b' ') {
+        return None;
+    }
+
+    let (time, mut rest) = rest[1..].split_at(8);
+    let mut time_parts = time.splitn(3, ':');
+    let hour: u32 = time_parts.next()?.parse().ok()?;
+    let minute: u32 = time_parts.next()?.parse().ok()?;
+    let second: u32 = time_parts.next()?.parse().ok()?;
+
+    let mut nanos = 0;
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let digits = fraction
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(fraction.len());
+        if digits == 0 {
+            return None;
+        }
+        let padded = format!("{:0<9}", &fraction[..digits.min(9)]);
+        nanos = padded.parse().ok()?;
+        rest = &fraction[digits..];
+    }
+
+    let offset = parse_offset(rest)?;
+
+    to_system_time(year, month, day, hour, minute, second, nanos, offset)
+}
+
+/// Parse a Unix timestamp in seconds, with optional fraction
+/// (nginx `$msec`, Caddy `ts`).
+pub fn parse_unix(seconds: f64) -> Option<SystemTime> {
+    if !seconds.is_finite() || seconds < 0.0 {
+        return None;
+    }
+    UNIX_EPOCH.checked_add(Duration::try_from_secs_f64(seconds).ok()?)
+}
+
+/// Parse a `+HHMM` / `-HHMM` / `+HH:MM` / `Z` offset into seconds east of UTC.
+fn parse_offset(raw: &str) -> Option<i64> {
+    if raw == "Z" || raw == "z" {
+        return Some(0);
+    }
+
+    let sign = match raw.as_bytes().first()? {
+        b'+' => 1,
+        b'-' => -1,
+        _ => return None,
+    };
+
+    let digits: Vec<u8> = raw.bytes().skip(1).filter(|b| *b != b':').collect();
+    if digits.len() != 4 || !digits.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+
+    let value = |pair: &[u8]| (pair[0] - b'0') as i64 * 10 + (pair[1] - b'0') as i64;
+    let (hours, minutes) = (value(&digits[..2]), value(&digits[2..]));
+
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn to_system_time(
+    year: i64,
+    month: u32,
+    day: u32,
+    hour: u32,
+    minute: u32,
+    second: u32,
+    nanos: u32,
+    offset_seconds: i64,
+) -> Option<SystemTime> {
+    // Four-digit years only, which also keeps the arithmetic below far
+    // from overflowing on garbage input.
+    if !(0..=9999).contains(&year)
+        || !(1..=12).contains(&month)
+        || day == 0
+        || day > days_in_month(year, month)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return None;
+    }
+
+    let days = days_from_civil(year, month, day);
+    let secs = days * 86_400
+        + hour as i64 * 3600
+        + minute as i64 * 60
+        + second as i64
+        - offset_seconds;
+
+    if secs < 0 {
+        return None;
+    }
+
+    Some(UNIX_EPOCH + Duration::new(secs as u64, nanos))
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date
+/// (Howard Hinnant's `days_from_civil`).
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = y - era * 400;
+    let m = month as i64;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Inverse of [`days_from_civil`]: (year, month, day) of a day number.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = if z >= 0 { z } else { z - 146_096 } / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Format as RFC 3339 in UTC with second precision, e.g.
+/// `2024-10-01T15:48:26Z`. Times before the epoch are clamped to it.
+pub fn format_rfc3339(ts: SystemTime) -> String {
+    let secs = ts.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()) as i64;
+    let (year, month, day) = civil_from_days(secs.div_euclid(86_400));
+    let rem = secs.rem_euclid(86_400);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unix(ts: SystemTime) -> u64 {
+        ts.duration_since(UNIX_EPOCH).unwrap().as_secs()
+    }
+
+    #[test]
+    fn parses_time_local_with_offset() {
+        let ts = parse_time_local("02/Oct/2024:00:48:26 +0900").unwrap();
+        // 2024-10-01T15:48:26Z
+        assert_eq!(unix(ts), 1_727_797_706);
+    }
+
+    #[test]
+    fn parses_time_local_in_utc() {
+        let ts = parse_time_local("01/Jan/1970:00:00:00 +0000").unwrap();
+        assert_eq!(unix(ts), 0);
+    }
+
+    #[test]
+    fn parses_rfc3339_variants() {
+        let expected = 1_727_797_706;
+
+        assert_eq!(unix(parse_rfc3339("2024-10-02T00:48:26+09:00").unwrap()), expected);
+        assert_eq!(unix(parse_rfc3339("2024-10-01T15:48:26Z").unwrap()), expected);
+        assert_eq!(unix(parse_rfc3339("2024-10-01 15:48:26+0000").unwrap()), expected);
+
+        let precise = parse_rfc3339("2024-10-01T15:48:26.250Z").unwrap();
+        assert_eq!(
+            precise.duration_since(UNIX_EPOCH).unwrap().subsec_millis(),
+            250
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_rfc3339() {
+        assert!(parse_rfc3339("2024-10-01").is_none());
+        assert!(parse_rfc3339("2024-10-01T15:48:26").is_none());
+        assert!(parse_rfc3339("2024-13-01T15:48:26Z").is_none());
+        assert!(parse_rfc3339("2024-10-01T15:48:26.Z").is_none());
+        assert!(parse_rfc3339("2023-02-31T00:00:00Z").is_none());
+        assert!(parse_rfc3339("2023-02-29T00:00:00Z").is_none());
+        assert!(parse_rfc3339("2023-04-31T00:00:00Z").is_none());
+        assert!(parse_rfc3339("2000-02-29T00:00:00Z").is_some());
+    }
+
+    #[test]
+    fn rejects_malformed_time_local() {
+        assert!(parse_time_local("02/Foo/2024:00:48:26 +0900").is_none());
+        assert!(parse_time_local("02/Oct/2024:25:48:26 +0900").is_none());
+        assert!(parse_time_local("02/Oct/2024:00:48:26").is_none());
+        assert!(parse_time_local("-").is_none());
+        assert!(parse_time_local("31/Jun/2024:00:48:26 +0900").is_none());
+    }
+
+    #[test]
+    fn rejects_out_of_range_years_without_panicking() {
+        assert!(parse_time_local("10/Oct/9223372036854775807:13:55:36 +0000").is_none());
+        assert!(parse_time_local("10/Oct/-9223372036854775808:13:55:36 +0000").is_none());
+        assert!(parse_time_local("10/Oct/10000:13:55:36 +0000").is_none());
+        assert!(parse_time_local("31/Dec/9999:23:59:59 +0000").is_some());
+        assert!(parse_rfc3339("99999999999999999-10-10T13:55:36Z").is_none());
+    }
+
+    #[test]
+    fn rejects_non_digit_offsets_without_panicking() {
+        assert!(parse_time_local("10/Oct/2023:13:55:36 +1é1").is_none());
+        assert!(parse_time_local("10/Oct/2023:13:55:36 +12é").is_none());
+        assert!(parse_time_local("10/Oct/2023:13:55:36 +1x00").is_none());
+        assert!(parse_rfc3339("2023-10-10T13:55:36+0é:0").is_none());
+    }
+
+    #[test]
+    fn parses_unix_and_rejects_out_of_range() {
+        assert_eq!(unix(parse_unix(1_727_797_706.25).unwrap()), 1_727_797_706);
+        assert!(parse_unix(-1.0).is_none());
+        assert!(parse_unix(f64::NAN).is_none());
+        assert!(parse_unix(1e20).is_none());
+        assert!(parse_unix(f64::MAX).is_none());
+    }
+
+    #[test]
+    fn formats_rfc3339_in_utc() {
+        let ts = parse_time_local("02/Oct/2024:00:48:26 +0900").unwrap();
+        assert_eq!(format_rfc3339(ts), "2024-10-01T15:48:26Z");
+        assert_eq!(format_rfc3339(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+
+        let leap = parse_rfc3339("2024-02-29T23:59:59Z").unwrap();
+        assert_eq!(format_rfc3339(leap), "2024-02-29T23:59:59Z");
+    }
+}
@@ -0,0 +1,123 @@
+use std::collections::BTreeSet;
+use std::io::{self, Write};
+use std::net::IpAddr;
+
+use crate::engine::decision::Decision;
+use crate::engine::scoring::ScoreReason;
+use crate::replay::report::{IpReport, ReplayReport};
+
+/// How one client fared under the current and the proposed config.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IpDiff {
+    pub ip: IpAddr,
+    pub score_before: u32,
+    pub score_after: u32,
+    pub decision_before: Decision,
+    pub decision_after: Decision,
+    /// Reasons only the current config gave.
+    pub reasons_removed: Vec<ScoreReason>,
+    /// Reasons only the proposed config gives.
+    pub reasons_added: Vec<ScoreReason>,
+}
+
+/// Clients whose outcome differs between two replays of the same logs.
+///
+/// "Blocked" means the client would have been acted on at some point
+/// (see [`IpReport::triggered`]), whether by `Detect` or `Block`.
+#[derive(Debug, Default)]
+pub struct ConfigDiff {
+    pub newly_blocked: Vec<IpDiff>,
+    pub no_longer_blocked: Vec<IpDiff>,
+    /// Same blocked/not-blocked verdict, different final score.
+    pub score_changed: Vec<IpDiff>,
+}
+
+impl ConfigDiff {
+    pub fn is_empty(&self) -> bool {
+        self.newly_blocked.is_empty()
+            && self.no_longer_blocked.is_empty()
+            && self.score_changed.is_empty()
+    }
+
+    pub fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        let sections = [
+            ("Newly blocked", &self.newly_blocked),
+            ("No longer blocked", &self.no_longer_blocked),
+            ("Score changed", &self.score_changed),
+        ];
+
+        for (title, entries) in sections {
+            writeln!(out, "{} ({}):", title, entries.len())?;
+            for d in entries {
+                writeln!(
+                    out,
+                    "  {:<39} score {} -> {}  {} -> {}",
+                    d.ip.to_string(),
+                    d.score_before,
+                    d.score_after,
+                    d.decision_before,
+                    d.decision_after
+                )?;
+                for reason in &d.reasons_removed {
+                    writeln!(out, "      - {:?}", reason)?;
+                }
+                for reason in &d.reasons_added {
+                    writeln!(out, "      + {:?}", reason)?;
+                }
+            }
+            writeln!(out)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Diff `before` (current config) against `after` (proposed config).
+///
+/// A client missing from one side (e.g. excluded by a `[filter]`
+/// change) counts as score 0, `Allow`, no reasons.
+pub fn compare(before: &ReplayReport, after: &ReplayReport) -> ConfigDiff {
+    let ips: BTreeSet<IpAddr> = before.ips.keys().chain(after.ips.keys()).copied().collect();
+    let mut diff = ConfigDiff::default();
+
+    for ip in ips {
+        let old = before.ips.get(&ip);
+        let new = after.ips.get(&ip);
+        let was_blocked = old.is_some_and(IpReport::triggered);
+        let is_blocked = new.is_some_and(IpReport::triggered);
+
+        let reasons = |report: Option<&IpReport>| {
+            report.map(|r| r.score.reasons.clone()).unwrap_or_default()
+        };
+        let (old_reasons, new_reasons) = (reasons(old), reasons(new));
+
+        let entry = IpDiff {
+            ip,
+            score_before: old.map_or(0, |r| r.score.score),
+            score_after: new.map_or(0, |r| r.score.score),
+            decision_before: old.map_or(Decision::Allow, |r| r.decision.clone()),
+            decision_after: new.map_or(Decision::Allow, |r| r.decision.clone()),
+            reasons_removed: old_reasons
+                .iter()
+                .filter(|r| !new_reasons.contains(r))
+                .cloned()
+                .collect(),
+            reasons_added: new_reasons
+                .iter()
+                .filter(|r| !old_reasons.contains(r))
+                .cloned()
+                .collect(),
+        };
+
+        match (was_blocked, is_blocked) {
+            (false, true) => diff.newly_blocked.push(entry),
+            (true, false) => diff.no_longer_blocked.push(entry),
+            _ if entry.score_before != entry.score_after => diff.score_changed.push(entry),
+            _ => {}
+        }
+    }
+
+    diff.score_changed
+        .sort_by_key(|d| std::cmp::Reverse(d.score_before.abs_diff(d.score_after)));
+    diff
+}
@@ -0,0 +1,309 @@
+pub mod compare;
+pub mod report;
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::config::loader::load_config;
+use crate::config::schema::{AargalConfig, SourceConfig};
+use crate::engine::filter::RequestFilter;
+use crate::engine::pipeline::evaluate_event;
+use crate::engine::signals::Signals;
+use crate::ingest::archive::ArchiveIngestor;
+use crate::ingest::{Ingestor, Poll};
+use crate::model::clock::Clock;
+use crate::model::state_store::StateStore;
+
+use compare::compare;
+use report::ReplayReport;
+
+pub fn run_replay(config_path: &Path, logs: &[PathBuf], all: bool) -> anyhow::Result<()> {
+    let config: AargalConfig = load_config(config_path)?;
+
+    let report = replay(&config, logs)?;
+    report.print(all)?;
+    Ok(())
+}
+
+/// Replay `logs` through the current and a proposed config and print
+/// the clients whose outcome changes.
+pub fn run_compare(current: &Path, proposed: &Path, logs: &[PathBuf]) -> anyhow::Result<()> {
+    let before: AargalConfig = load_config(current)?;
+    let after: AargalConfig = load_config(proposed)?;
+
+    let diff = compare(&replay(&before, logs)?, &replay(&after, logs)?);
+
+    let mut out = std::io::stdout().lock();
+    writeln!(out, "{} -> {}\n", current.display(), proposed.display())?;
+    if diff.is_empty() {
+        writeln!(out, "No differences")?;
+    } else {
+        diff.write(&mut out)?;
+    }
+    Ok(())
+}
+
+/// Drive `logs` through the daemon's filter and pipeline without any
+/// side effects: actions are evaluated but never executed.
+///
+/// Files are merged oldest event first, like `source = "archive"`, and
+/// the report uses log timestamps, not the time of the replay. State
+/// ages by the same event clock, so a replay's outcome does not depend
+/// on when or how fast it runs.
+pub fn replay(config: &AargalConfig, logs: &[PathBuf]) -> anyhow::Result<ReplayReport> {
+    let sources: Vec<SourceConfig> = logs
+        .iter()
+        .map(|path| SourceConfig {
+            // Literal paths, not globs: the shell has already expanded them.
+            path: glob::Pattern::escape(&path.to_string_lossy()),
+            format: None,
+            log_format: None,
+            label: None,
+        })
+        .collect();
+
+    let mut ingestor = ArchiveIngestor::open(&sources, &config.parser)?;
+    let mut filter = RequestFilter::new(&config.parser, &config.filter)?;
+    let signals = Signals::new(config)?;
+    let mut state = StateStore::with_clock(
+        config.general.state_ttl_seconds,
+        config.scoring.rates.windows_seconds.clone(),
+        Clock::event(),
+    );
+    state.remember_offences_for(config.scoring.offence_memory_seconds);
+    let mut report = ReplayReport::default();
+
+    loop {
+        let poll = ingestor.next_event();
+        report.ingest.record(&poll);
+
+        match poll {
+            Poll::Event(event) => {
+                if !filter.accept(&event) {
+                    continue;
+                }
+                let outcome = evaluate_event(&event, &mut state, config, &signals);
+                report.record(event.timestamp, outcome);
+                state.evict_if_due();
+            }
+            Poll::Idle | Poll::Unparsed(_) => {}
+            Poll::Error(e) => log::warn!("Replay read error: {}", e),
+            Poll::Eof => break,
+        }
+    }
+
+    report.filtered = filter.stats().total();
+    Ok(report)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::decision::{Decision, Tier};
+    use crate::engine::scoring::ScoreReason;
+    use crate::parser::time::format_rfc3339;
+    use std::fs;
+    use std::time::Duration;
+
+    const CONFIG: &str = r#"
+        [general]
+        mode = "enforce"
+        state_ttl_seconds = 3600
+
+        [ingest]
+        source = "archive"
+        poll_interval_ms = 100
+
+        [parser]
+        format = "nginx_combined"
+        ignore_status = []
+
+        [scoring]
+        threshold = 60
+
+        [scoring.weights]
+        rate = 40
+        error = 30
+        user_agent = 20
+        path_entropy = 10
+
+        [scoring.rates]
+        windows_seconds = [10]
+        max_requests_per_second = 1.0
+        max_errors_per_minute = 60.0
+
+        [actions]
+        on_block = "fail2ban"
+
+        [fail2ban]
+        enabled = true
+        socket = "/nonexistent/fail2ban.sock"
+        jail = "aargal"
+
+        [logging]
+        level = "info"
+        json = false
+    "#;
+
+    fn line(ip: &str, second: u32, status: u16) -> String {
+        format!(
+            "{} - - [02/Oct/2024:10:{:02}:{:02} +0000] \"GET /x HTTP/1.1\" {} 0 \"-\" \"Mozilla/5.0 (X11; Linux x86_64)\"\n",
+            ip,
+            second / 60,
+            second % 60,
+            status
+        )
+    }
+
+    #[test]
+    fn reports_first_trigger_in_event_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("access.log");
+        let mut text = String::new();
+        for second in 0..40 {
+            text += &line("203.0.113.9", second, 404);
+        }
+        text += &line("198.51.100.1", 5, 200);
+        fs::write(&log, text).unwrap();
+
+        let config: AargalConfig = toml::from_str(CONFIG).unwrap();
+        let report = replay(&config, &[log]).unwrap();
+
+        assert_eq!(report.ingest.events, 41);
+        let triggered = report.triggered();
+        assert_eq!(triggered.len(), 1);
+
+        // n errors in the window score 4n + 3n: 63 on the 9th request.
+        let noisy = triggered[0];
+        assert_eq!(noisy.ip.to_string(), "203.0.113.9");
+        assert!(matches!(noisy.decision, Decision::Block(_)));
+        assert_eq!(noisy.requests, 40);
+        assert_eq!(
+            noisy.first_triggered.map(format_rfc3339).as_deref(),
+            Some("2024-10-02T10:00:08Z")
+        );
+
+        let quiet = &report.ips[&"198.51.100.1".parse().unwrap()];
+        assert_eq!(quiet.decision, Decision::Allow);
+
+        let mut out = Vec::new();
+        report.write(&mut out, true).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("2 IPs seen, 1 would be acted on"));
+        assert!(out.lines().last().unwrap().starts_with("198.51.100.1"));
+    }
+
+    #[test]
+    fn compares_two_configs_over_the_same_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("access.log");
+        let mut text = String::new();
+        for second in 0..40 {
+            text += &line("203.0.113.9", second, 404);
+        }
+        // One request a second, every other one an error: at most 10
+        // requests and 5 errors per window, score 40 + 15.
+        for second in 0..30 {
+            text += &line("198.51.100.1", second, if second % 2 == 0 { 404 } else { 200 });
+        }
+        fs::write(&log, text).unwrap();
+        let logs = [log];
+
+        let current: AargalConfig = toml::from_str(CONFIG).unwrap();
+        let lower: AargalConfig =
+            toml::from_str(&CONFIG.replace("threshold = 60", "threshold = 50")).unwrap();
+        let no_404: AargalConfig =
+            toml::from_str(&CONFIG.replace("ignore_status = []", "ignore_status = [404]")).unwrap();
+
+        let baseline = replay(&current, &logs).unwrap();
+
+        let diff = compare(&baseline, &replay(&lower, &logs).unwrap());
+        assert_eq!(diff.newly_blocked.len(), 1);
+        assert_eq!(diff.newly_blocked[0].ip.to_string(), "198.51.100.1");
+        assert!(diff.no_longer_blocked.is_empty());
+        assert!(diff.score_changed.is_empty());
+
+        let diff = compare(&baseline, &replay(&no_404, &logs).unwrap());
+        assert!(diff.newly_blocked.is_empty());
+        assert_eq!(diff.no_longer_blocked.len(), 1);
+        assert_eq!(diff.no_longer_blocked[0].score_after, 0);
+
+        let changed = &diff.score_changed[0];
+        assert_eq!(changed.ip.to_string(), "198.51.100.1");
+        assert_eq!((changed.score_before, changed.score_after), (55, 20));
+        assert_eq!(
+            changed.reasons_removed,
+            vec![
+                ScoreReason::HighRate { requests: 10, window_secs: 10 },
+                ScoreReason::HighErrorRate { errors: 5, window_secs: 10 },
+            ]
+        );
+        assert_eq!(
+            changed.reasons_added,
+            vec![ScoreReason::HighRate { requests: 5, window_secs: 10 }]
+        );
+
+        let mut out = Vec::new();
+        diff.write(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("No longer blocked (1):"));
+        assert!(out.contains("      + HighRate { requests: 5, window_secs: 10 }"));
+    }
+
+    #[test]
+    fn banned_clients_are_remembered_past_the_state_ttl() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("access.log");
+        let mut text = String::new();
+        for second in 0..40 {
+            text += &line("203.0.113.9", second, 404);
+        }
+        // Moves the event clock past the TTL, so the state sweep runs.
+        text += &line("198.51.100.1", 1800, 200).replace(":10:", ":11:");
+        for second in 0..40 {
+            text += &line("203.0.113.9", second, 404).replace(":10:", ":12:");
+        }
+        fs::write(&log, text).unwrap();
+
+        let config = format!(
+            "{}\n[[scoring.tiers]]\nname = \"ban\"\nenter = 60\naction = \"block\"\nban_seconds = [600, 3600]\n",
+            CONFIG
+        );
+        let config: AargalConfig = toml::from_str(&config).unwrap();
+        let report = replay(&config, &[log]).unwrap();
+
+        let noisy = &report.ips[&"203.0.113.9".parse().unwrap()];
+        assert_eq!(
+            noisy.decision,
+            Decision::Block(Tier { name: "ban".into(), duration: Some(Duration::from_secs(3600)) })
+        );
+    }
+
+    #[test]
+    fn honeypot_hit_acts_at_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("access.log");
+        let mut text = line("192.0.2.44", 0, 200);
+        text += &line("192.0.2.44", 1, 404).replace("/x", "/wp-login.php?action=register");
+        text += &line("192.0.2.44", 2, 200);
+        fs::write(&log, text).unwrap();
+
+        let config = format!(
+            "{}\n[scoring.honeypot]\npaths = [\"/wp-login.php\"]\nscore = 5\nblock = true\n",
+            CONFIG
+        );
+        let config: AargalConfig = toml::from_str(&config).unwrap();
+        let report = replay(&config, &[log]).unwrap();
+
+        let trapped = &report.ips[&"192.0.2.44".parse().unwrap()];
+        assert!(matches!(trapped.decision, Decision::Block(_)));
+        assert_eq!(
+            trapped.first_triggered.map(format_rfc3339).as_deref(),
+            Some("2024-10-02T10:00:01Z")
+        );
+        assert!(trapped.score.reasons.contains(&ScoreReason::Honeypot {
+            path: "/wp-login.php?action=register".into()
+        }));
+    }
+}
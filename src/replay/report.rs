@@ -0,0 +1,122 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::time::SystemTime;
+
+use crate::engine::decision::Decision;
+use crate::engine::pipeline::Outcome;
+use crate::engine::scoring::ScoreResult;
+use crate::ingest::IngestStats;
+use crate::parser::time::format_rfc3339;
+
+/// Final state of one client after a replay.
+#[derive(Debug, Clone)]
+pub struct IpReport {
+    pub ip: IpAddr,
+    pub requests: u64,
+    /// Event time of the first and last request.
+    pub first_seen: SystemTime,
+    pub last_seen: SystemTime,
+    pub score: ScoreResult,
+    pub decision: Decision,
+    /// Event time at which the decision first left `Allow`.
+    pub first_triggered: Option<SystemTime>,
+}
+
+impl IpReport {
+    pub fn triggered(&self) -> bool {
+        self.first_triggered.is_some()
+    }
+}
+
+/// Everything a replay concluded, keyed by client.
+#[derive(Debug, Default)]
+pub struct ReplayReport {
+    pub ips: BTreeMap<IpAddr, IpReport>,
+    pub ingest: IngestStats,
+    pub filtered: u64,
+}
+
+impl ReplayReport {
+    /// Fold in the pipeline outcome of an event logged at `time`.
+    pub fn record(&mut self, time: SystemTime, outcome: Outcome) {
+        let entry = self.ips.entry(outcome.ip).or_insert_with(|| IpReport {
+            ip: outcome.ip,
+            requests: 0,
+            first_seen: time,
+            last_seen: time,
+            score: outcome.score.clone(),
+            decision: outcome.decision.clone(),
+            first_triggered: None,
+        });
+
+        entry.requests += 1;
+        entry.last_seen = entry.last_seen.max(time);
+        if outcome.decision != Decision::Allow && entry.first_triggered.is_none() {
+            entry.first_triggered = Some(time);
+        }
+        entry.score = outcome.score;
+        entry.decision = outcome.decision;
+    }
+
+    /// Clients that would have been acted on, earliest trigger first.
+    pub fn triggered(&self) -> Vec<&IpReport> {
+        let mut triggered: Vec<_> = self.ips.values().filter(|ip| ip.triggered()).collect();
+        triggered.sort_by_key(|ip| (ip.first_triggered, ip.ip));
+        triggered
+    }
+
+    /// Write the report as a table: triggered clients first, then (with
+    /// `all`) every other client by descending score.
+    pub fn write(&self, out: &mut impl Write, all: bool) -> io::Result<()> {
+        let triggered = self.triggered();
+        writeln!(out, "Replayed {}; {} filtered", self.ingest, self.filtered)?;
+        writeln!(
+            out,
+            "{} IPs seen, {} would be acted on\n",
+            self.ips.len(),
+            triggered.len()
+        )?;
+
+        let mut rows = triggered;
+        if all {
+            let mut rest: Vec<_> = self.ips.values().filter(|ip| !ip.triggered()).collect();
+            rest.sort_by(|a, b| b.score.score.cmp(&a.score.score).then(a.ip.cmp(&b.ip)));
+            rows.extend(rest);
+        }
+
+        writeln!(
+            out,
+            "{:<39} {:>5} {:<8} {:<16} {:<20} {:>8}  REASONS",
+            "IP", "SCORE", "DECISION", "TIER", "FIRST TRIGGER", "REQUESTS"
+        )?;
+        for ip in rows {
+            let trigger = ip.first_triggered.map(format_rfc3339).unwrap_or_else(|| "-".into());
+            let reasons = ip
+                .score
+                .reasons
+                .iter()
+                .map(|reason| format!("{:?}", reason))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let tier = ip.decision.tier().map_or_else(|| "-".into(), |tier| tier.to_string());
+            writeln!(
+                out,
+                "{:<39} {:>5} {:<8} {:<16} {:<20} {:>8}  {}",
+                ip.ip.to_string(),
+                ip.score.score,
+                ip.decision,
+                tier,
+                trigger,
+                ip.requests,
+                reasons
+            )?;
+        }
+
+        Ok(())
+    }
+
+    pub fn print(&self, all: bool) -> io::Result<()> {
+        self.write(&mut io::stdout().lock(), all)
+    }
+}